@@ -0,0 +1,119 @@
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+// Everything `--emit` can ask for, in the order the compiler produces them
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum EmitKind {
+    Tokens,
+    Ast,
+    LlvmIr,
+    LlvmBc,
+    Asm,
+    Obj,
+    Exe,
+}
+
+impl EmitKind {
+    const NAMES: [(&'static str, EmitKind); 7] = [
+        ("tokens", EmitKind::Tokens),
+        ("ast", EmitKind::Ast),
+        ("llvm-ir", EmitKind::LlvmIr),
+        ("llvm-bc", EmitKind::LlvmBc),
+        ("asm", EmitKind::Asm),
+        ("obj", EmitKind::Obj),
+        ("exe", EmitKind::Exe),
+    ];
+
+    // What goes after the package name when no path is given. The executable has none
+    fn extension(self) -> Option<&'static str> {
+        match self {
+            EmitKind::Tokens => Some("tokens"),
+            EmitKind::Ast => Some("ast"),
+            EmitKind::LlvmIr => Some("ll"),
+            EmitKind::LlvmBc => Some("bc"),
+            EmitKind::Asm => Some("s"),
+            EmitKind::Obj => Some("o"),
+            EmitKind::Exe => None,
+        }
+    }
+}
+
+// One `--emit` entry, `kind` or `kind=path`. A path of `-` means stdout
+#[derive(Debug, Clone)]
+pub struct Emit {
+    pub kind: EmitKind,
+    pub path: Option<PathBuf>,
+}
+
+impl Emit {
+    // Where the artifact goes, `stem` being the target directory joined with the package name
+    pub fn destination(&self, stem: &Path) -> PathBuf {
+        if let Some(path) = &self.path {
+            return path.clone();
+        }
+        match self.kind.extension() {
+            Some(extension) => {
+                let mut file = stem.as_os_str().to_owned();
+                file.push(".");
+                file.push(extension);
+                PathBuf::from(file)
+            }
+            None => stem.to_path_buf(),
+        }
+    }
+}
+
+impl FromStr for Emit {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let (name, path) = match value.split_once('=') {
+            Some((name, path)) => (name, Some(PathBuf::from(path))),
+            None => (value, None),
+        };
+        let Some((_, kind)) = EmitKind::NAMES.iter().find(|(known, _)| *known == name) else {
+            let names: Vec<&str> = EmitKind::NAMES.iter().map(|(known, _)| *known).collect();
+            return Err(format!("unknown kind '{}', expected one of {}", name, names.join(", ")));
+        };
+        Ok(Emit { kind: *kind, path })
+    }
+}
+
+pub fn is_stdout(path: &Path) -> bool {
+    path == Path::new("-")
+}
+
+pub fn write_artifact(path: &Path, contents: &[u8]) -> io::Result<()> {
+    if is_stdout(path) {
+        let mut stdout = io::stdout().lock();
+        stdout.write_all(contents)?;
+        return stdout.flush();
+    }
+    fs::write(path, contents)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn kinds_parse_with_optional_paths() {
+        let emit: Emit = "llvm-ir".parse().unwrap();
+        assert_eq!((emit.kind, emit.path), (EmitKind::LlvmIr, None));
+        let emit: Emit = "asm=-".parse().unwrap();
+        assert_eq!(emit.kind, EmitKind::Asm);
+        assert!(is_stdout(emit.path.as_deref().unwrap()));
+        assert!("wasm".parse::<Emit>().unwrap_err().contains("expected one of tokens, ast"));
+    }
+
+    #[test]
+    fn destinations_default_next_to_the_stem() {
+        let stem = Path::new("target/debug/hello");
+        let destination = |value: &str| value.parse::<Emit>().unwrap().destination(stem);
+        assert_eq!(destination("llvm-bc"), PathBuf::from("target/debug/hello.bc"));
+        assert_eq!(destination("exe"), PathBuf::from("target/debug/hello"));
+        assert_eq!(destination("obj=out.o"), PathBuf::from("out.o"));
+    }
+}
@@ -0,0 +1,28 @@
+use super::native::target_machine;
+use super::optimize::{optimize, Options};
+use super::runtime::define_runtime;
+use super::{add_c_main, BackendError};
+use inkwell::module::Module;
+
+// Compiles the module straight into memory and calls its `main` like the C runtime would,
+// `program` ending up as argv[0]. Nothing is written to disk, the exit code comes back
+pub fn run_in_memory(module: &Module, program: &str, args: &[String], options: &Options) -> Result<i32, BackendError> {
+    if let Some(target) = &options.target {
+        return Err(BackendError::Jit(format!("can only run code for this machine, not for {}", target)));
+    }
+    let machine = target_machine(options)?;
+    module.set_triple(&machine.get_triple());
+    module.set_data_layout(&machine.get_target_data().get_data_layout());
+    define_runtime(module, false)?;
+    let main = add_c_main(module)?;
+    optimize(module, &machine, options)?;
+
+    // Lumina's own calls into libc get resolved against this process
+    let engine = module
+        .create_jit_execution_engine(options.opt_level.codegen_level())
+        .map_err(|err| BackendError::Jit(err.to_string()))?;
+
+    let argv: Vec<&str> = std::iter::once(program).chain(args.iter().map(String::as_str)).collect();
+    let status = unsafe { engine.run_function_as_main(main, &argv) };
+    Ok(status)
+}
@@ -0,0 +1,76 @@
+mod emit;
+mod jit;
+mod native;
+mod object;
+mod optimize;
+mod runtime;
+mod wasm;
+
+use crate::parser::ENTRY_POINT;
+use inkwell::module::Module;
+use inkwell::values::FunctionValue;
+use inkwell::AddressSpace;
+use std::fmt;
+use std::path::PathBuf;
+
+pub use emit::{write_artifact, Emit, EmitKind};
+pub use jit::run_in_memory;
+pub use native::{data_layout, emit};
+pub use optimize::{OptLevel, Options};
+pub use wasm::is_wasm;
+
+#[derive(Debug)]
+pub enum BackendError {
+    Target(String),
+    Emit(String),
+    EntryPoint(String),
+    Linker(String),
+    Jit(String),
+    Runtime(String),
+    Optimize(String, String), // The pipeline, what LLVM made of it
+    Write(PathBuf, std::io::Error),
+}
+
+impl fmt::Display for BackendError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            BackendError::Target(message) => write!(f, "target: {}", message),
+            BackendError::Emit(message) => write!(f, "emitting object file: {}", message),
+            BackendError::EntryPoint(message) => write!(f, "entry point: {}", message),
+            BackendError::Linker(message) => write!(f, "linking: {}", message),
+            BackendError::Jit(message) => write!(f, "JIT: {}", message),
+            BackendError::Runtime(message) => write!(f, "runtime: {}", message),
+            BackendError::Optimize(passes, message) => write!(f, "running passes '{}': {}", passes, message),
+            BackendError::Write(path, err) => write!(f, "writing {}: {}", path.display(), err),
+        }
+    }
+}
+
+// `int main(int argc, char **argv)` for the C runtime to call, running the top level code
+// and exiting with whatever it returns
+fn add_c_main<'ctx>(module: &Module<'ctx>) -> Result<FunctionValue<'ctx>, BackendError> {
+    let context = module.get_context();
+    let entry_point = module
+        .get_function(ENTRY_POINT)
+        .ok_or_else(|| BackendError::EntryPoint("the module has no top level code".into()))?;
+    if module.get_function("main").is_some() {
+        return Err(BackendError::EntryPoint(
+            "'main' is already a function, it can't also be the program's entry point".into(),
+        ));
+    }
+
+    let i32_type = context.i32_type();
+    let ptr_type = context.ptr_type(AddressSpace::default());
+    let main = module.add_function("main", i32_type.fn_type(&[i32_type.into(), ptr_type.into()], false), None);
+
+    let builder = context.create_builder();
+    builder.position_at_end(context.append_basic_block(main, "entry"));
+    let build = || -> Result<(), inkwell::builder::BuilderError> {
+        let status = builder.build_call(entry_point, &[], "status")?;
+        let status = status.try_as_basic_value().left().unwrap_or_else(|| i32_type.const_zero().into());
+        builder.build_return(Some(&status))?;
+        Ok(())
+    };
+    build().map_err(|err| BackendError::EntryPoint(err.to_string()))?;
+    Ok(main)
+}
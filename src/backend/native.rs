@@ -0,0 +1,156 @@
+use super::emit::{is_stdout, write_artifact, EmitKind};
+use super::object::check_object;
+use super::optimize::{optimize, Options};
+use super::runtime::define_runtime;
+use super::wasm;
+use super::{add_c_main, BackendError};
+use inkwell::data_layout::DataLayout;
+use inkwell::module::Module;
+use inkwell::targets::{CodeModel, FileType, InitializationConfig, RelocMode, Target, TargetMachine, TargetTriple};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+// Optimizes the module for the target machine and writes each of the requested artifacts.
+// The front end ones (tokens and the AST) are already written by then. `functions` are the
+// program's own, which a wasm module exports
+pub fn emit(
+    module: &Module,
+    artifacts: &[(EmitKind, PathBuf)],
+    functions: &[String],
+    options: &Options,
+) -> Result<(), BackendError> {
+    let machine = target_machine(options)?;
+    module.set_triple(&machine.get_triple());
+    module.set_data_layout(&machine.get_target_data().get_data_layout());
+    let triple = machine.get_triple().as_str().to_string_lossy().into_owned();
+    let wasm = wasm::is_wasm(&triple);
+    define_runtime(module, wasm)?;
+    let exports = if wasm {
+        wasm::add_exports(module, functions)?
+    } else {
+        add_c_main(module)?;
+        Vec::new()
+    };
+    optimize(module, &machine, options)?;
+
+    // In pipeline order, so an object that was asked for exists by the time we link
+    let mut artifacts = artifacts.to_vec();
+    artifacts.sort_by_key(|(kind, _)| *kind);
+    for (kind, path) in artifacts.iter() {
+        match kind {
+            EmitKind::Tokens | EmitKind::Ast => {}
+            EmitKind::LlvmIr => write(path, module.print_to_string().to_bytes())?,
+            EmitKind::LlvmBc => write(path, module.write_bitcode_to_memory().as_slice())?,
+            EmitKind::Asm => write_machine_code(&machine, module, FileType::Assembly, path)?,
+            EmitKind::Obj => write_machine_code(&machine, module, FileType::Object, path)?,
+            EmitKind::Exe => {
+                // The linker needs the object on disk. Reuse it if it was asked for as well,
+                // otherwise it's only there until linking is done
+                let requested = artifacts
+                    .iter()
+                    .find(|(kind, path)| *kind == EmitKind::Obj && !is_stdout(path))
+                    .map(|(_, path)| path.clone());
+                let object = match &requested {
+                    Some(object) => object.clone(),
+                    None => {
+                        let mut object = path.as_os_str().to_owned();
+                        object.push(".o");
+                        let object = PathBuf::from(object);
+                        write_machine_code(&machine, module, FileType::Object, &object)?;
+                        object
+                    }
+                };
+                let linked = if wasm { wasm::link(&object, path, &triple) } else { link(&object, path) };
+                if requested.is_none() {
+                    let _ = fs::remove_file(&object);
+                }
+                linked?;
+                if wasm {
+                    let linked = fs::read(path).map_err(|err| BackendError::Write(path.clone(), err))?;
+                    wasm::check_module(&linked, &exports)?;
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+fn write(path: &Path, contents: &[u8]) -> Result<(), BackendError> {
+    write_artifact(path, contents).map_err(|err| BackendError::Write(path.to_path_buf(), err))
+}
+
+fn write_machine_code(machine: &TargetMachine, module: &Module, file_type: FileType, path: &Path) -> Result<(), BackendError> {
+    let buffer = machine
+        .write_to_memory_buffer(module, file_type)
+        .map_err(|err| BackendError::Emit(err.to_string()))?;
+    if file_type == FileType::Object {
+        check_object(buffer.as_slice(), &machine.get_triple().as_str().to_string_lossy())?;
+    }
+    write(path, buffer.as_slice())
+}
+
+// How the target lays out types, which debug info needs before the module gets to emit
+pub fn data_layout(options: &Options) -> Result<DataLayout, BackendError> {
+    Ok(target_machine(options)?.get_target_data().get_data_layout())
+}
+
+// The machine `--target` asks for, with only its backend initialized, or the host
+pub(super) fn target_machine(options: &Options) -> Result<TargetMachine, BackendError> {
+    let (triple, cpu, features) = match &options.target {
+        Some(triple) => {
+            Target::initialize_all(&InitializationConfig::default());
+            let triple = TargetMachine::normalize_triple(&TargetTriple::create(triple));
+            (triple, "generic".to_string(), String::new())
+        }
+        None => {
+            Target::initialize_native(&InitializationConfig::default()).map_err(BackendError::Target)?;
+            let cpu = TargetMachine::get_host_cpu_name().to_string();
+            let features = TargetMachine::get_host_cpu_features().to_string();
+            (TargetMachine::get_default_triple(), cpu, features)
+        }
+    };
+    let cpu = options.cpu.clone().unwrap_or(cpu);
+    let features = options.features.clone().unwrap_or(features);
+
+    let target = Target::from_triple(&triple).map_err(|err| BackendError::Target(err.to_string()))?;
+    // PIC so the system linker is happy making a PIE out of it. wasm-ld only wants that
+    // for shared libraries
+    let reloc_mode = if wasm::is_wasm(&triple.as_str().to_string_lossy()) {
+        RelocMode::Static
+    } else {
+        RelocMode::PIC
+    };
+    target
+        .create_target_machine(
+            &triple,
+            &cpu,
+            &features,
+            options.opt_level.codegen_level(),
+            reloc_mode,
+            CodeModel::Default,
+        )
+        .ok_or_else(|| BackendError::Target(format!("LLVM can't generate code for {} with cpu '{}'", triple, cpu)))
+}
+
+// The C compiler knows where libc and the startup files are, so it does the linking.
+// $CC picks a different one, cross builds need it pointed at one for the target
+fn link(object: &Path, output: &Path) -> Result<(), BackendError> {
+    let linker = std::env::var("CC").unwrap_or_else(|_| "cc".to_string());
+    let result = Command::new(&linker)
+        .arg(object)
+        .arg("-o")
+        .arg(output)
+        .arg("-lm") // fmod, for % on floats
+        .output()
+        .map_err(|err| BackendError::Linker(format!("couldn't run {}: {}", linker, err)))?;
+
+    if !result.status.success() {
+        return Err(BackendError::Linker(format!(
+            "{} failed: {}",
+            linker,
+            String::from_utf8_lossy(&result.stderr).trim()
+        )));
+    }
+    Ok(())
+}
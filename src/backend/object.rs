@@ -0,0 +1,121 @@
+use super::BackendError;
+
+// What an object file for a triple's architecture looks like, enough to tell from the header
+// that LLVM made one for the machine we asked for
+struct Format {
+    arch: &'static str,  // Start of the triple
+    elf_machine: u16,    // e_machine
+    macho_cpu: u32,      // cputype, 0 when there's no Mach-O for it
+    bits: u8,
+}
+
+const FORMATS: [Format; 8] = [
+    Format { arch: "x86_64", elf_machine: 62, macho_cpu: 0x0100_0007, bits: 64 },
+    Format { arch: "aarch64", elf_machine: 183, macho_cpu: 0x0100_000c, bits: 64 },
+    Format { arch: "arm64", elf_machine: 183, macho_cpu: 0x0100_000c, bits: 64 },
+    Format { arch: "riscv64", elf_machine: 243, macho_cpu: 0, bits: 64 },
+    Format { arch: "riscv32", elf_machine: 243, macho_cpu: 0, bits: 32 },
+    Format { arch: "i686", elf_machine: 3, macho_cpu: 7, bits: 32 },
+    Format { arch: "i386", elf_machine: 3, macho_cpu: 7, bits: 32 },
+    Format { arch: "arm", elf_machine: 40, macho_cpu: 12, bits: 32 },
+];
+
+const ELF_MAGIC: &[u8] = b"\x7fELF";
+const MACHO_MAGIC_64: u32 = 0xfeed_facf;
+const MACHO_MAGIC_32: u32 = 0xfeed_face;
+const WASM_MAGIC: &[u8] = b"\0asm";
+
+// Reads the header of an object file written for `triple` and makes sure it's for that
+// architecture. Formats and architectures we don't know about are let through
+pub fn check_object(object: &[u8], triple: &str) -> Result<(), BackendError> {
+    let mismatch = |found: String| {
+        Err(BackendError::Emit(format!("object file for {} came out as {}", triple, found)))
+    };
+
+    if triple.starts_with("wasm") {
+        if !object.starts_with(WASM_MAGIC) {
+            return mismatch("something other than a wasm module".into());
+        }
+        return Ok(());
+    }
+    let Some(format) = FORMATS.iter().find(|format| triple.starts_with(format.arch)) else {
+        return Ok(());
+    };
+
+    if object.starts_with(ELF_MAGIC) && object.len() >= 20 {
+        // EI_CLASS is 1 for 32 bit and 2 for 64 bit, EI_DATA 1 for little endian
+        let bits = if object[4] == 2 { 64 } else { 32 };
+        let machine = match object[5] {
+            1 => u16::from_le_bytes([object[18], object[19]]),
+            _ => u16::from_be_bytes([object[18], object[19]]),
+        };
+        if machine != format.elf_machine || bits != format.bits {
+            return mismatch(format!("a {} bit ELF object for machine {}", bits, machine));
+        }
+        return Ok(());
+    }
+
+    if object.len() >= 8 {
+        let magic = u32::from_le_bytes([object[0], object[1], object[2], object[3]]);
+        if magic == MACHO_MAGIC_64 || magic == MACHO_MAGIC_32 {
+            let cpu = u32::from_le_bytes([object[4], object[5], object[6], object[7]]);
+            if cpu != format.macho_cpu {
+                return mismatch(format!("a Mach-O object for cpu type {:#x}", cpu));
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The first 20 bytes of a little endian ELF object, up to and including e_machine
+    fn elf(bits: u8, machine: u16) -> Vec<u8> {
+        let mut header = ELF_MAGIC.to_vec();
+        header.extend([if bits == 64 { 2 } else { 1 }, 1, 1, 0]);
+        header.resize(16, 0);
+        header.extend(1u16.to_le_bytes()); // e_type, a relocatable file
+        header.extend(machine.to_le_bytes());
+        header
+    }
+
+    fn macho(cpu: u32) -> Vec<u8> {
+        let mut header = MACHO_MAGIC_64.to_le_bytes().to_vec();
+        header.extend(cpu.to_le_bytes());
+        header
+    }
+
+    #[test]
+    fn matching_headers_pass() {
+        assert!(check_object(&elf(64, 183), "aarch64-unknown-linux-gnu").is_ok());
+        assert!(check_object(&elf(64, 243), "riscv64gc-unknown-linux-gnu").is_ok());
+        assert!(check_object(&elf(32, 243), "riscv32imac-unknown-none-elf").is_ok());
+        assert!(check_object(&macho(0x0100_000c), "aarch64-apple-darwin").is_ok());
+        assert!(check_object(&macho(0x0100_000c), "arm64-apple-macosx").is_ok());
+    }
+
+    #[test]
+    fn mismatched_headers_fail() {
+        let err = check_object(&elf(64, 62), "aarch64-unknown-linux-gnu").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "emitting object file: object file for aarch64-unknown-linux-gnu came out as a 64 bit ELF object for machine 62"
+        );
+        assert!(check_object(&elf(32, 243), "riscv64gc-unknown-linux-gnu").is_err());
+        assert!(check_object(&elf(64, 183), "riscv64gc-unknown-linux-gnu").is_err());
+        assert!(check_object(&macho(0x0100_0007), "aarch64-apple-darwin").is_err());
+    }
+
+    #[test]
+    fn unknown_architectures_pass() {
+        assert!(check_object(&elf(64, 62), "powerpc64le-unknown-linux-gnu").is_ok());
+    }
+
+    #[test]
+    fn wasm_needs_the_wasm_magic() {
+        assert!(check_object(b"\0asm\x01\0\0\0", "wasm32-wasi").is_ok());
+        assert!(check_object(&elf(32, 0), "wasm32-wasi").is_err());
+    }
+}
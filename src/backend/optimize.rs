@@ -0,0 +1,117 @@
+use super::BackendError;
+use inkwell::module::Module;
+use inkwell::passes::PassBuilderOptions;
+use inkwell::targets::TargetMachine;
+use inkwell::OptimizationLevel;
+use std::str::FromStr;
+
+// -O0 to -O3, and -Os for small code that's still mostly fast
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum OptLevel {
+    O0,
+    O1,
+    O2,
+    O3,
+    Os,
+}
+
+impl OptLevel {
+    // Profiles in lumina.toml only have the numbered levels, anything past 3 is rejected there
+    pub fn from_profile(level: u8) -> OptLevel {
+        match level {
+            0 => OptLevel::O0,
+            1 => OptLevel::O1,
+            2 => OptLevel::O2,
+            _ => OptLevel::O3,
+        }
+    }
+
+    // How hard the code generator itself tries, separate from the IR passes
+    pub fn codegen_level(self) -> OptimizationLevel {
+        match self {
+            OptLevel::O0 => OptimizationLevel::None,
+            OptLevel::O1 => OptimizationLevel::Less,
+            OptLevel::O2 | OptLevel::Os => OptimizationLevel::Default,
+            OptLevel::O3 => OptimizationLevel::Aggressive,
+        }
+    }
+
+    // The new pass manager's pipeline for this level, like `opt -passes=default<O2>`
+    fn pipeline(self) -> &'static str {
+        match self {
+            OptLevel::O0 => "default<O0>",
+            OptLevel::O1 => "default<O1>",
+            OptLevel::O2 => "default<O2>",
+            OptLevel::O3 => "default<O3>",
+            OptLevel::Os => "default<Os>",
+        }
+    }
+}
+
+impl FromStr for OptLevel {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "0" => Ok(OptLevel::O0),
+            "1" => Ok(OptLevel::O1),
+            "2" => Ok(OptLevel::O2),
+            "3" => Ok(OptLevel::O3),
+            "s" => Ok(OptLevel::Os),
+            _ => Err(format!("unknown optimization level '{}', expected 0, 1, 2, 3 or s", value)),
+        }
+    }
+}
+
+// What the backend does with a module and the machine it makes code for
+#[derive(Debug, Clone)]
+pub struct Options {
+    pub opt_level: OptLevel,
+    pub passes: Option<String>,   // Replaces the level's pipeline, same syntax as opt's -passes
+    pub target: Option<String>,   // Triple to cross compile for, the host otherwise
+    pub cpu: Option<String>,      // Defaults to the host's cpu, or "generic" for other targets
+    pub features: Option<String>, // LLVM feature string like "+neon,-fp-armv8"
+}
+
+// Runs the level's pipeline, or the custom one, over the whole module. -O0 without custom
+// passes leaves the IR exactly as codegen made it
+pub fn optimize(module: &Module, machine: &TargetMachine, options: &Options) -> Result<(), BackendError> {
+    let passes = match &options.passes {
+        Some(passes) => passes.as_str(),
+        None if options.opt_level == OptLevel::O0 => return Ok(()),
+        None => options.opt_level.pipeline(),
+    };
+
+    // Same as clang, the vectorizers only run from -O2 up
+    let vectorize = matches!(options.opt_level, OptLevel::O2 | OptLevel::O3 | OptLevel::Os);
+    let builder = PassBuilderOptions::create();
+    builder.set_loop_vectorization(vectorize);
+    builder.set_loop_slp_vectorization(vectorize);
+    builder.set_loop_interleaving(vectorize);
+    builder.set_loop_unrolling(options.opt_level != OptLevel::Os);
+    builder.set_merge_functions(options.opt_level == OptLevel::Os);
+
+    module
+        .run_passes(passes, machine, builder)
+        .map_err(|err| BackendError::Optimize(passes.to_string(), err.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn levels_parse_like_the_flag_takes_them() {
+        assert_eq!("2".parse::<OptLevel>(), Ok(OptLevel::O2));
+        assert_eq!("s".parse::<OptLevel>(), Ok(OptLevel::Os));
+        assert!("4".parse::<OptLevel>().is_err());
+        assert_eq!(OptLevel::Os.pipeline(), "default<Os>");
+    }
+
+    #[test]
+    fn profiles_map_onto_levels() {
+        assert_eq!(OptLevel::from_profile(0), OptLevel::O0);
+        assert_eq!(OptLevel::from_profile(3), OptLevel::O3);
+        assert_eq!(OptLevel::from_profile(2).codegen_level(), OptimizationLevel::Default);
+    }
+}
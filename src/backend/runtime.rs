@@ -0,0 +1,79 @@
+use super::BackendError;
+use crate::parser::RUNTIME_PRINT;
+use inkwell::attributes::AttributeLoc;
+use inkwell::builder::BuilderError;
+use inkwell::module::{Linkage, Module};
+use inkwell::targets::TargetData;
+use inkwell::values::FunctionValue;
+use inkwell::AddressSpace;
+
+// Gives the runtime functions codegen declared their bodies for the target. Native code and
+// the JIT print with libc, wasm hands the text straight to WASI. Once defined they're internal,
+// so a wasm module doesn't export them and they can be inlined
+pub fn define_runtime(module: &Module, wasm: bool) -> Result<(), BackendError> {
+    let Some(print) = module.get_function(RUNTIME_PRINT).filter(|print| print.count_basic_blocks() == 0) else {
+        return Ok(());
+    };
+    let defined = if wasm { define_wasi_print(module, print) } else { define_libc_print(module, print) };
+    defined.map_err(|err| BackendError::Runtime(format!("{}: {}", RUNTIME_PRINT, err)))?;
+    print.set_linkage(Linkage::Internal);
+    Ok(())
+}
+
+// printf("%s", text)
+fn define_libc_print<'ctx>(module: &Module<'ctx>, print: FunctionValue<'ctx>) -> Result<(), BuilderError> {
+    let context = module.get_context();
+    let ptr_type = context.ptr_type(AddressSpace::default());
+    let printf = module
+        .get_function("printf")
+        .unwrap_or_else(|| module.add_function("printf", context.i32_type().fn_type(&[ptr_type.into()], true), None));
+
+    let builder = context.create_builder();
+    builder.position_at_end(context.append_basic_block(print, "entry"));
+    let text = print.get_nth_param(0).expect("print takes the text");
+    let format = builder.build_global_string_ptr("%s", "fmt")?;
+    builder.build_call(printf, &[format.as_pointer_value().into(), text.into()], "")?;
+    builder.build_return(None)?;
+    Ok(())
+}
+
+// fd_write(stdout, [{ text, strlen(text) }], 1, &written). stdout isn't buffered this way,
+// so there's nothing left to flush when the program exits
+fn define_wasi_print<'ctx>(module: &Module<'ctx>, print: FunctionValue<'ctx>) -> Result<(), BuilderError> {
+    let context = module.get_context();
+    let ptr_type = context.ptr_type(AddressSpace::default());
+    let i32_type = context.i32_type();
+    let size_type = context.ptr_sized_int_type(&TargetData::create(&module.get_data_layout().as_str().to_string_lossy()), None);
+
+    let strlen = module
+        .get_function("strlen")
+        .unwrap_or_else(|| module.add_function("strlen", size_type.fn_type(&[ptr_type.into()], false), None));
+    let fd_write = module.get_function("lumina_fd_write").unwrap_or_else(|| {
+        let fn_type = i32_type.fn_type(&[i32_type.into(), ptr_type.into(), size_type.into(), ptr_type.into()], false);
+        let fd_write = module.add_function("lumina_fd_write", fn_type, None);
+        fd_write.add_attribute(
+            AttributeLoc::Function,
+            context.create_string_attribute("wasm-import-module", "wasi_snapshot_preview1"),
+        );
+        fd_write.add_attribute(AttributeLoc::Function, context.create_string_attribute("wasm-import-name", "fd_write"));
+        fd_write
+    });
+
+    let builder = context.create_builder();
+    builder.position_at_end(context.append_basic_block(print, "entry"));
+    let text = print.get_nth_param(0).expect("print takes the text");
+    let length = builder.build_call(strlen, &[text.into()], "length")?;
+    let length = length.try_as_basic_value().left().expect("strlen returns the length");
+
+    let iovec_type = context.struct_type(&[ptr_type.into(), size_type.into()], false);
+    let iovec = builder.build_alloca(iovec_type, "iovec")?;
+    builder.build_store(builder.build_struct_gep(iovec_type, iovec, 0, "buf")?, text)?;
+    builder.build_store(builder.build_struct_gep(iovec_type, iovec, 1, "len")?, length)?;
+    let written = builder.build_alloca(size_type, "written")?;
+
+    let stdout = i32_type.const_int(1, false);
+    let count = size_type.const_int(1, false);
+    builder.build_call(fd_write, &[stdout.into(), iovec.into(), count.into(), written.into()], "")?;
+    builder.build_return(None)?;
+    Ok(())
+}
@@ -0,0 +1,301 @@
+use super::BackendError;
+use crate::parser::ENTRY_POINT;
+use inkwell::attributes::AttributeLoc;
+use inkwell::module::Module;
+use std::path::Path;
+use std::process::Command;
+
+const WASI_MODULE: &str = "wasi_snapshot_preview1";
+
+pub fn is_wasm(triple: &str) -> bool {
+    triple.starts_with("wasm")
+}
+
+// Exports the program's own top level functions under their names and adds the `_start`
+// WASI runs, which sets up libc, calls the top level code and exits with what it returns.
+// Methods, specializations and the prelude stay internal. Hands back the names of
+// everything exported
+pub fn add_exports(module: &Module, functions: &[String]) -> Result<Vec<String>, BackendError> {
+    let context = module.get_context();
+    let entry_point = module
+        .get_function(ENTRY_POINT)
+        .ok_or_else(|| BackendError::EntryPoint("the module has no top level code".into()))?;
+    if module.get_function("_start").is_some() {
+        return Err(BackendError::EntryPoint(
+            "'_start' is already a function, it can't also be the module's entry point".into(),
+        ));
+    }
+
+    let mut exports = Vec::new();
+    for name in functions {
+        let Some(function) = module.get_function(name).filter(|function| function.count_basic_blocks() > 0) else {
+            return Err(BackendError::Emit(format!("'{}' has no body to export", name)));
+        };
+        function.add_attribute(AttributeLoc::Function, context.create_string_attribute("wasm-export-name", name));
+        exports.push(name.clone());
+    }
+
+    let i32_type = context.i32_type();
+    let void_type = context.void_type();
+    // libc's, which flushes stdout before it asks WASI to exit
+    let exit = module
+        .get_function("exit")
+        .unwrap_or_else(|| module.add_function("exit", void_type.fn_type(&[i32_type.into()], false), None));
+
+    // Made by the linker, runs libc's constructors
+    let call_ctors = module.add_function("__wasm_call_ctors", void_type.fn_type(&[], false), None);
+
+    let start = module.add_function("_start", void_type.fn_type(&[], false), None);
+    start.add_attribute(AttributeLoc::Function, context.create_string_attribute("wasm-export-name", "_start"));
+    let builder = context.create_builder();
+    builder.position_at_end(context.append_basic_block(start, "entry"));
+    let build = || -> Result<(), inkwell::builder::BuilderError> {
+        builder.build_call(call_ctors, &[], "")?;
+        let status = builder.build_call(entry_point, &[], "status")?;
+        let status = status.try_as_basic_value().left().unwrap_or_else(|| i32_type.const_zero().into());
+        builder.build_call(exit, &[status.into()], "")?;
+        builder.build_unreachable()?;
+        Ok(())
+    };
+    build().map_err(|err| BackendError::EntryPoint(err.to_string()))?;
+    exports.push("_start".to_string());
+    Ok(exports)
+}
+
+// clang does the linking, like cc does for native code. It knows where wasi-libc and the
+// compiler-rt builtins are, which is everything snprintf, malloc and the rest need, so the
+// module only imports from WASI. $WASM_CC picks a different clang, and $WASI_SYSROOT points
+// it at wasi-libc when it doesn't know. `_start` is ours, so none of libc's startup files
+pub fn link(object: &Path, output: &Path, triple: &str) -> Result<(), BackendError> {
+    let linker = std::env::var("WASM_CC").unwrap_or_else(|_| "clang".to_string());
+    let mut command = Command::new(&linker);
+    command.arg(format!("--target={}", triple)).arg("-nostartfiles");
+    if let Ok(sysroot) = std::env::var("WASI_SYSROOT") {
+        command.arg(format!("--sysroot={}", sysroot));
+    }
+    let result = command
+        .arg(object)
+        .arg("-o")
+        .arg(output)
+        .output()
+        .map_err(|err| BackendError::Linker(format!("couldn't run {}: {}", linker, err)))?;
+
+    if !result.status.success() {
+        return Err(BackendError::Linker(format!(
+            "{} failed: {}",
+            linker,
+            String::from_utf8_lossy(&result.stderr).trim()
+        )));
+    }
+    Ok(())
+}
+
+// Walks the sections of a linked module to make sure it's well formed: the header is right,
+// every section fits, each function has a body, nothing is imported from anywhere but WASI,
+// and everything in `exports` is exported
+pub fn check_module(bytes: &[u8], exports: &[String]) -> Result<(), BackendError> {
+    let invalid = |message: String| BackendError::Emit(format!("invalid wasm module: {}", message));
+    if bytes.len() < 8 || &bytes[..4] != b"\0asm" {
+        return Err(invalid("no wasm header".into()));
+    }
+    if bytes[4..8] != [1, 0, 0, 0] {
+        return Err(invalid("unsupported version".into()));
+    }
+
+    let mut reader = Reader { bytes, position: 8 };
+    let mut function_count = 0;
+    let mut body_count = 0;
+    let mut exported = Vec::new();
+    while !reader.at_end() {
+        let id = reader.byte()?;
+        let size = reader.leb()? as usize;
+        let end = reader.position + size;
+        if end > bytes.len() {
+            return Err(invalid(format!("section {} runs past the end of the module", id)));
+        }
+        let mut section = Reader { bytes: &bytes[..end], position: reader.position };
+        match id {
+            2 => {
+                for _ in 0..section.leb()? {
+                    let module = section.name()?;
+                    let field = section.name()?;
+                    let kind = section.byte()?;
+                    // Anything else would need a host that provides it, plain WASI runtimes don't
+                    if module != WASI_MODULE {
+                        return Err(invalid(format!("'{}' is imported from '{}', not WASI", field, module)));
+                    }
+                    section.skip_import(kind)?;
+                }
+            }
+            3 => function_count = section.leb()?,
+            7 => {
+                for _ in 0..section.leb()? {
+                    exported.push(section.name()?);
+                    section.byte()?;
+                    section.leb()?;
+                }
+            }
+            10 => body_count = section.leb()?,
+            _ => {}
+        }
+        reader.position = end;
+    }
+
+    if function_count != body_count {
+        return Err(invalid(format!("{} functions but {} bodies", function_count, body_count)));
+    }
+    if let Some(missing) = exports.iter().find(|name| !exported.contains(name)) {
+        return Err(invalid(format!("'{}' isn't exported", missing)));
+    }
+    Ok(())
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl Reader<'_> {
+    fn at_end(&self) -> bool {
+        self.position >= self.bytes.len()
+    }
+
+    fn byte(&mut self) -> Result<u8, BackendError> {
+        let byte = *self
+            .bytes
+            .get(self.position)
+            .ok_or_else(|| BackendError::Emit("invalid wasm module: unexpected end".into()))?;
+        self.position += 1;
+        Ok(byte)
+    }
+
+    // Unsigned LEB128
+    fn leb(&mut self) -> Result<u32, BackendError> {
+        let mut value: u32 = 0;
+        for shift in (0..35).step_by(7) {
+            let byte = self.byte()?;
+            value |= ((byte & 0x7f) as u32) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+        Err(BackendError::Emit("invalid wasm module: integer too long".into()))
+    }
+
+    fn name(&mut self) -> Result<String, BackendError> {
+        let length = self.leb()? as usize;
+        let start = self.position;
+        if start + length > self.bytes.len() {
+            return Err(BackendError::Emit("invalid wasm module: name runs past its section".into()));
+        }
+        self.position += length;
+        Ok(String::from_utf8_lossy(&self.bytes[start..start + length]).into_owned())
+    }
+
+    // What follows an import's kind: a type index, a table, a memory, a global or a tag
+    fn skip_import(&mut self, kind: u8) -> Result<(), BackendError> {
+        match kind {
+            0 => {
+                self.leb()?;
+            }
+            1 => {
+                self.byte()?;
+                self.skip_limits()?;
+            }
+            2 => self.skip_limits()?,
+            3 => {
+                self.byte()?;
+                self.byte()?;
+            }
+            4 => {
+                self.byte()?;
+                self.leb()?;
+            }
+            _ => return Err(BackendError::Emit(format!("invalid wasm module: unknown import kind {}", kind))),
+        }
+        Ok(())
+    }
+
+    fn skip_limits(&mut self) -> Result<(), BackendError> {
+        let flags = self.byte()?;
+        self.leb()?;
+        if flags & 1 != 0 {
+            self.leb()?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn name(text: &str) -> Vec<u8> {
+        let mut bytes = vec![text.len() as u8];
+        bytes.extend(text.as_bytes());
+        bytes
+    }
+
+    // A module made of the given sections, each an id and its contents. Sizes and counts
+    // here all fit in one LEB128 byte
+    fn module(sections: &[(u8, Vec<u8>)]) -> Vec<u8> {
+        let mut bytes = b"\0asm\x01\0\0\0".to_vec();
+        for (id, contents) in sections {
+            bytes.push(*id);
+            bytes.push(contents.len() as u8);
+            bytes.extend(contents);
+        }
+        bytes
+    }
+
+    // One function imported from `from`, one defined and exported as _start
+    fn program(from: &str) -> Vec<u8> {
+        let types = vec![1, 0x60, 0, 0];
+        let mut imports = vec![1];
+        imports.extend(name(from));
+        imports.extend(name("fd_write"));
+        imports.extend([0, 0]);
+        let functions = vec![1, 0];
+        let mut exports = vec![1];
+        exports.extend(name("_start"));
+        exports.extend([0, 1]);
+        let code = vec![1, 2, 0, 0x0b];
+        module(&[(1, types), (2, imports), (3, functions), (7, exports), (10, code)])
+    }
+
+    fn message(result: Result<(), BackendError>) -> String {
+        result.unwrap_err().to_string()
+    }
+
+    #[test]
+    fn wasi_imports_pass() {
+        assert!(check_module(&program(WASI_MODULE), &["_start".to_string()]).is_ok());
+    }
+
+    #[test]
+    fn other_imports_fail() {
+        assert_eq!(
+            message(check_module(&program("env"), &[])),
+            "emitting object file: invalid wasm module: 'fd_write' is imported from 'env', not WASI"
+        );
+    }
+
+    #[test]
+    fn exports_have_to_be_there() {
+        let exports = ["_start".to_string(), "area".to_string()];
+        assert!(message(check_module(&program(WASI_MODULE), &exports)).ends_with("'area' isn't exported"));
+    }
+
+    #[test]
+    fn broken_modules_fail() {
+        assert!(message(check_module(b"\0elf\x01\0\0\0", &[])).ends_with("no wasm header"));
+        assert!(message(check_module(b"\0asm\x02\0\0\0", &[])).ends_with("unsupported version"));
+
+        let bodiless = module(&[(3, vec![1, 0]), (10, vec![0])]);
+        assert!(message(check_module(&bodiless, &[])).ends_with("1 functions but 0 bodies"));
+
+        let mut truncated = program(WASI_MODULE);
+        truncated.truncate(truncated.len() - 2);
+        assert!(message(check_module(&truncated, &[])).ends_with("section 10 runs past the end of the module"));
+    }
+}
@@ -7,6 +7,9 @@ use std::collections::VecDeque;
 pub enum Token {
     Identifier(String),                 
     Number(f64),                        
+    Integer(u64),
+    StringLiteral(String),
+    InterpolatedString(Vec<StringPart>), // "Hello {name}"
     Keyword(String),                       
     OperatorToken(Operator),                  
     MathOperatorToken(MathOperator),           
@@ -17,15 +20,40 @@ pub enum Token {
     RightBrace,                       
     LeftParen,                          
     RightParen,     
+    LeftBracket,
+    RightBracket,
     Comma,
+    Dot,
+    Colon,
+    Arrow,
     Comment(String),                      
     EndOfInput,
 }
 
+// A piece of a string with {expressions} in it, either plain text, a bare {} for format(...)
+// to fill in, or one expression's tokens, ending in EndOfInput, with their spans
+#[derive(Debug, Clone, PartialEq)]
+pub enum StringPart {
+    Text(String),
+    Placeholder,
+    Code(Vec<Token>, Vec<Span>),
+}
+
+// Where a token starts in the source, both 1-based, and which source file it's in
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Span {
+    pub line: usize,
+    pub column: usize,
+    pub file: usize,
+}
+
 #[derive(Debug, Clone, PartialEq)]
 pub enum LexerError {
     UnexpectedCharacter(char),
     UnterminatedComment,
+    UnterminatedString,
+    UnterminatedInterpolation,
+    InvalidEscape(char),
 }
 
 // Lexer struct: Main state for the lexer
@@ -34,16 +62,45 @@ pub enum LexerError {
 pub struct Lexer {
     input: VecDeque<char>,  
     tokens: Vec<Token>,
+    spans: Vec<Span>, // One per token
+    line_starts: Vec<usize>,
     index: usize,
+    file: usize,
 }
 
 impl Lexer {
     pub fn new(input: &str) -> Self {
         let input = input.chars().collect::<VecDeque<_>>();
+        let line_starts = std::iter::once(0)
+            .chain(input.iter().enumerate().filter(|(_, ch)| **ch == '\n').map(|(i, _)| i + 1))
+            .collect();
         Self {
             input,
             tokens: Vec::new(),
+            spans: Vec::new(),
+            line_starts,
             index: 0,
+            file: 0,
+        }
+    }
+
+    // Tags every span with the file the source came from, when there's more than one
+    pub fn in_file(mut self, file: usize) -> Self {
+        self.file = file;
+        self
+    }
+
+    // Spans line up with the tokens returned by tokenize
+    pub fn spans(&self) -> &[Span] {
+        &self.spans
+    }
+
+    fn span_at(&self, index: usize) -> Span {
+        let line = self.line_starts.partition_point(|start| *start <= index);
+        Span {
+            line,
+            column: index - self.line_starts[line - 1] + 1,
+            file: self.file,
         }
     }
 
@@ -53,6 +110,7 @@ impl Lexer {
 
     pub fn tokenize(&mut self) -> Result<&[Token], LexerError> {
         while let Some(ch) = self.input.get(self.index).copied() {
+            let start = self.index;
 
             match ch {
                 // Whitespace
@@ -67,6 +125,18 @@ impl Lexer {
                     self.consume_comment()?;
                 }
 
+                // Return type arrow, fn add(a, b) -> int
+                '-' if self.peek_next() == Some('>') => {
+                    self.tokens.push(Token::Arrow);
+                    self.index += 2;
+                }
+
+                // Type annotation, x: int (but not the := walrus)
+                ':' if self.peek_next() != Some('=') => {
+                    self.tokens.push(Token::Colon);
+                    self.index += 1;
+                }
+
                 // Math Operator
                 '+' | '-' | '*' | '/' | '%' => {
                     self.consume_math_operator();
@@ -76,7 +146,10 @@ impl Lexer {
                 '}' => {self.tokens.push(Token::RightBrace); self.index += 1;},
                 '(' => {self.tokens.push(Token::LeftParen); self.index += 1;},
                 ')' => {self.tokens.push(Token::RightParen); self.index += 1;},
+                '[' => {self.tokens.push(Token::LeftBracket); self.index += 1;},
+                ']' => {self.tokens.push(Token::RightBracket); self.index += 1;},
                 ',' => {self.tokens.push(Token::Comma); self.index += 1;}
+                '.' => {self.tokens.push(Token::Dot); self.index += 1;}
 
 
                 'a'..='z' | 'A'..='Z' | '_' => {
@@ -94,11 +167,18 @@ impl Lexer {
                 // Number (0-9)
                 '0'..='9' => self.consume_number(),
 
+                '"' => self.consume_string()?,
+
                 _ => return Err(LexerError::UnexpectedCharacter(ch)),
             }
+
+            // Everything pushed for this character starts where it does
+            let span = self.span_at(start);
+            self.spans.resize(self.tokens.len(), span);
         }
 
         self.tokens.push(Token::EndOfInput);
+        self.spans.push(self.span_at(self.index));
         Ok(&self.tokens)
     }
 
@@ -122,8 +202,8 @@ impl Lexer {
                     }
                     return;
                 },
-                // Logical Operators (e.g., &&, ||)
-                ('&', '&') | ('|', '|') => {
+                // Logical Operators (e.g., &&, ||, ^^)
+                ('&', '&') | ('|', '|') | ('^', '^') => {
                     operator.push(next_ch);
                     self.index += 1;
                     match operator.parse::<LogicalOperator>() {
@@ -192,7 +272,7 @@ impl Lexer {
             }
         }
 
-        let keywords = ["if", "else", "elif", "while", "for", "in", "return", "print", "fn"];
+        let keywords = ["if", "else", "elif", "while", "for", "in", "return", "fn", "struct", "impl", "trait", "dyn", "let", "import", "from", "pub", "as", "true", "false", "null"];
         if keywords.contains(&identifier.as_str()) {
             self.tokens.push(Token::Keyword(identifier));
         } else {
@@ -246,6 +326,104 @@ impl Lexer {
     }
 
     
+    // {expression}s in a string get split out and {{ and }} are plain braces. A {} is a
+    // placeholder of its own, so format("{{}}", x) can tell it from escaped braces
+    fn consume_string(&mut self) -> Result<(), LexerError> {
+        self.index += 1; // Skip the opening quote
+        let mut string = String::new();
+        let mut parts = Vec::new();
+
+        while let Some(ch) = self.input.get(self.index).copied() {
+            self.index += 1;
+            match ch {
+                '"' if parts.is_empty() => {
+                    self.tokens.push(Token::StringLiteral(string));
+                    return Ok(());
+                }
+                '"' => {
+                    if !string.is_empty() {
+                        parts.push(StringPart::Text(string));
+                    }
+                    self.tokens.push(Token::InterpolatedString(parts));
+                    return Ok(());
+                }
+                '{' | '}' if self.input.get(self.index) == Some(&ch) => {
+                    self.index += 1;
+                    string.push(ch);
+                }
+                '{' if self.input.get(self.index) == Some(&'}') => {
+                    self.index += 1;
+                    if !string.is_empty() {
+                        parts.push(StringPart::Text(std::mem::take(&mut string)));
+                    }
+                    parts.push(StringPart::Placeholder);
+                }
+                '{' => {
+                    if !string.is_empty() {
+                        parts.push(StringPart::Text(std::mem::take(&mut string)));
+                    }
+                    parts.push(self.consume_interpolation()?);
+                }
+                '\\' => {
+                    let escaped = self.input.get(self.index).copied().ok_or(LexerError::UnterminatedString)?;
+                    self.index += 1;
+                    match escaped {
+                        'n' => string.push('\n'),
+                        't' => string.push('\t'),
+                        'r' => string.push('\r'),
+                        '0' => string.push('\0'),
+                        '"' | '\\' => string.push(escaped),
+                        _ => return Err(LexerError::InvalidEscape(escaped)),
+                    }
+                }
+                _ => string.push(ch),
+            }
+        }
+
+        Err(LexerError::UnterminatedString)
+    }
+
+    // Everything up to the matching }, lexed on its own with the spans moved to where it is
+    // in this file. Strings inside can have braces of their own
+    fn consume_interpolation(&mut self) -> Result<StringPart, LexerError> {
+        let start = self.index;
+        let mut depth = 0;
+        loop {
+            let ch = self.input.get(self.index).copied().ok_or(LexerError::UnterminatedInterpolation)?;
+            self.index += 1;
+            match ch {
+                '"' => loop {
+                    let ch = self.input.get(self.index).copied().ok_or(LexerError::UnterminatedString)?;
+                    self.index += 1;
+                    match ch {
+                        '\\' => self.index += 1,
+                        '"' => break,
+                        _ => {}
+                    }
+                },
+                '{' => depth += 1,
+                '}' if depth == 0 => break,
+                '}' => depth -= 1,
+                _ => {}
+            }
+        }
+
+        let code: String = self.input.range(start..self.index - 1).collect();
+        let mut lexer = Lexer::new(&code).in_file(self.file);
+        lexer.tokenize()?;
+        let origin = self.span_at(start);
+        let spans = lexer
+            .spans
+            .iter()
+            .map(|span| Span {
+                line: span.line + origin.line - 1,
+                column: if span.line == 1 { span.column + origin.column - 1 } else { span.column },
+                file: self.file,
+            })
+            .collect();
+        Ok(StringPart::Code(lexer.tokens, spans))
+    }
+
     fn consume_number(&mut self) {
         let mut number = String::new();
         let mut is_float = false;
@@ -263,8 +441,71 @@ impl Lexer {
             }
         }
 
+        // Without a dot it's an integer, unless it's too big for one
+        if !is_float {
+            if let Ok(value) = number.parse::<u64>() {
+                self.tokens.push(Token::Integer(value));
+                return;
+            }
+        }
         if let Ok(value) = number.parse::<f64>() {
             self.tokens.push(Token::Number(value))
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn string_parts(source: &str) -> Vec<StringPart> {
+        let mut lexer = Lexer::new(source);
+        match lexer.tokenize().expect("source should lex").first() {
+            Some(Token::InterpolatedString(parts)) => parts.clone(),
+            other => panic!("expected an interpolated string, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn escaped_braces_are_text() {
+        let mut lexer = Lexer::new("\"{{x}}\"");
+        let tokens = lexer.tokenize().expect("source should lex");
+        assert_eq!(tokens.first(), Some(&Token::StringLiteral("{x}".to_string())));
+    }
+
+    #[test]
+    fn bare_braces_are_placeholders() {
+        assert_eq!(
+            string_parts("\"{{}} = {}\""),
+            vec![StringPart::Text("{} = ".to_string()), StringPart::Placeholder]
+        );
+    }
+
+    #[test]
+    fn expressions_are_lexed_on_their_own() {
+        let parts = string_parts("\"sum {a + b}!\"");
+        assert_eq!(parts.len(), 3);
+        assert_eq!(parts[0], StringPart::Text("sum ".to_string()));
+        assert_eq!(parts[2], StringPart::Text("!".to_string()));
+        let StringPart::Code(tokens, spans) = &parts[1] else {
+            panic!("expected code, got {:?}", parts[1]);
+        };
+        assert_eq!(
+            tokens,
+            &vec![
+                Token::Identifier("a".to_string()),
+                Token::MathOperatorToken(MathOperator::Add),
+                Token::Identifier("b".to_string()),
+                Token::EndOfInput,
+            ]
+        );
+        // Spans point into the outer source, just past the {
+        assert_eq!((spans[0].line, spans[0].column), (1, 7));
+    }
+
+    #[test]
+    fn unterminated_interpolation() {
+        let mut lexer = Lexer::new("\"{a + b\"");
+        assert!(lexer.tokenize().is_err());
+    }
+}
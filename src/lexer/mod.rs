@@ -1,4 +1,4 @@
 mod lexer;
 pub mod enums;
 
-pub use lexer::{Lexer, Token};
+pub use lexer::{Lexer, LexerError, Span, StringPart, Token};
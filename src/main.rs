@@ -1,11 +1,24 @@
+mod backend;
 mod lexer;
+mod modules;
 mod parser;
+mod project;
+mod semantic;
 
-use parser::Parser as SyntaxParser;
-use parser::enums::{Statement, Expression};
-use clap::Parser;
-use lexer::{Lexer, Token};
+use parser::enums::{Statement, Expression, SymbolId};
+use clap::{Parser, Subcommand};
+use inkwell::context::Context;
+use inkwell::data_layout::DataLayout;
+use inkwell::module::Module as LlvmModule;
+use backend::{Emit, EmitKind, OptLevel, Options};
+use modules::{Module, ModuleLoader};
+use parser::CodegenContext;
+use project::{Profile, Project};
+use semantic::{MethodTable, Resolver, TypeChecker};
+use std::collections::HashSet;
 use std::fs;
+use std::path::{Path, PathBuf};
+use std::process;
 
 #[derive(Parser, Debug)]
 #[command(
@@ -14,95 +27,407 @@ use std::fs;
     about = "Lumina Compiler"
 )]
 struct Args {
-    #[arg(default_value = "main.lum")]
-    input: String,
+    #[command(subcommand)]
+    command: Command,
 
-    #[arg(short, long, default_value = "output.out")]
-    output: String,
-
-    #[arg(short, long)]
+    #[arg(short, long, global = true)]
     verbose: bool,
 }
 
+#[derive(Subcommand, Debug)]
+enum Command {
+    #[command(about = "Compile the project into its target directory")]
+    Build {
+        #[command(flatten)]
+        build: BuildArgs,
+
+        #[command(flatten)]
+        output: OutputArgs,
+    },
+
+    #[command(about = "Compile the project, or a single file, in memory and run it. Arguments after -- go to the program")]
+    Run {
+        // Runs just this file and what it imports from next to it, no lumina.toml needed
+        file: Option<PathBuf>,
+
+        #[command(flatten)]
+        build: BuildArgs,
+
+        #[arg(last = true)]
+        args: Vec<String>,
+    },
+}
+
+#[derive(clap::Args, Debug)]
+struct BuildArgs {
+    #[arg(long, help = "Build with the release profile")]
+    release: bool,
+
+    #[arg(long, conflicts_with = "release", help = "Build with a profile from lumina.toml")]
+    profile: Option<String>,
+
+    // Otherwise the closest lumina.toml from the current directory up is used
+    #[arg(long)]
+    manifest_path: Option<PathBuf>,
+
+    #[arg(short = 'O', value_name = "LEVEL", help = "Optimization level, 0 to 3 or s, instead of the profile's")]
+    opt_level: Option<OptLevel>,
+
+    #[arg(long, help = "LLVM passes to run instead of the optimization level's pipeline, as in opt -passes")]
+    passes: Option<String>,
+
+    #[arg(short = 'g', help = "Emit DWARF debug info, even if the profile doesn't")]
+    debug_info: bool,
+
+    #[arg(long, value_name = "TRIPLE", help = "Cross compile for this target, like aarch64-unknown-linux-gnu")]
+    target: Option<String>,
+
+    #[arg(long, help = "CPU to generate code for, the host's by default")]
+    cpu: Option<String>,
+
+    #[arg(long, help = "LLVM target features to turn on or off, like +avx2,-sse4.1")]
+    features: Option<String>,
+}
+
+// What `build` writes out. `run` keeps everything in memory, so it doesn't take these
+#[derive(clap::Args, Debug)]
+struct OutputArgs {
+    // Defaults to the package name inside the profile's target directory
+    #[arg(short, long, help = "Where to write the executable")]
+    output: Option<PathBuf>,
+
+    #[arg(
+        long,
+        value_delimiter = ',',
+        default_value = "exe",
+        help = "What to write out: tokens, ast, llvm-ir, llvm-bc, asm, obj, exe. kind=path picks the file, - is stdout"
+    )]
+    emit: Vec<Emit>,
+}
+
 fn main() {
     let args = Args::parse();
 
     if args.verbose {
         println!("Lumina Compiler v0.1.0");
-        println!("Input file: {}", args.input);
-        println!("Output file: {}", args.output);
     }
 
-    let source_code = match fs::read_to_string(&args.input) {
-        Ok(content) => content,
-        Err(err) => {
-            eprintln!("Failed to read file {}: {}", args.input, err);
-            std::process::exit(1);
+    match &args.command {
+        Command::Build { build, output } => {
+            build_project(build, output, args.verbose);
+        }
+        Command::Run { file, build, args: program_args } => {
+            run_program(file.as_deref(), build, program_args, args.verbose);
+        }
+    }
+}
+
+// Compiles the whole project, its entry file and everything it imports, into an executable
+fn build_project(build: &BuildArgs, output: &OutputArgs, verbose: bool) {
+    let (project, profile) = load_project(build);
+    if verbose {
+        println!(
+            "Building {} v{} [{}, opt-level {}{}]",
+            project.name(),
+            project.manifest.package.version,
+            profile.name,
+            profile.opt_level,
+            if profile.debug { ", debug info" } else { "" }
+        );
+    }
+
+    let target_dir = project.target_dir(&profile);
+    if let Err(err) = fs::create_dir_all(&target_dir) {
+        fail(format!("Failed to create {}: {}", target_dir.display(), err));
+    }
+    let stem = target_dir.join(project.name());
+    let artifacts: Vec<(EmitKind, PathBuf)> = output
+        .emit
+        .iter()
+        .map(|emit| match (emit.kind, &emit.path, &output.output) {
+            (EmitKind::Exe, None, Some(output)) => (emit.kind, output.clone()),
+            (EmitKind::Exe, None, None) if build.target.as_deref().is_some_and(backend::is_wasm) => {
+                let mut module = stem.as_os_str().to_owned();
+                module.push(".wasm");
+                (emit.kind, PathBuf::from(module))
+            }
+            _ => (emit.kind, emit.destination(&stem)),
+        })
+        .collect();
+
+    let context = Context::create();
+    let mut loader = project_loader(&project);
+    let (module, functions) = compile(&context, &mut loader, &project.entry(), &artifacts, build, &profile, verbose);
+
+    if verbose {
+        println!("Writing {:?}...", artifacts);
+    }
+    if let Err(err) = backend::emit(&module, &artifacts, &functions, &backend_options(build, &profile)) {
+        fail(err);
+    }
+    if verbose {
+        println!("Compilation successful.");
+    }
+}
+
+// Runs through the JIT, so nothing ends up on disk. The program's exit code becomes ours
+fn run_program(file: Option<&Path>, build: &BuildArgs, args: &[String], verbose: bool) {
+    let context = Context::create();
+    let (name, module, profile) = match file {
+        Some(file) => {
+            let dir = file.parent().unwrap_or(Path::new(".")).to_path_buf();
+            let mut loader = ModuleLoader::new(vec![dir]);
+            let name = file.file_stem().unwrap_or_default().to_string_lossy().into_owned();
+            let profile = Profile::builtin(profile_name(build));
+            let (module, _) = compile(&context, &mut loader, file, &[], build, &profile, verbose);
+            (name, module, profile)
+        }
+        None => {
+            let (project, profile) = load_project(build);
+            let mut loader = project_loader(&project);
+            let (module, _) = compile(&context, &mut loader, &project.entry(), &[], build, &profile, verbose);
+            (project.name().to_string(), module, profile)
         }
     };
 
-    if args.verbose {
-        println!("Successfully read the input file.");
+    if verbose {
+        println!("Running {}", name);
+    }
+    match backend::run_in_memory(&module, &name, args, &backend_options(build, &profile)) {
+        Ok(status) => process::exit(status),
+        Err(err) => fail(err),
     }
+}
 
-    if args.verbose {
-        println!("Starting lexical analysis...");
+fn project_loader(project: &Project) -> ModuleLoader {
+    let mut loader = ModuleLoader::new(project.source_dirs());
+    match project.dependencies() {
+        Ok(dependencies) => {
+            for (name, dependency) in dependencies {
+                loader.add_package(name, dependency.entry(), dependency.source_dirs());
+            }
+        }
+        Err(err) => fail(err),
     }
-    let tokens = lex(&source_code);
-    if args.verbose {
-        println!("Lexical analysis completed. Tokens: {:?}", tokens);
+    loader
+}
+
+// Every pass from parsing the entry file to LLVM IR. The tokens and ASTs in `artifacts` are
+// written on the way, the rest is up to the backend. Also hands back the names of the
+// functions the program's own modules define at their top level
+fn compile<'ctx>(
+    context: &'ctx Context,
+    loader: &mut ModuleLoader,
+    entry: &Path,
+    artifacts: &[(EmitKind, PathBuf)],
+    build: &BuildArgs,
+    profile: &Profile,
+    verbose: bool,
+) -> (LlvmModule<'ctx>, Vec<String>) {
+    if verbose {
+        println!("Loading modules...");
     }
+    let (prelude, modules) = load_modules(loader, entry);
+    if verbose {
+        for module in modules.iter() {
+            println!("Parsed {}", loader.path(module.file).display());
+        }
+    }
+    emit_front_end(&modules, loader, artifacts);
+    // The entry file is loaded last
+    let main_file = modules.last().map(|module| module.file);
 
-    if args.verbose {
-        println!("Starting syntax analysis...");
+    if verbose {
+        println!("Resolving names...");
     }
-    let ast = parse(tokens);
-    if args.verbose {
-        println!("Syntax analysis completed. AST: {:?}", ast);
+    // The prelude comes first in the resolved program, everything after it is the program's
+    let prelude_length = prelude.len();
+    let (ast, captured) = resolve_names(prelude, modules, loader);
+    let functions = top_level_functions(&ast[prelude_length..]);
+
+    if verbose {
+        println!("Resolving methods...");
     }
+    let (mut ast, methods) = resolve_methods(ast);
 
-    if args.verbose {
+    if verbose {
+        println!("Checking types...");
+    }
+    check_types(&mut ast, &methods, loader);
+
+    if verbose {
+        println!("Specializing generics...");
+    }
+    let ast = parser::monomorphize(ast);
+
+    if verbose {
         println!("Generating code...");
     }
-    let machine_code = generate_code(ast);
-    if args.verbose {
+    let debug_info = build.debug_info || profile.debug;
+    let files = main_file.filter(|_| debug_info).map(|main_file| {
+        let layout = backend::data_layout(&backend_options(build, profile)).unwrap_or_else(|err| fail(err));
+        (loader.files(), main_file, layout)
+    });
+    let module = generate_code(context, ast, methods, captured, files, profile.overflow_checks);
+    if verbose {
         println!("Code generation completed.");
     }
+    (module, functions)
+}
 
-    if args.verbose {
-        println!("Writing to output file: {}", args.output);
-    }
-    match fs::write(&args.output, machine_code) {
-        Ok(_) => {
-            if args.verbose {
-                println!("Compilation successful. Output written to {}", args.output);
+// Generic ones only exist as their specializations, so they're left out
+fn top_level_functions(ast: &[Statement]) -> Vec<String> {
+    ast.iter()
+        .filter_map(|stmt| match stmt.node() {
+            Statement::FunctionDefinition { name, generics, .. } if generics.is_empty() => Some(name.clone()),
+            _ => None,
+        })
+        .collect()
+}
+
+// Every file's tokens or AST, one after another under a line naming the file
+fn emit_front_end(modules: &[Module], loader: &ModuleLoader, artifacts: &[(EmitKind, PathBuf)]) {
+    for (kind, path) in artifacts {
+        if !matches!(kind, EmitKind::Tokens | EmitKind::Ast) {
+            continue;
+        }
+        let mut text = String::new();
+        for module in modules {
+            text.push_str(&format!("# {}\n", loader.path(module.file).display()));
+            if *kind == EmitKind::Tokens {
+                for (token, span) in module.tokens.iter() {
+                    text.push_str(&format!("{}:{} {:?}\n", span.line, span.column, token));
+                }
+            } else {
+                text.push_str(&format!("{:#?}\n", module.ast));
             }
         }
+        if let Err(err) = backend::write_artifact(path, text.as_bytes()) {
+            fail(format!("Failed to write {}: {}", path.display(), err));
+        }
+    }
+}
+
+fn load_project(build: &BuildArgs) -> (Project, Profile) {
+    let project = match &build.manifest_path {
+        Some(path) => Project::load(path),
+        None => Project::find(Path::new(".")),
+    };
+    let project = project.unwrap_or_else(|err| fail(err));
+
+    let profile = project.profile(profile_name(build)).unwrap_or_else(|err| fail(err));
+    (project, profile)
+}
+
+fn profile_name(build: &BuildArgs) -> &str {
+    match (&build.profile, build.release) {
+        (Some(name), _) => name.as_str(),
+        (None, true) => "release",
+        (None, false) => "debug",
+    }
+}
+
+// -O and --passes win over what the profile says
+fn backend_options(build: &BuildArgs, profile: &Profile) -> Options {
+    Options {
+        opt_level: build.opt_level.unwrap_or(OptLevel::from_profile(profile.opt_level)),
+        passes: build.passes.clone(),
+        target: build.target.clone(),
+        cpu: build.cpu.clone(),
+        features: build.features.clone(),
+    }
+}
+
+fn fail(message: impl std::fmt::Display) -> ! {
+    eprintln!("{}", message);
+    process::exit(1);
+}
+
+// The prelude, the program's own file and every module it imports, all parsed
+fn load_modules(loader: &mut ModuleLoader, input: &Path) -> (Vec<Statement>, Vec<Module>) {
+    let prelude = loader.load_prelude().expect("the prelude should always parse");
+    match loader.load(input) {
+        Ok(modules) => (prelude, modules),
+        Err(err) => {
+            eprintln!("{}", err);
+            std::process::exit(1);
+        }
+    }
+}
+
+fn resolve_methods(ast: Vec<Statement>) -> (Vec<Statement>, MethodTable) {
+    match MethodTable::build(ast) {
+        Ok(resolved) => resolved,
         Err(err) => {
-            eprintln!("Failed to write output file {}: {}", args.output, err);
+            eprintln!("Method resolution failed: {:?}", err);
             std::process::exit(1);
         }
     }
 }
 
-fn lex(source: &str) -> Vec<Token> {
-    println!("Lexing source code...");
-    let mut lexer = Lexer::new(source);
-    let tokens = lexer.tokenize().unwrap();
-    println!("{:?}", tokens);
-    tokens.to_vec()
+// Every module ends up in one program, the names in imported ones prefixed with the module.
+// Also says which variables lambdas capture
+fn resolve_names(
+    prelude: Vec<Statement>,
+    modules: Vec<Module>,
+    loader: &ModuleLoader,
+) -> (Vec<Statement>, HashSet<SymbolId>) {
+    let mut resolver = Resolver::new();
+    match resolver.resolve_program(prelude, modules) {
+        Ok(ast) => (ast, resolver.captured().clone()),
+        Err(err) => {
+            eprintln!("{}:{}", loader.path(err.span.file).display(), err);
+            std::process::exit(1);
+        }
+    }
 }
 
-fn parse(tokens: Vec<Token>) -> Vec<Statement> {
-    println!("Parsing tokens...");
-    let mut parser = SyntaxParser::new(tokens);
-    let ast = parser.parse().unwrap();
-    println!("{ast:#?}");
-    ast
+fn check_types(ast: &mut [Statement], methods: &MethodTable, loader: &ModuleLoader) {
+    let mut checker = TypeChecker::new(methods);
+    if let Err(err) = checker.check_program(ast) {
+        eprintln!("{}:{}", loader.path(err.span.file).display(), err);
+        std::process::exit(1);
+    }
+}
 
+// The whole program as one LLVM module, with debug info when given the source files and
+// the target's data layout
+fn generate_code<'ctx>(
+    context: &'ctx Context,
+    ast: Vec<Statement>,
+    methods: MethodTable,
+    captured: HashSet<SymbolId>,
+    debug_info: Option<(&[PathBuf], usize, DataLayout)>,
+    overflow_checks: bool,
+) -> LlvmModule<'ctx> {
+    let mut codegen = CodegenContext::new(context, "main", methods);
+    codegen.overflow_checks = overflow_checks;
+    codegen.captured = captured;
+    if let Some((files, main_file, layout)) = debug_info {
+        codegen.module.set_data_layout(&layout);
+        codegen.enable_debug_info(files, main_file);
+    }
+    if let Err(err) = codegen.generate_program(&ast) {
+        fail(format!("Code generation failed: {}", err));
+    }
+    codegen.module
 }
 
-fn generate_code(ast: Vec<Statement>) -> Vec<u8> {
-    println!("Generating code from AST...");
-    todo!("Implement code generator");
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_build_writes_files() {
+        let build = Args::try_parse_from(["lumina", "build", "--emit=llvm-ir,exe", "-o", "out"]).unwrap();
+        let Command::Build { output, .. } = build.command else {
+            panic!("expected build, got {:?}", build.command);
+        };
+        assert_eq!(output.emit.len(), 2);
+        assert_eq!(output.output, Some(PathBuf::from("out")));
+
+        assert!(Args::try_parse_from(["lumina", "run", "--emit=llvm-ir"]).is_err());
+        assert!(Args::try_parse_from(["lumina", "run", "-o", "out"]).is_err());
+        assert!(Args::try_parse_from(["lumina", "run", "main.lum", "--release", "--", "--emit=x"]).is_ok());
+    }
 }
@@ -0,0 +1,320 @@
+use crate::lexer::{Lexer, LexerError, Span, Token};
+use crate::parser::enums::Statement;
+use crate::parser::Parser;
+use crate::semantic::PRELUDE;
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug)]
+pub enum ModuleError {
+    Io(PathBuf, std::io::Error),
+    Lex(PathBuf, LexerError),
+    Parse(PathBuf, Span),             // Where the statement that failed starts
+    NotFound(String, PathBuf),        // Module, the file importing it
+    Cycle(Vec<PathBuf>),              // Starts and ends with the same file
+}
+
+impl fmt::Display for ModuleError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ModuleError::Io(path, err) => write!(f, "{}: {}", path.display(), err),
+            ModuleError::Lex(path, err) => write!(f, "{}: {:?}", path.display(), err),
+            ModuleError::Parse(path, span) => write!(
+                f,
+                "{}:{}:{}: failed to parse this statement",
+                path.display(),
+                span.line,
+                span.column
+            ),
+            ModuleError::NotFound(module, importer) => write!(
+                f,
+                "{}: cannot find module '{}', looked next to it and in the search path",
+                importer.display(),
+                module
+            ),
+            ModuleError::Cycle(files) => {
+                let files: Vec<String> = files.iter().map(|file| file.display().to_string()).collect();
+                write!(f, "import cycle: {}", files.join(" -> "))
+            }
+        }
+    }
+}
+
+// One parsed source file. The entry file is the module with an empty name, everything else
+// is named after how it was first imported, unless another file already has that name.
+// Then it's named after its path. Each file says in `imports` what its own names stand for
+pub struct Module {
+    pub name: String,
+    pub file: usize,                      // Index into the loader's files, same as in its spans
+    pub imports: HashMap<String, String>, // What each import in the file was resolved to
+    pub tokens: Vec<(Token, Span)>,       // Only kept around for --emit=tokens
+    pub ast: Vec<Statement>,
+}
+
+// A library the program depends on. `import geometry` is its entry file, `import geometry.shapes`
+// is shapes.lum in one of its source directories
+struct Package {
+    entry: PathBuf,
+    source_dirs: Vec<PathBuf>,
+}
+
+// Finds, reads and parses every file a program is made of. `import util.strings` looks for
+// util/strings.lum next to the importing file first, then in each search path in order,
+// unless `util` is the name of a package
+pub struct ModuleLoader {
+    search_paths: Vec<PathBuf>,
+    packages: HashMap<String, Package>,
+    files: Vec<PathBuf>,
+    modules: Vec<Module>,             // Every module comes after the modules it imports
+    loaded: HashMap<PathBuf, String>, // Canonical path to module name
+    loading: Vec<(PathBuf, PathBuf)>, // Canonical and written path of the imports being followed
+    root: PathBuf,                    // The entry file's directory
+}
+
+impl ModuleLoader {
+    pub fn new(search_paths: Vec<PathBuf>) -> Self {
+        Self {
+            search_paths,
+            packages: HashMap::new(),
+            files: Vec::new(),
+            modules: Vec::new(),
+            loaded: HashMap::new(),
+            loading: Vec::new(),
+            root: PathBuf::new(),
+        }
+    }
+
+    pub fn add_package(&mut self, name: String, entry: PathBuf, source_dirs: Vec<PathBuf>) {
+        self.packages.insert(name, Package { entry, source_dirs });
+    }
+
+    // The file a span's file index stands for
+    pub fn path(&self, file: usize) -> &Path {
+        &self.files[file]
+    }
+
+    // Every file loaded so far, indexed like spans are
+    pub fn files(&self) -> &[PathBuf] {
+        &self.files
+    }
+
+    pub fn load_prelude(&mut self) -> Result<Vec<Statement>, ModuleError> {
+        let (_, _, ast) = self.parse_source(PathBuf::from("<prelude>"), PRELUDE)?;
+        Ok(ast)
+    }
+
+    // The entry file and everything it imports, dependencies first
+    pub fn load(&mut self, root: &Path) -> Result<Vec<Module>, ModuleError> {
+        let canonical = fs::canonicalize(root).map_err(|err| ModuleError::Io(root.to_path_buf(), err))?;
+        self.root = canonical.parent().map(Path::to_path_buf).unwrap_or_default();
+        self.load_module(root.to_path_buf(), String::new())?;
+        Ok(std::mem::take(&mut self.modules))
+    }
+
+    fn load_module(&mut self, path: PathBuf, name: String) -> Result<String, ModuleError> {
+        let canonical = fs::canonicalize(&path).map_err(|err| ModuleError::Io(path.clone(), err))?;
+        if let Some(start) = self.loading.iter().position(|(loading, _)| *loading == canonical) {
+            let mut cycle: Vec<PathBuf> = self.loading[start..].iter().map(|(_, written)| written.clone()).collect();
+            cycle.push(path);
+            return Err(ModuleError::Cycle(cycle));
+        }
+        if let Some(existing) = self.loaded.get(&canonical) {
+            return Ok(existing.clone());
+        }
+        // Taken before its imports are loaded, so none of them can end up with the same name
+        let name = self.namespace(name, &canonical);
+        self.loaded.insert(canonical.clone(), name.clone());
+
+        let source = fs::read_to_string(&path).map_err(|err| ModuleError::Io(path.clone(), err))?;
+        let (file, tokens, ast) = self.parse_source(path.clone(), &source)?;
+
+        self.loading.push((canonical.clone(), path.clone()));
+        let mut imports = HashMap::new();
+        for stmt in ast.iter() {
+            let Statement::Import { module, .. } = stmt.node() else {
+                continue;
+            };
+            if imports.contains_key(module) {
+                continue;
+            }
+            let Some(found) = self.locate(module, &path) else {
+                return Err(ModuleError::NotFound(module.clone(), path));
+            };
+            let loaded_as = self.load_module(found, module.clone())?;
+            imports.insert(module.clone(), loaded_as);
+        }
+        self.loading.pop();
+
+        self.modules.push(Module {
+            name: name.clone(),
+            file,
+            imports,
+            tokens,
+            ast,
+        });
+        Ok(name)
+    }
+
+    // `import util` in two directories can mean two different files. The first one to load
+    // is `util`, the other is named after where it is, like `b.util` for b/util.lum next to
+    // the entry file, or its whole path when it's somewhere else
+    fn namespace(&self, name: String, canonical: &Path) -> String {
+        let taken = |candidate: &str| self.loaded.values().any(|existing| existing == candidate);
+        if name.is_empty() || !taken(&name) {
+            return name;
+        }
+        let path = canonical.with_extension("");
+        if let Ok(relative) = path.strip_prefix(&self.root) {
+            let dotted: Vec<String> = relative
+                .components()
+                .map(|part| part.as_os_str().to_string_lossy().into_owned())
+                .collect();
+            let dotted = dotted.join(".");
+            if !taken(&dotted) {
+                return dotted;
+            }
+        }
+        path.display().to_string()
+    }
+
+    fn locate(&self, module: &str, importer: &Path) -> Option<PathBuf> {
+        let (root, rest) = module.split_once('.').unwrap_or((module, ""));
+        if let Some(package) = self.packages.get(root) {
+            if rest.is_empty() {
+                return Some(package.entry.clone()).filter(|entry| entry.is_file());
+            }
+            let relative = PathBuf::from(format!("{}.lum", rest.replace('.', "/")));
+            return package
+                .source_dirs
+                .iter()
+                .map(|dir| dir.join(&relative))
+                .find(|candidate| candidate.is_file());
+        }
+
+        let relative = PathBuf::from(format!("{}.lum", module.replace('.', "/")));
+        let beside = importer.parent().unwrap_or(Path::new(""));
+        std::iter::once(beside)
+            .chain(self.search_paths.iter().map(PathBuf::as_path))
+            .map(|dir| dir.join(&relative))
+            .find(|candidate| candidate.is_file())
+    }
+
+    fn parse_source(
+        &mut self,
+        path: PathBuf,
+        source: &str,
+    ) -> Result<(usize, Vec<(Token, Span)>, Vec<Statement>), ModuleError> {
+        let file = self.files.len();
+        self.files.push(path.clone());
+
+        let mut lexer = Lexer::new(source).in_file(file);
+        let tokens = match lexer.tokenize() {
+            Ok(tokens) => tokens.to_vec(),
+            Err(err) => return Err(ModuleError::Lex(path, err)),
+        };
+        let spans = lexer.spans().to_vec();
+        let mut parser = Parser::new(tokens.clone(), spans.clone());
+        let ast = parser.parse().map_err(|span| ModuleError::Parse(path, span))?;
+        Ok((file, tokens.into_iter().zip(spans).collect(), ast))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::semantic::Resolver;
+
+    // A directory of .lum files, removed again when the test is done with it
+    struct Sources(PathBuf);
+
+    impl Sources {
+        fn new(test: &str, files: &[(&str, &str)]) -> Self {
+            let dir = std::env::temp_dir().join(format!("lumina-loader-{}-{}", test, std::process::id()));
+            for (name, source) in files {
+                let path = dir.join(name);
+                fs::create_dir_all(path.parent().unwrap()).unwrap();
+                fs::write(path, source).unwrap();
+            }
+            Self(dir)
+        }
+
+        fn load(&self) -> Result<Vec<Module>, ModuleError> {
+            ModuleLoader::new(Vec::new()).load(&self.0.join("main.lum"))
+        }
+    }
+
+    impl Drop for Sources {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    #[test]
+    fn imports_come_first() {
+        let sources = Sources::new(
+            "order",
+            &[
+                ("main.lum", "import util.text\nprint(text.name())\n"),
+                ("util/text.lum", "pub fn name() -> str { return \"text\" }\n"),
+            ],
+        );
+        let modules = sources.load().unwrap();
+        let names: Vec<&str> = modules.iter().map(|module| module.name.as_str()).collect();
+        assert_eq!(names, vec!["util.text", ""]);
+        assert_eq!(modules[1].imports.get("util.text").map(String::as_str), Some("util.text"));
+    }
+
+    #[test]
+    fn broken_imports_are_errors() {
+        let missing = Sources::new("missing", &[("main.lum", "import nowhere\n")]);
+        assert!(matches!(missing.load(), Err(ModuleError::NotFound(module, _)) if module == "nowhere"));
+
+        let cycle = Sources::new("cycle", &[("main.lum", "import a\n"), ("a.lum", "import main\n")]);
+        assert!(matches!(cycle.load(), Err(ModuleError::Cycle(files)) if files.len() == 3));
+    }
+
+    #[test]
+    fn parse_errors_say_where() {
+        let sources = Sources::new("parse", &[("main.lum", "let x = 1\nlet = 2\n")]);
+        match sources.load() {
+            Err(ModuleError::Parse(path, span)) => {
+                assert!(path.ends_with("main.lum"));
+                assert_eq!((span.line, span.column), (2, 1));
+            }
+            other => panic!("expected a parse error, got {:?}", other.map(|_| ())),
+        }
+    }
+
+    #[test]
+    fn same_import_names_can_mean_different_files() {
+        let sources = Sources::new(
+            "namespaces",
+            &[
+                ("main.lum", "import a.first\nimport b.second\n"),
+                ("a/first.lum", "import util\n"),
+                ("a/util.lum", "pub fn name() -> str { return \"a\" }\n"),
+                ("b/second.lum", "import util\n"),
+                ("b/util.lum", "pub fn name() -> str { return \"b\" }\n"),
+            ],
+        );
+        let modules = sources.load().unwrap();
+        let imports = |name: &str| {
+            let module = modules.iter().find(|module| module.name == name).expect("the module is loaded");
+            module.imports.get("util").cloned()
+        };
+        assert_eq!(imports("a.first").as_deref(), Some("util"));
+        assert_eq!(imports("b.second").as_deref(), Some("b.util"));
+
+        let ast = Resolver::new().resolve_program(Vec::new(), modules).unwrap();
+        let functions: Vec<&str> = ast
+            .iter()
+            .filter_map(|stmt| match stmt.node() {
+                Statement::FunctionDefinition { name, .. } => Some(name.as_str()),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(functions, vec!["util::name", "b.util::name"]);
+    }
+}
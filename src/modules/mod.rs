@@ -0,0 +1,3 @@
+mod loader;
+
+pub use loader::{Module, ModuleError, ModuleLoader};
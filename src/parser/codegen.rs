@@ -1,28 +1,156 @@
+use super::debuginfo::DebugInfo;
 use super::enums::*;
+use super::format::format_pieces;
 use crate::lexer::enums::*;
+use inkwell::basic_block::BasicBlock;
+use inkwell::builder::BuilderError;
 use inkwell::context::Context;
-use inkwell::types::{AnyType, BasicType};
-use inkwell::values::{AnyValue, BasicMetadataValueEnum, BasicValue, BasicValueEnum, FunctionValue, PointerValue};
-use inkwell::FloatPredicate;
-use std::collections::HashMap;
+use inkwell::module::Linkage;
+use inkwell::types::{BasicMetadataTypeEnum, BasicType, BasicTypeEnum, FunctionType, StructType};
+use inkwell::values::{AnyValue, BasicMetadataValueEnum, BasicValueEnum, FunctionValue, IntValue, PointerValue};
+use crate::semantic::{intrinsics, mangle_method, MethodTable, SHOW};
+use inkwell::{AddressSpace, FloatPredicate, IntPredicate};
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+// The function holding the program's top level code. It isn't `main` itself, whatever runs
+// the module decides how it gets called
+pub const ENTRY_POINT: &str = "lumina.main";
 
 pub struct CodegenContext<'ctx> {
     pub context: &'ctx Context,
     pub builder: inkwell::builder::Builder<'ctx>,
     pub module: inkwell::module::Module<'ctx>,
-    pub variable_table: HashMap<String, PointerValue<'ctx>>, // Variables by name
+    // Variables by resolved symbol, their stack slot and the type stored in it
+    pub variable_table: HashMap<SymbolId, (PointerValue<'ctx>, BasicTypeEnum<'ctx>)>,
     pub function_table: HashMap<String, FunctionValue<'ctx>>, // Functions by name
+    pub struct_table: HashMap<String, (StructType<'ctx>, Vec<Parameter>)>, // Struct types and their fields
+    pub closure_signatures: HashMap<String, FunctionType<'ctx>>, // Closure types by name, with the function they hold
+    pub list_elements: HashMap<String, BasicTypeEnum<'ctx>>, // List types by name, with what they hold
+    pub methods: MethodTable,
+    pub debug_info: Option<DebugInfo<'ctx>>, // Only with -g
+    pub overflow_checks: bool, // Int arithmetic panics instead of wrapping around, for debug builds
+    // Variables a lambda captures. They live on the heap, so the lambda and the code around
+    // it read and write the same one
+    pub captured: HashSet<SymbolId>,
+    unverified: Vec<(FunctionValue<'ctx>, String)>, // Made while debug info was unfinished
 }
 
 impl<'ctx> CodegenContext<'ctx> {
+    pub fn new(context: &'ctx Context, module_name: &str, methods: MethodTable) -> Self {
+        Self {
+            context,
+            builder: context.create_builder(),
+            module: context.create_module(module_name),
+            variable_table: HashMap::new(),
+            function_table: HashMap::new(),
+            struct_table: HashMap::new(),
+            closure_signatures: HashMap::new(),
+            list_elements: HashMap::new(),
+            methods,
+            debug_info: None,
+            overflow_checks: false,
+            captured: HashSet::new(),
+            unverified: Vec::new(),
+        }
+    }
+
+    // Top level code goes in the entry point, with every struct and function defined before
+    // it runs so they can be used from anywhere in it. Functions are declared before any of
+    // their bodies, so they can call each other whichever order they're written in
+    pub fn generate_program(&mut self, ast: &[Statement]) -> Result<(), CodegenError> {
+        let main = self.module.add_function(ENTRY_POINT, self.context.i32_type().fn_type(&[], false), None);
+        let entry = self.context.append_basic_block(main, "entry");
+        self.builder.position_at_end(entry);
+        self.debug_enter_function(main, false);
+
+        let (definitions, code): (Vec<&Statement>, Vec<&Statement>) = ast.iter().partition(|stmt| {
+            matches!(stmt.node(), Statement::StructDefinition { .. } | Statement::FunctionDefinition { .. })
+        });
+        let (structs, functions): (Vec<&Statement>, Vec<&Statement>) = definitions
+            .into_iter()
+            .partition(|stmt| matches!(stmt.node(), Statement::StructDefinition { .. }));
+        for stmt in structs {
+            stmt.generate_ir(self)?;
+        }
+        self.declare_functions(functions.iter().copied())?;
+        for stmt in functions.into_iter().chain(code) {
+            stmt.generate_ir(self)?;
+        }
+
+        self.builder.build_return(Some(&self.context.i32_type().const_zero()))?;
+        self.debug_leave_function();
+        self.verify_function(main, "the top level code")?;
+        self.finish_debug_info();
+        for (function, name) in std::mem::take(&mut self.unverified) {
+            check_function(function, &name)?;
+        }
+        self.module.verify().map_err(|message| CodegenError::InvalidModule(message.to_string()))
+    }
+
+    // Catches broken IR where it was made, before LLVM trips over it later on. Unfinished
+    // debug info doesn't verify, so with -g functions wait until it's finished
+    pub fn verify_function(&mut self, function: FunctionValue<'ctx>, name: &str) -> Result<(), CodegenError> {
+        if self.debug_info.is_some() {
+            self.unverified.push((function, name.to_string()));
+            return Ok(());
+        }
+        check_function(function, name)
+    }
+
     // Insert variable into the context
-    pub fn insert_variable(&mut self, name: String, ptr: PointerValue<'ctx>) {
-        self.variable_table.insert(name, ptr);
+    pub fn insert_variable(&mut self, symbol: SymbolId, ptr: PointerValue<'ctx>, ty: BasicTypeEnum<'ctx>) {
+        self.variable_table.insert(symbol, (ptr, ty));
     }
 
     // Retrieve variable from the context
-    pub fn get_variable(&self, name: &str) -> Option<PointerValue<'ctx>> {
-        self.variable_table.get(name).cloned()
+    pub fn get_variable(&self, symbol: Option<SymbolId>) -> Option<(PointerValue<'ctx>, BasicTypeEnum<'ctx>)> {
+        self.variable_table.get(&symbol?).cloned()
+    }
+
+    // A new variable holding `value`, with the type checker's type when there is one.
+    // Parameters say which one they are for the debugger
+    pub fn declare_variable(
+        &mut self,
+        symbol: SymbolId,
+        name: &str,
+        value: BasicValueEnum<'ctx>,
+        ty: Option<&Type>,
+        argument: Option<u32>,
+    ) -> Result<PointerValue<'ctx>, CodegenError> {
+        let slot = self.build_variable_slot(symbol, value.get_type(), name)?;
+        self.builder.build_store(slot, value)?;
+        self.debug_declare(name, slot, value.get_type(), ty, argument);
+        self.insert_variable(symbol, slot, value.get_type());
+        Ok(slot)
+    }
+
+    // Where a variable is kept, a stack slot unless a lambda captures it
+    pub fn build_variable_slot(
+        &self,
+        symbol: SymbolId,
+        ty: BasicTypeEnum<'ctx>,
+        name: &str,
+    ) -> Result<PointerValue<'ctx>, CodegenError> {
+        if self.captured.contains(&symbol) {
+            return Ok(self.builder.build_malloc(ty, name)?);
+        }
+        self.build_entry_alloca(ty, name)
+    }
+
+    // Stack slots all go at the top of the function's entry block, where mem2reg can turn
+    // them into registers, and so a loop doesn't grow the stack every time around
+    pub fn build_entry_alloca(&self, ty: BasicTypeEnum<'ctx>, name: &str) -> Result<PointerValue<'ctx>, CodegenError> {
+        let entry = self
+            .current_function()?
+            .get_first_basic_block()
+            .ok_or_else(|| CodegenError::BuildError("Function has no entry block".into()))?;
+        let builder = self.context.create_builder();
+        match entry.get_first_instruction() {
+            Some(first) => builder.position_before(&first),
+            None => builder.position_at_end(entry),
+        }
+        Ok(builder.build_alloca(ty, name)?)
     }
 
     // Insert function into the context
@@ -30,10 +158,674 @@ impl<'ctx> CodegenContext<'ctx> {
         self.function_table.insert(name, func);
     }
 
+    // The prototypes of the function definitions among `stmts`, their bodies come later
+    pub fn declare_functions<'a>(
+        &mut self,
+        stmts: impl IntoIterator<Item = &'a Statement>,
+    ) -> Result<(), CodegenError> {
+        for stmt in stmts {
+            if let Statement::FunctionDefinition { name, parameters, return_type, .. } = stmt.node() {
+                if self.get_function(name).is_none() {
+                    self.declare_function(name, parameters, return_type.as_ref())?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn declare_function(
+        &mut self,
+        name: &str,
+        parameters: &[Parameter],
+        return_type: Option<&Type>,
+    ) -> Result<FunctionValue<'ctx>, CodegenError> {
+        let param_types = parameters
+            .iter()
+            .map(|param| self.llvm_type(param.ty.as_ref()).map(BasicMetadataTypeEnum::from))
+            .collect::<Result<Vec<_>, _>>()?;
+        let return_type = self.llvm_type(Some(return_type.unwrap_or(&Type::Null)))?;
+        let function = self.module.add_function(name, return_type.fn_type(&param_types, false), None);
+        self.insert_function(name.to_string(), function);
+        Ok(function)
+    }
+
+    // The function the builder is adding code to
+    pub fn current_function(&self) -> Result<FunctionValue<'ctx>, CodegenError> {
+        self.current_block()?
+            .get_parent()
+            .ok_or_else(|| CodegenError::BuildError("Code outside of a function".into()))
+    }
+
+    pub fn current_block(&self) -> Result<BasicBlock<'ctx>, CodegenError> {
+        self.builder
+            .get_insert_block()
+            .ok_or_else(|| CodegenError::BuildError("Code outside of a function".into()))
+    }
+
     // Retrieve function from the context
     pub fn get_function(&self, name: &str) -> Option<FunctionValue<'ctx>> {
         self.function_table.get(name).cloned()
     }
+
+    // Insert struct type into the context
+    pub fn insert_struct(&mut self, name: String, struct_type: StructType<'ctx>, fields: Vec<Parameter>) {
+        self.struct_table.insert(name, (struct_type, fields));
+    }
+
+    // Retrieve struct type from the context
+    pub fn get_struct(&self, name: &str) -> Option<(StructType<'ctx>, Vec<Parameter>)> {
+        self.struct_table.get(name).cloned()
+    }
+
+    // The name impl blocks use for the type of a value
+    pub fn type_name_of(&self, value: &BasicValueEnum<'ctx>) -> Option<String> {
+        match value {
+            BasicValueEnum::FloatValue(_) => Some("float".into()),
+            BasicValueEnum::IntValue(v) if v.get_type().get_bit_width() == 1 => Some("bool".into()),
+            BasicValueEnum::IntValue(_) => Some("int".into()),
+            BasicValueEnum::ArrayValue(_) | BasicValueEnum::PointerValue(_) => Some("str".into()),
+            BasicValueEnum::StructValue(v) => v
+                .get_type()
+                .get_name()
+                .and_then(|name| name.to_str().ok())
+                .map(String::from),
+            _ => None,
+        }
+    }
+
+    // LLVM type for an annotated value, anything unannotated is a number
+    pub fn llvm_type(&mut self, ty: Option<&Type>) -> Result<BasicTypeEnum<'ctx>, CodegenError> {
+        let ptr_type = self.context.ptr_type(AddressSpace::default());
+        match ty {
+            None | Some(Type::Float) | Some(Type::Var(_)) => Ok(self.context.f64_type().into()),
+            Some(Type::Int) => Ok(self.context.i64_type().into()),
+            Some(Type::Integer(_, bits)) => Ok(self.int_type(*bits).into()),
+            Some(Type::Bool) => Ok(self.context.bool_type().into()),
+            Some(Type::Str) | Some(Type::Null) => Ok(ptr_type.into()),
+            Some(Type::Function(parameters, return_type)) => {
+                let param_types = parameters
+                    .iter()
+                    .map(|param| self.llvm_type(Some(param)))
+                    .collect::<Result<Vec<_>, _>>()?;
+                let return_type = self.llvm_type(Some(return_type))?;
+                let fn_type = self.closure_fn_type(&param_types, return_type);
+                Ok(self.closure_type(fn_type).into())
+            }
+            Some(Type::List(element)) => {
+                let element_type = self.llvm_type(Some(element))?;
+                Ok(self.list_type(element_type).into())
+            }
+            // Monomorphization leaves only plain struct names behind
+            Some(Type::Named(name, args)) if args.is_empty() => self
+                .get_struct(name)
+                .map(|(struct_type, _)| struct_type.into())
+                .ok_or_else(|| CodegenError::BuildError(format!("Unknown type: {}", name))),
+            Some(Type::Dyn(trait_name)) => Ok(self.dyn_type(trait_name).into()),
+            Some(ty @ (Type::Named(..) | Type::Param(_))) => Err(CodegenError::BuildError(format!(
+                "Generic type {} should have been monomorphized",
+                ty
+            ))),
+        }
+    }
+
+    // The arguments of a call, each converted to the type of the parameter it's passed as.
+    // Anything past the parameters, like a variadic C function's, is passed as it is
+    pub fn generate_arguments(
+        &mut self,
+        args: &[Expression],
+        param_types: &[BasicTypeEnum<'ctx>],
+    ) -> Result<Vec<BasicMetadataValueEnum<'ctx>>, CodegenError> {
+        let mut values = Vec::new();
+        for (i, arg) in args.iter().enumerate() {
+            let value = arg.generate_ir(self)?;
+            let value = match param_types.get(i) {
+                Some(param_type) => self.coerce(value, *param_type)?,
+                None => value,
+            };
+            values.push(value.into());
+        }
+        Ok(values)
+    }
+
+    // Converts a value to the type it's being stored or passed as, numbers convert
+    // between int and float, anything else has to match already
+    pub fn coerce(
+        &self,
+        value: BasicValueEnum<'ctx>,
+        target: BasicTypeEnum<'ctx>,
+    ) -> Result<BasicValueEnum<'ctx>, CodegenError> {
+        if value.get_type() == target {
+            return Ok(value);
+        }
+
+        match (value, target) {
+            (BasicValueEnum::FloatValue(v), BasicTypeEnum::IntType(t)) => {
+                Ok(self.builder.build_float_to_signed_int(v, t, "ftoi")?.into())
+            }
+            (BasicValueEnum::IntValue(v), BasicTypeEnum::FloatType(t)) => {
+                Ok(self.builder.build_signed_int_to_float(v, t, "itof")?.into())
+            }
+            _ => Err(CodegenError::BuildError(format!(
+                "Expected a value of type {} but found {}",
+                target.print_to_string(),
+                value.get_type().print_to_string()
+            ))),
+        }
+    }
+
+    // Conditions are bools, numbers count as true when they aren't zero
+    pub fn build_condition(&self, value: BasicValueEnum<'ctx>) -> Result<IntValue<'ctx>, CodegenError> {
+        match value {
+            BasicValueEnum::IntValue(v) if v.get_type().get_bit_width() == 1 => Ok(v),
+            BasicValueEnum::IntValue(v) => {
+                Ok(self.builder.build_int_compare(IntPredicate::NE, v, v.get_type().const_zero(), "cond")?)
+            }
+            BasicValueEnum::FloatValue(v) => {
+                Ok(self.builder.build_float_compare(FloatPredicate::ONE, v, v.get_type().const_zero(), "cond")?)
+            }
+            _ => Err(CodegenError::BuildError("Condition is not a bool".into())),
+        }
+    }
+
+    // Two ints stay ints, anything else with a float in it is done in floats
+    pub fn build_math(
+        &mut self,
+        op: &MathOperator,
+        lhs: BasicValueEnum<'ctx>,
+        rhs: BasicValueEnum<'ctx>,
+        signed: bool,
+    ) -> Result<BasicValueEnum<'ctx>, CodegenError> {
+        if let (BasicValueEnum::IntValue(l), BasicValueEnum::IntValue(r)) = (lhs, rhs) {
+            return Ok(self.build_int_math(op, l, r, signed)?.into());
+        }
+
+        let float_type = self.context.f64_type().into();
+        let l = self.coerce(lhs, float_type)?.into_float_value();
+        let r = self.coerce(rhs, float_type)?.into_float_value();
+        let result = match op {
+            MathOperator::Add => self.builder.build_float_add(l, r, "tmpadd")?,
+            MathOperator::Subtract => self.builder.build_float_sub(l, r, "tmpsub")?,
+            MathOperator::Multiply => self.builder.build_float_mul(l, r, "tmpmul")?,
+            MathOperator::Divide => self.builder.build_float_div(l, r, "tmpdiv")?,
+            MathOperator::Modulus => self.builder.build_float_rem(l, r, "tmprem")?,
+        };
+        Ok(result.into())
+    }
+
+    // Numbers compare like build_math does them, bools as ints and strings by their contents
+    pub fn build_comparison(
+        &self,
+        op: &ComparisonOperator,
+        lhs: BasicValueEnum<'ctx>,
+        rhs: BasicValueEnum<'ctx>,
+        signed: bool,
+    ) -> Result<BasicValueEnum<'ctx>, CodegenError> {
+        let int_predicate = match (op, signed) {
+            (ComparisonOperator::Equals, _) => IntPredicate::EQ,
+            (ComparisonOperator::NotEquals, _) => IntPredicate::NE,
+            (ComparisonOperator::GreaterThan, true) => IntPredicate::SGT,
+            (ComparisonOperator::LessThan, true) => IntPredicate::SLT,
+            (ComparisonOperator::GreaterThanEq, true) => IntPredicate::SGE,
+            (ComparisonOperator::LessThanEq, true) => IntPredicate::SLE,
+            (ComparisonOperator::GreaterThan, false) => IntPredicate::UGT,
+            (ComparisonOperator::LessThan, false) => IntPredicate::ULT,
+            (ComparisonOperator::GreaterThanEq, false) => IntPredicate::UGE,
+            (ComparisonOperator::LessThanEq, false) => IntPredicate::ULE,
+        };
+
+        match (lhs, rhs) {
+            (BasicValueEnum::IntValue(l), BasicValueEnum::IntValue(r)) => {
+                Ok(self.builder.build_int_compare(int_predicate, l, r, "cmp")?.into())
+            }
+            (BasicValueEnum::PointerValue(l), BasicValueEnum::PointerValue(r)) => {
+                // null isn't a string, so comparing with it only checks the pointer
+                if l.is_null() || r.is_null() {
+                    let i64_type = self.context.i64_type();
+                    let l = self.builder.build_ptr_to_int(l, i64_type, "ptr")?;
+                    let r = self.builder.build_ptr_to_int(r, i64_type, "ptr")?;
+                    return Ok(self.builder.build_int_compare(int_predicate, l, r, "cmp")?.into());
+                }
+                let strcmp = self.libc_function("strcmp", &[self.ptr_type().into(), self.ptr_type().into()], false);
+                let order = self
+                    .builder
+                    .build_call(strcmp, &[l.into(), r.into()], "strcmp")?
+                    .try_as_basic_value()
+                    .left()
+                    .ok_or_else(|| CodegenError::BuildError("strcmp returned nothing".into()))?
+                    .into_int_value();
+                let zero = self.context.i32_type().const_zero();
+                Ok(self.builder.build_int_compare(int_predicate, order, zero, "cmp")?.into())
+            }
+            (BasicValueEnum::IntValue(_) | BasicValueEnum::FloatValue(_), BasicValueEnum::IntValue(_) | BasicValueEnum::FloatValue(_)) => {
+                let float_type = self.context.f64_type().into();
+                let l = self.coerce(lhs, float_type)?.into_float_value();
+                let r = self.coerce(rhs, float_type)?.into_float_value();
+                let float_predicate = match op {
+                    ComparisonOperator::Equals => FloatPredicate::OEQ,
+                    ComparisonOperator::NotEquals => FloatPredicate::ONE,
+                    ComparisonOperator::GreaterThan => FloatPredicate::OGT,
+                    ComparisonOperator::LessThan => FloatPredicate::OLT,
+                    ComparisonOperator::GreaterThanEq => FloatPredicate::OGE,
+                    ComparisonOperator::LessThanEq => FloatPredicate::OLE,
+                };
+                Ok(self.builder.build_float_compare(float_predicate, l, r, "cmp")?.into())
+            }
+            _ => Err(CodegenError::BuildError(format!(
+                "Cannot compare {} with {}",
+                lhs.get_type().print_to_string(),
+                rhs.get_type().print_to_string()
+            ))),
+        }
+    }
+
+    // Where an assignment target is stored and what it holds, a variable or a field of one
+    pub fn address_of(
+        &mut self,
+        target: &Expression,
+    ) -> Result<(PointerValue<'ctx>, BasicTypeEnum<'ctx>), CodegenError> {
+        match target {
+            Expression::Identifier(name, symbol) => self
+                .get_variable(*symbol)
+                .ok_or_else(|| CodegenError::BuildError(format!("Cannot assign to '{}'", name))),
+            Expression::FieldAccess(object, field) => {
+                let (ptr, object_type) = self.address_of(object)?;
+                let BasicTypeEnum::StructType(struct_type) = object_type else {
+                    return Err(CodegenError::BuildError(format!("Cannot assign to field '{}' of this value", field)));
+                };
+                let type_name = struct_type.get_name().and_then(|name| name.to_str().ok()).unwrap_or_default();
+                let (_, fields) = self
+                    .get_struct(type_name)
+                    .ok_or_else(|| CodegenError::BuildError(format!("Cannot assign to field '{}' of this value", field)))?;
+                let index = fields.iter().position(|f| &f.name == field).ok_or_else(|| {
+                    CodegenError::BuildError(format!("'{}' has no field '{}'", type_name, field))
+                })? as u32;
+                let field_ptr = self.builder.build_struct_gep(struct_type, ptr, index, field)?;
+                let field_type = struct_type.get_field_type_at_index(index).unwrap();
+                Ok((field_ptr, field_type))
+            }
+            // List elements live behind the list's data pointer, so any list value will do
+            Expression::Index(list, index) => {
+                let BasicValueEnum::StructValue(list) = list.generate_ir(self)? else {
+                    return Err(CodegenError::BuildError("Only lists can be indexed".into()));
+                };
+                let element_type = self
+                    .list_element(list.get_type())
+                    .ok_or_else(|| CodegenError::BuildError("Only lists can be indexed".into()))?;
+                let index = index.generate_ir(self)?;
+                let index = self.coerce(index, self.context.i64_type().into())?.into_int_value();
+                let data = self.builder.build_extract_value(list, 1, "listdata")?.into_pointer_value();
+                let slot = unsafe { self.builder.build_gep(element_type, data, &[index], "element")? };
+                Ok((slot, element_type))
+            }
+            _ => Err(CodegenError::BuildError("Cannot assign to this expression".into())),
+        }
+    }
+
+    // Adds `block` to the function being built and continues there
+    fn continue_in(&self, block: BasicBlock<'ctx>) -> Result<(), CodegenError> {
+        if self.builder.get_insert_block().map_or(false, |current| current.get_terminator().is_none()) {
+            self.builder.build_unconditional_branch(block)?;
+        }
+        self.builder.position_at_end(block);
+        Ok(())
+    }
+
+    // Gives back what the last statement evaluates to when it's an expression, that's the
+    // value of the block
+    fn generate_block(&mut self, stmts: &[Statement]) -> Result<Option<BasicValueEnum<'ctx>>, CodegenError> {
+        self.declare_functions(stmts)?;
+        self.debug_enter_block();
+        let mut value = None;
+        let result = stmts.iter().try_for_each(|stmt| {
+            let result = stmt.generate_ir(self)?;
+            value = matches!(stmt.node(), Statement::Expression(_)).then_some(result);
+            Ok(())
+        });
+        self.debug_leave_block();
+        result.map(|_| value)
+    }
+
+    // Notes where a branch of an if ends and what it evaluated to, unless it already jumped
+    // somewhere else. A branch that returned ends up in a block nothing jumps to, that one
+    // is closed off so it doesn't look like it reaches the end of the if
+    fn record_branch(
+        &self,
+        incoming: &mut Vec<Option<(BasicValueEnum<'ctx>, BasicBlock<'ctx>)>>,
+        value: Option<BasicValueEnum<'ctx>>,
+    ) -> Result<(), CodegenError> {
+        let Some(block) = self.builder.get_insert_block() else {
+            return Ok(());
+        };
+        if block.get_terminator().is_some() {
+            return Ok(());
+        }
+        if is_unreachable(block) {
+            self.builder.build_unreachable()?;
+            return Ok(());
+        }
+        incoming.push(value.map(|value| (value, block)));
+        Ok(())
+    }
+
+    // Falling off the end of a body returns the value of its last expression. Functions
+    // that return null give back a zero, and a body that already returned just ends
+    fn build_fall_through(
+        &mut self,
+        value: Option<BasicValueEnum<'ctx>>,
+        returns: &Type,
+        return_type: BasicTypeEnum<'ctx>,
+    ) -> Result<(), CodegenError> {
+        let Some(block) = self.builder.get_insert_block() else {
+            return Ok(());
+        };
+        if block.get_terminator().is_some() {
+            return Ok(());
+        }
+        if is_unreachable(block) {
+            self.builder.build_unreachable()?;
+            return Ok(());
+        }
+        let value = match value {
+            Some(value) if *returns != Type::Null => self.coerce(value, return_type)?,
+            _ => return_type.const_zero(),
+        };
+        self.builder.build_return(Some(&value))?;
+        Ok(())
+    }
+
+    // Closures are a { function pointer, environment pointer } pair. Each signature gets
+    // its own named struct so calling one knows what the function behind it looks like
+    pub fn closure_type(&mut self, fn_type: FunctionType<'ctx>) -> StructType<'ctx> {
+        let name = format!("closure {}", fn_type.print_to_string().to_string());
+        if let Some(existing) = self.context.get_struct_type(&name) {
+            return existing;
+        }
+
+        let ptr_type = self.context.ptr_type(AddressSpace::default());
+        let struct_type = self.context.opaque_struct_type(&name);
+        struct_type.set_body(&[ptr_type.into(), ptr_type.into()], false);
+        self.closure_signatures.insert(name, fn_type);
+        struct_type
+    }
+
+    // Lists are a { length, data pointer } pair, named after the element type like closures are
+    pub fn list_type(&mut self, element_type: BasicTypeEnum<'ctx>) -> StructType<'ctx> {
+        let name = format!("list {}", element_type.print_to_string().to_string());
+        if let Some(existing) = self.context.get_struct_type(&name) {
+            return existing;
+        }
+
+        let struct_type = self.context.opaque_struct_type(&name);
+        struct_type.set_body(
+            &[self.context.i64_type().into(), self.context.ptr_type(AddressSpace::default()).into()],
+            false,
+        );
+        self.list_elements.insert(name, element_type);
+        struct_type
+    }
+
+    // The element type a list struct holds
+    pub fn list_element(&self, list_type: StructType<'ctx>) -> Option<BasicTypeEnum<'ctx>> {
+        let name = list_type.get_name()?.to_str().ok()?;
+        self.list_elements.get(name).copied()
+    }
+
+    // The function type a closure struct holds
+    pub fn closure_signature(&self, closure_type: StructType<'ctx>) -> Option<FunctionType<'ctx>> {
+        let name = closure_type.get_name()?.to_str().ok()?;
+        self.closure_signatures.get(name).copied()
+    }
+
+    // The function behind a closure takes the environment first, then the actual arguments
+    pub fn closure_fn_type(
+        &self,
+        param_types: &[BasicTypeEnum<'ctx>],
+        return_type: BasicTypeEnum<'ctx>,
+    ) -> FunctionType<'ctx> {
+        let mut params: Vec<BasicMetadataTypeEnum> = vec![self.context.ptr_type(AddressSpace::default()).into()];
+        params.extend(param_types.iter().map(|ty| BasicMetadataTypeEnum::from(*ty)));
+        return_type.fn_type(&params, false)
+    }
+
+    pub fn build_closure(
+        &mut self,
+        function: FunctionValue<'ctx>,
+        env: PointerValue<'ctx>,
+    ) -> Result<BasicValueEnum<'ctx>, CodegenError> {
+        let closure = self.closure_type(function.get_type()).get_undef();
+        let fn_ptr = function.as_global_value().as_pointer_value();
+        let closure = self.builder.build_insert_value(closure, fn_ptr, 0, "closure")?;
+        let closure = self.builder.build_insert_value(closure, env, 1, "closure")?;
+        Ok(closure.into_struct_value().into())
+    }
+
+    // Named functions don't take an environment, so using one as a value goes through
+    // a small wrapper that ignores it
+    pub fn closure_for_function(
+        &mut self,
+        name: &str,
+        function: FunctionValue<'ctx>,
+    ) -> Result<BasicValueEnum<'ctx>, CodegenError> {
+        let wrapper_name = format!("{}.closure", name);
+        let wrapper = match self.module.get_function(&wrapper_name) {
+            Some(wrapper) => wrapper,
+            None => {
+                let param_types = function.get_type().get_param_types();
+                let return_type = function
+                    .get_type()
+                    .get_return_type()
+                    .unwrap_or_else(|| self.context.f64_type().into());
+                let wrapper = self.module.add_function(
+                    &wrapper_name,
+                    self.closure_fn_type(&param_types, return_type),
+                    Some(Linkage::Internal),
+                );
+                let previous_block = self.builder.get_insert_block();
+
+                let entry = self.context.append_basic_block(wrapper, "entry");
+                self.builder.position_at_end(entry);
+                self.debug_enter_function(wrapper, true);
+                let args: Vec<BasicMetadataValueEnum> = wrapper.get_param_iter().skip(1).map(|p| p.into()).collect();
+                let call = self.builder.build_call(function, &args, "calltmp")?;
+                match call.try_as_basic_value().left() {
+                    Some(value) => self.builder.build_return(Some(&value))?,
+                    None => self.builder.build_return(Some(&return_type.const_zero()))?,
+                };
+                self.debug_leave_function();
+
+                if let Some(block) = previous_block {
+                    self.builder.position_at_end(block);
+                }
+                self.verify_function(wrapper, name)?;
+                wrapper
+            }
+        };
+
+        let null_env = self.context.ptr_type(AddressSpace::default()).const_null();
+        self.build_closure(wrapper, null_env)
+    }
+
+    // dyn values are a { data pointer, vtable pointer } pair, one named struct per trait
+    pub fn dyn_type(&self, trait_name: &str) -> StructType<'ctx> {
+        let name = format!("dyn {}", trait_name);
+        if let Some(existing) = self.context.get_struct_type(&name) {
+            return existing;
+        }
+
+        let ptr_type = self.context.ptr_type(AddressSpace::default());
+        let struct_type = self.context.opaque_struct_type(&name);
+        struct_type.set_body(&[ptr_type.into(), ptr_type.into()], false);
+        struct_type
+    }
+
+    // Boxes a value and pairs it with the vtable for its type's impl of the trait
+    pub fn build_dyn(
+        &mut self,
+        value: BasicValueEnum<'ctx>,
+        trait_name: &str,
+    ) -> Result<BasicValueEnum<'ctx>, CodegenError> {
+        let dyn_type = self.dyn_type(trait_name);
+        if value.get_type() == dyn_type.into() {
+            return Ok(value);
+        }
+
+        let type_name = self
+            .type_name_of(&value)
+            .ok_or_else(|| CodegenError::BuildError(format!("Cannot turn this value into dyn {}", trait_name)))?;
+        let vtable = self.vtable(&type_name, value.get_type(), trait_name)?;
+
+        let data = self.builder.build_malloc(value.get_type(), "dyndata")?;
+        self.builder.build_store(data, value)?;
+
+        let instance = self.builder.build_insert_value(dyn_type.get_undef(), data, 0, "dyn")?;
+        let instance = self.builder.build_insert_value(instance, vtable, 1, "dyn")?;
+        Ok(instance.into_struct_value().into())
+    }
+
+    // A constant array of the type's methods in the order the trait declares them
+    fn vtable(
+        &mut self,
+        type_name: &str,
+        self_type: BasicTypeEnum<'ctx>,
+        trait_name: &str,
+    ) -> Result<PointerValue<'ctx>, CodegenError> {
+        let name = format!("vtable {} as {}", type_name, trait_name);
+        if let Some(existing) = self.module.get_global(&name) {
+            return Ok(existing.as_pointer_value());
+        }
+
+        let methods = self
+            .methods
+            .trait_methods(trait_name)
+            .ok_or_else(|| CodegenError::BuildError(format!("Unknown trait: {}", trait_name)))?
+            .to_vec();
+        let mut entries = Vec::new();
+        for method in &methods {
+            let mangled = mangle_method(type_name, &method.name);
+            let function = match self.get_function(&mangled) {
+                Some(function) => function,
+                // Types without a Show impl of their own still have one
+                None if trait_name == SHOW => self.show_function(Some(&Type::from_name(type_name)), self_type)?,
+                None => return Err(CodegenError::BuildError(format!("Undefined function: {}", mangled))),
+            };
+            let shim = self.dyn_shim(function, self_type)?;
+            entries.push(shim.as_global_value().as_pointer_value());
+        }
+
+        let ptr_type = self.context.ptr_type(AddressSpace::default());
+        let table = ptr_type.const_array(&entries);
+        let global = self.module.add_global(table.get_type(), None, &name);
+        global.set_initializer(&table);
+        global.set_constant(true);
+        Ok(global.as_pointer_value())
+    }
+
+    // Methods take `self` by value, but a dyn only has a pointer to it, so the vtable
+    // points at a wrapper that loads it first
+    fn dyn_shim(
+        &mut self,
+        function: FunctionValue<'ctx>,
+        self_type: BasicTypeEnum<'ctx>,
+    ) -> Result<FunctionValue<'ctx>, CodegenError> {
+        let name = format!("{} dyn", function.get_name().to_str().unwrap_or_default());
+        if let Some(existing) = self.module.get_function(&name) {
+            return Ok(existing);
+        }
+
+        let ptr_type = self.context.ptr_type(AddressSpace::default());
+        let mut param_types: Vec<BasicMetadataTypeEnum> = vec![ptr_type.into()];
+        param_types.extend(
+            function
+                .get_type()
+                .get_param_types()
+                .into_iter()
+                .skip(1)
+                .map(BasicMetadataTypeEnum::from),
+        );
+        let return_type = function
+            .get_type()
+            .get_return_type()
+            .unwrap_or_else(|| ptr_type.into());
+        let shim_type = return_type.fn_type(&param_types, false);
+        let shim = self.module.add_function(&name, shim_type, Some(Linkage::Internal));
+        let previous_block = self.builder.get_insert_block();
+
+        let entry = self.context.append_basic_block(shim, "entry");
+        self.builder.position_at_end(entry);
+        self.debug_enter_function(shim, true);
+        let data = shim.get_nth_param(0).unwrap().into_pointer_value();
+        let receiver = self.builder.build_load(self_type, data, "self")?;
+        let mut args: Vec<BasicMetadataValueEnum> = vec![receiver.into()];
+        args.extend(shim.get_param_iter().skip(1).map(BasicMetadataValueEnum::from));
+        let call = self.builder.build_call(function, &args, "calltmp")?;
+        match call.try_as_basic_value().left() {
+            Some(value) => self.builder.build_return(Some(&value))?,
+            None => self.builder.build_return(Some(&return_type.const_zero()))?,
+        };
+        self.debug_leave_function();
+
+        if let Some(block) = previous_block {
+            self.builder.position_at_end(block);
+        }
+        self.verify_function(shim, &name)?;
+        Ok(shim)
+    }
+
+    // Looks the method up in the dyn value's vtable and calls it with the boxed value as `self`
+    pub fn build_dyn_call(
+        &mut self,
+        receiver: BasicValueEnum<'ctx>,
+        trait_name: &str,
+        method: &str,
+        args: Vec<BasicValueEnum<'ctx>>,
+    ) -> Result<BasicValueEnum<'ctx>, CodegenError> {
+        let methods = self
+            .methods
+            .trait_methods(trait_name)
+            .ok_or_else(|| CodegenError::BuildError(format!("Unknown trait: {}", trait_name)))?
+            .to_vec();
+        let index = methods
+            .iter()
+            .position(|m| m.name == method)
+            .ok_or_else(|| CodegenError::BuildError(format!("No method '{}' in trait {}", method, trait_name)))?;
+        let signature = &methods[index];
+
+        let ptr_type = self.context.ptr_type(AddressSpace::default());
+        let mut param_types: Vec<BasicMetadataTypeEnum> = vec![ptr_type.into()];
+        for param in signature.parameters.iter().skip(1) {
+            param_types.push(self.llvm_type(param.ty.as_ref())?.into());
+        }
+        let return_type = self.llvm_type(Some(signature.return_type.as_ref().unwrap_or(&Type::Null)))?;
+        let fn_type = return_type.fn_type(&param_types, false);
+
+        let BasicValueEnum::StructValue(receiver) = receiver else {
+            return Err(CodegenError::BuildError(format!("Cannot call '{}' on this value", method)));
+        };
+        let data = self.builder.build_extract_value(receiver, 0, "dyndata")?;
+        let vtable = self.builder.build_extract_value(receiver, 1, "vtable")?.into_pointer_value();
+        let index = self.context.i64_type().const_int(index as u64, false);
+        let slot = unsafe { self.builder.build_gep(ptr_type, vtable, &[index], "method")? };
+        let fn_ptr = self.builder.build_load(ptr_type, slot, method)?.into_pointer_value();
+
+        let mut arg_values: Vec<BasicMetadataValueEnum> = vec![data.into()];
+        for (arg, param_type) in args.into_iter().zip(fn_type.get_param_types().into_iter().skip(1)) {
+            arg_values.push(self.coerce(arg, param_type)?.into());
+        }
+        let call = self.builder.build_indirect_call(fn_type, fn_ptr, &arg_values, "calltmp")?;
+        call.try_as_basic_value()
+            .left()
+            .ok_or_else(|| CodegenError::BuildError(format!("Method '{}' does not return a value.", method)))
+    }
+}
+
+// Declarations all get a symbol from the resolver before codegen runs
+fn resolved(symbol: Option<SymbolId>, name: &str) -> Result<SymbolId, CodegenError> {
+    symbol.ok_or_else(|| CodegenError::BuildError(format!("'{}' was never resolved", name)))
+}
+
+// Everything but the unsigned ints, floats included, is done as signed
+pub(super) fn is_signed(ty: Option<&Type>) -> bool {
+    ty.and_then(Type::integer).map_or(true, |(signed, _)| signed)
 }
 
 pub trait Codegen<'ctx> {
@@ -42,29 +834,134 @@ pub trait Codegen<'ctx> {
 
 #[derive(Debug)]
 pub enum CodegenError {
-    BuildError(String),
+    BuildError(String),              // Something in the program we can't generate code for
+    Builder(BuilderError),           // LLVM wouldn't build an instruction
+    InvalidFunction(String, String), // The function, its IR
+    InvalidModule(String),
+}
+
+impl fmt::Display for CodegenError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CodegenError::BuildError(message) => write!(f, "{}", message),
+            CodegenError::Builder(err) => write!(f, "LLVM couldn't build an instruction: {}", err),
+            CodegenError::InvalidFunction(name, message) => {
+                write!(f, "generated invalid code for {}:\n{}", name, message.trim())
+            }
+            CodegenError::InvalidModule(message) => write!(f, "generated an invalid module: {}", message.trim()),
+        }
+    }
+}
+
+impl From<BuilderError> for CodegenError {
+    fn from(err: BuilderError) -> Self {
+        CodegenError::Builder(err)
+    }
 }
 
 impl<'ctx> Codegen<'ctx> for Statement {
     fn generate_ir(&self, context: &mut CodegenContext<'ctx>) -> Result<BasicValueEnum<'ctx>, CodegenError> {
         match self {
-            // ignore for now i fail
-            // too tored
+            Statement::Expression(expr) => expr.generate_ir(context),
+            Statement::Block(stmts) => match context.generate_block(stmts)? {
+                Some(value) => Ok(value),
+                None => LiteralValue::Null.generate_ir(context),
+            },
+            Statement::Comment(_) => LiteralValue::Null.generate_ir(context),
             Statement::Return(value) => {
-                let val = return context.context;
-                let val = context.builder.build_return(value).unwrap();
-                match *value {
-                    Some(value) => {
-                        
-                        Ok(val.as_any_value_enum().into())
-                        
-                    },
-                    None => Err(CodegenError::BuildError("Fail".into()))
+                let function = context.current_function()?;
+                let return_type = function
+                    .get_type()
+                    .get_return_type()
+                    .ok_or_else(|| CodegenError::BuildError("Function has no return type".into()))?;
+                let value = match value.as_ref() {
+                    Some(expr) => {
+                        let value = expr.generate_ir(context)?;
+                        context.coerce(value, return_type)?
+                    }
+                    None => return_type.const_zero(),
+                };
+                context.builder.build_return(Some(&value))?;
+
+                // Anything after the return still needs a block to go in, nothing jumps to it
+                let after = context.context.append_basic_block(function, "afterreturn");
+                context.builder.position_at_end(after);
+                LiteralValue::Null.generate_ir(context)
+            }
+            Statement::FunctionDefinition { name, parameters, return_type, body, .. } => {
+                // Usually declared already, along with everything else in its block
+                let function = match context.get_function(name) {
+                    Some(function) => function,
+                    None => context.declare_function(name, parameters, return_type.as_ref())?,
+                };
+                let returns = return_type.as_ref().unwrap_or(&Type::Null);
+                let return_type = context.llvm_type(Some(returns))?;
+
+                let previous_block = context.builder.get_insert_block();
+                // Function bodies only see their own parameters and locals
+                let outer_variables = std::mem::take(&mut context.variable_table);
+
+                let entry = context.context.append_basic_block(function, "entry");
+                context.builder.position_at_end(entry);
+                context.debug_enter_function(function, false);
+                let result = parameters.iter().enumerate().try_for_each(|(i, param)| {
+                    let value = function.get_nth_param(i as u32).unwrap();
+                    let symbol = resolved(param.symbol, &param.name)?;
+                    context.declare_variable(symbol, &param.name, value, param.ty.as_ref(), Some(i as u32 + 1))?;
+                    Ok(())
+                });
+                let result = result.and_then(|_| context.generate_block(body));
+                if let Ok(value) = &result {
+                    context.build_fall_through(*value, returns, return_type)?;
                 }
+                context.debug_leave_function();
 
-            },
-            _ => todo!("I forgo")
-        } 
+                context.variable_table = outer_variables;
+                if let Some(block) = previous_block {
+                    context.builder.position_at_end(block);
+                }
+                result?;
+                context.verify_function(function, name)?;
+                LiteralValue::Null.generate_ir(context)
+            }
+            Statement::StructDefinition { name, fields, .. } => {
+                let struct_type = context.context.opaque_struct_type(name);
+                let field_types = fields
+                    .iter()
+                    .map(|field| context.llvm_type(field.ty.as_ref()))
+                    .collect::<Result<Vec<_>, _>>()?;
+                struct_type.set_body(&field_types, false);
+                context.insert_struct(name.clone(), struct_type, fields.clone());
+                LiteralValue::Null.generate_ir(context)
+            }
+            Statement::Let { name, symbol, ty, value } => {
+                let value = value.generate_ir(context)?;
+                // Without an annotation the variable just takes the value's type
+                let var_type = match ty {
+                    Some(ty) => context.llvm_type(Some(ty))?,
+                    None => value.get_type(),
+                };
+                let value = context.coerce(value, var_type)?;
+                context.declare_variable(resolved(*symbol, name)?, name, value, ty.as_ref(), None)?;
+                LiteralValue::Null.generate_ir(context)
+            }
+            Statement::Spanned(span, inner) => {
+                context.debug_statement(*span);
+                inner.generate_ir(context)
+            }
+            Statement::Impl { type_name, .. } => Err(CodegenError::BuildError(format!(
+                "impl block for '{}' was not lowered before codegen",
+                type_name
+            ))),
+            Statement::TraitDefinition { name, .. } => Err(CodegenError::BuildError(format!(
+                "trait '{}' was not lowered before codegen",
+                name
+            ))),
+            Statement::Import { module, .. } => Err(CodegenError::BuildError(format!(
+                "import of '{}' was not resolved before codegen",
+                module
+            ))),
+        }
     }
 }
 
@@ -76,9 +973,14 @@ impl<'ctx> Codegen<'ctx> for LiteralValue {
                 let float_value = float_type.const_float(*value);
                 Ok(float_value.into())
             }
+            LiteralValue::Integer(value, ty) => context.build_integer(*value, ty.as_ref()),
             LiteralValue::String(value) => {
-                let string_value = context.context.const_string(value.as_bytes(), false);
-                Ok(string_value.into())
+                let string_value = context.builder.build_global_string_ptr(value, "str")?;
+                Ok(string_value.as_pointer_value().into())
+            }
+            LiteralValue::Template(pieces) => {
+                let string_value = context.builder.build_global_string_ptr(&pieces.join("{}"), "str")?;
+                Ok(string_value.as_pointer_value().into())
             }
             LiteralValue::Boolean(value) => {
                 let bool_type = context.context.bool_type();
@@ -97,210 +999,826 @@ impl<'ctx> Codegen<'ctx> for LiteralValue {
 impl<'ctx> Codegen<'ctx> for Expression {
     fn generate_ir(&self, context: &mut CodegenContext<'ctx>) -> Result<BasicValueEnum<'ctx>, CodegenError> {
         match self {
-            
             Expression::While(cond, block) => {
-                // Create basic blocks for the loop.
-                let parent_fn = context.builder.get_insert_block().unwrap().get_parent().unwrap();
-                let cond_block = context.context.append_basic_block(parent_fn, "whilecond");
-                let body_block = context.context.append_basic_block(parent_fn, "whilebody");
-                let end_block = context.context.append_basic_block(parent_fn, "endwhile");
-
-                // Jump to the condition block.
-                context.builder.build_unconditional_branch(cond_block);
+                let function = context.current_function()?;
+                let cond_block = context.context.append_basic_block(function, "whilecond");
+                let body_block = context.context.append_basic_block(function, "whilebody");
+                let end_block = context.context.append_basic_block(function, "endwhile");
 
-                // Generate condition.
-                context.builder.position_at_end(cond_block);
-                let cond_value = cond.generate_ir(context)?.into_int_value();
-                context.builder.build_conditional_branch(cond_value, body_block, end_block);
+                context.continue_in(cond_block)?;
+                let cond_value = cond.generate_ir(context)?;
+                let cond_value = context.build_condition(cond_value)?;
+                context.builder.build_conditional_branch(cond_value, body_block, end_block)?;
 
-                // Generate the body.
                 context.builder.position_at_end(body_block);
-                for stmt in block {
-                    stmt.generate_ir(context)?;
-                }
-                // After body, jump back to the condition block.
-                context.builder.build_unconditional_branch(cond_block);
+                context.generate_block(block)?;
+                context.builder.build_unconditional_branch(cond_block)?;
 
-                // Continue after the loop.
                 context.builder.position_at_end(end_block);
-                match context.builder.build_unreachable() {
-                    Ok(v) => Ok(v.into()),
-                    Err(e) => Err(CodegenError::BuildError("Coped and failed, while loop func".into()))
-                }
+                LiteralValue::Null.generate_ir(context)
             }
+            // for i in range(10), for i in range(2, 10) or for x in xs
+            Expression::For(var, iterable, block) => {
+                let function = context.current_function()?;
+                let cond_block = context.context.append_basic_block(function, "forcond");
+                let body_block = context.context.append_basic_block(function, "forbody");
+                let step_block = context.context.append_basic_block(function, "forstep");
+                let end_block = context.context.append_basic_block(function, "endfor");
+
+                let var_type = context.llvm_type(var.ty.as_ref())?;
+                let var_symbol = resolved(var.symbol, &var.name)?;
+                let var_slot = context.build_variable_slot(var_symbol, var_type, &var.name)?;
+                context.debug_declare(&var.name, var_slot, var_type, var.ty.as_ref(), None);
+                context.insert_variable(var_symbol, var_slot, var_type);
+
+                let i64_type = context.context.i64_type();
+                match iterable.as_ref() {
+                    Expression::FunctionCall(callee, args)
+                        if matches!(callee.as_ref(), Expression::Identifier(name, None) if name == "range") =>
+                    {
+                        let mut bounds = Vec::new();
+                        for arg in args {
+                            let value = arg.generate_ir(context)?;
+                            bounds.push(context.coerce(value, var_type)?);
+                        }
+                        let (start, end) = match bounds[..] {
+                            [end] => (var_type.const_zero(), end),
+                            [start, end] => (start, end),
+                            _ => return Err(CodegenError::BuildError("range takes an end, or a start and an end".into())),
+                        };
+                        context.builder.build_store(var_slot, start)?;
 
-            // Identifier_name would be i in for i in range(100) {}
-            Expression::For(identifier_name, cond, block) => {
-                let parent_fn = context.builder.get_insert_block().unwrap().get_parent().unwrap();
-                let start_block = context.context.append_basic_block(parent_fn, "forstart");
-                let body_block = context.context.append_basic_block(parent_fn, "forbody");
-                let end_block = context.context.append_basic_block(parent_fn, "endfor");
-
-                // Generate the range (assume cond is a range expression).
-                let range_val = cond.generate_ir(context)?;
-
-                // Create a pointer for the loop variable.
-                let loop_var_ptr = context.builder.build_alloca(range_val.get_type(), identifier_name).unwrap();
-
-                // Set up the initial value of the loop variable.
-                let start_value = range_val.get_first_use().unwrap().get_used_value();
-                context.builder.build_store(loop_var_ptr, start_value.left().unwrap());
-
-                // Jump to the start block.
-                context.builder.build_unconditional_branch(start_block);
-                todo!()
-                // Fucking myself bad
-                // context.builder.position_at_end(start_block);
-                // let pointee_type = context.context.ptr_type(inkwell::AddressSpace::default());
-                // let current_val = context.builder.build_load(pointee_type, loop_var_ptr, identifier_name).unwrap().into_int_value();
-                // let end_value = range_val;
-                // let cond = context.builder.build_int_compare(IntPredicate::ULT, current_val, end_value, "forcond");
-                // context.builder.build_conditional_branch(cond, body_block, end_block);
-                //
-                // // Generate the body of the loop.
-                // context.builder.position_at_end(body_block);
-                // for stmt in block {
-                //     stmt.generate_ir(context)?;
-                // }
-                // // Increment the loop variable.
-                // let incremented = context.builder.build_int_add(current_val, range_val.get_step(), "increment");
-                // context.builder.build_store(loop_var_ptr, incremented);
-                //
-                // // Jump back to the condition.
-                // context.builder.build_unconditional_branch(start_block);
-                //
-                // // End block.
-                // context.builder.position_at_end(end_block);
-                // Ok(context.builder.build_unreachable().into())
-            }
-            Expression::Assignment(name, val) => {
-
-                todo!()
+                        context.continue_in(cond_block)?;
+                        let current = context.builder.build_load(var_type, var_slot, &var.name)?;
+                        let signed = is_signed(var.ty.as_ref());
+                        let keep_going = context.build_comparison(&ComparisonOperator::LessThan, current, end, signed)?;
+                        context
+                            .builder
+                            .build_conditional_branch(keep_going.into_int_value(), body_block, end_block)?;
+
+                        context.builder.position_at_end(body_block);
+                        context.generate_block(block)?;
+                        context.continue_in(step_block)?;
+                        let current = context.builder.build_load(var_type, var_slot, &var.name)?;
+                        let one = match var_type {
+                            BasicTypeEnum::IntType(t) => t.const_int(1, false).into(),
+                            _ => context.context.f64_type().const_float(1.0).into(),
+                        };
+                        let next = context.build_math(&MathOperator::Add, current, one, signed)?;
+                        context.builder.build_store(var_slot, next)?;
+                        context.builder.build_unconditional_branch(cond_block)?;
+                    }
+                    other => {
+                        let BasicValueEnum::StructValue(list) = other.generate_ir(context)? else {
+                            return Err(CodegenError::BuildError("Only ranges and lists can be looped over".into()));
+                        };
+                        let element_type = context
+                            .list_element(list.get_type())
+                            .ok_or_else(|| CodegenError::BuildError("Only ranges and lists can be looped over".into()))?;
+                        let length = context.builder.build_extract_value(list, 0, "length")?.into_int_value();
+                        let data = context.builder.build_extract_value(list, 1, "listdata")?.into_pointer_value();
+                        let index_slot = context.build_entry_alloca(i64_type.into(), "index")?;
+                        context.builder.build_store(index_slot, i64_type.const_zero())?;
+
+                        context.continue_in(cond_block)?;
+                        let index = context.builder.build_load(i64_type, index_slot, "index")?.into_int_value();
+                        let keep_going = context.builder.build_int_compare(IntPredicate::SLT, index, length, "forcond")?;
+                        context.builder.build_conditional_branch(keep_going, body_block, end_block)?;
+
+                        context.builder.position_at_end(body_block);
+                        let slot = unsafe { context.builder.build_gep(element_type, data, &[index], "element")? };
+                        let element = context.builder.build_load(element_type, slot, &var.name)?;
+                        let element = context.coerce(element, var_type)?;
+                        context.builder.build_store(var_slot, element)?;
+                        context.generate_block(block)?;
+                        context.continue_in(step_block)?;
+                        let index = context.builder.build_load(i64_type, index_slot, "index")?.into_int_value();
+                        let next = context.builder.build_int_add(index, i64_type.const_int(1, false), "next")?;
+                        context.builder.build_store(index_slot, next)?;
+                        context.builder.build_unconditional_branch(cond_block)?;
+                    }
+                }
+
+                context.builder.position_at_end(end_block);
+                LiteralValue::Null.generate_ir(context)
+            }
+            Expression::Assignment(target, value) => {
+                let value = value.generate_ir(context)?;
+                match target.as_ref() {
+                    // The first assignment to a variable makes its slot
+                    Expression::Identifier(name, symbol) if context.get_variable(*symbol).is_none() => {
+                        context.declare_variable(resolved(*symbol, name)?, name, value, None, None)?;
+                    }
+                    other => {
+                        let (slot, slot_type) = context.address_of(other)?;
+                        let value = context.coerce(value, slot_type)?;
+                        context.builder.build_store(slot, value)?;
+                    }
+                }
+                LiteralValue::Null.generate_ir(context)
             }
             Expression::If(cond, block) => {
+                let function = context.current_function()?;
+                let then_block = context.context.append_basic_block(function, "then");
+                let end_block = context.context.append_basic_block(function, "endif");
 
-                todo!()
-            }
-            Expression::Keyword(name) => {
+                let cond_value = cond.generate_ir(context)?;
+                let cond_value = context.build_condition(cond_value)?;
+                context.builder.build_conditional_branch(cond_value, then_block, end_block)?;
 
-                todo!()
+                context.builder.position_at_end(then_block);
+                context.generate_block(block)?;
+                context.continue_in(end_block)?;
+                LiteralValue::Null.generate_ir(context)
             }
-            Expression::Grouping(expr) => {
+            Expression::Keyword(name) => Err(CodegenError::BuildError(format!("Unexpected keyword: {}", name))),
+            Expression::Grouping(expr) => expr.generate_ir(context),
+            // Each condition is tested in turn, the first one that holds runs its block. With an
+            // else, the blocks' values meet in a phi and that's the value of the whole thing
+            Expression::IfElse(cond, block, elif_branches, else_block) => {
+                let function = context.current_function()?;
+                let end_block = context.context.append_basic_block(function, "endif");
+                let mut incoming = Vec::new();
 
-                todo!()
-            }
-            Expression::IfElse(cond_1, block, other_cond_statement, else_statement) => {
-                todo!()
+                let branches = std::iter::once((cond.as_ref(), block)).chain(
+                    elif_branches
+                        .iter()
+                        .map(|(elif_cond, elif_block)| (elif_cond.as_ref(), elif_block)),
+                );
+                for (branch_cond, branch_block) in branches {
+                    let then_block = context.context.append_basic_block(function, "then");
+                    let next_block = context.context.append_basic_block(function, "else");
 
-            }
-            Expression::BitwiseOp(cond_1, op, cond_2) => {
+                    let cond_value = branch_cond.generate_ir(context)?;
+                    let cond_value = context.build_condition(cond_value)?;
+                    context.builder.build_conditional_branch(cond_value, then_block, next_block)?;
 
-                todo!()
+                    context.builder.position_at_end(then_block);
+                    let value = context.generate_block(branch_block)?;
+                    context.record_branch(&mut incoming, value)?;
+                    context.continue_in(end_block)?;
+                    context.builder.position_at_end(next_block);
+                }
+
+                let Some(else_block) = else_block else {
+                    context.continue_in(end_block)?;
+                    return LiteralValue::Null.generate_ir(context);
+                };
+                let value = context.generate_block(else_block)?;
+                context.record_branch(&mut incoming, value)?;
+                context.continue_in(end_block)?;
+
+                // Branches that return never get here, so they aren't in `incoming`. If a block
+                // ended in a statement, or the types don't line up because the value isn't used,
+                // there's nothing to merge
+                let Some((first, _)) = incoming.first().copied().flatten() else {
+                    return LiteralValue::Null.generate_ir(context);
+                };
+                let mut values = Vec::new();
+                for branch in &incoming {
+                    match branch {
+                        Some((value, block)) if value.get_type() == first.get_type() => values.push((*value, *block)),
+                        _ => return LiteralValue::Null.generate_ir(context),
+                    }
+                }
+                let phi = context.builder.build_phi(first.get_type(), "iftmp")?;
+                for (value, block) in &values {
+                    phi.add_incoming(&[(value, *block)]);
+                }
+                Ok(phi.as_basic_value())
+            }
+            Expression::BitwiseOp(lhs, op, rhs, ty) => {
+                let BasicValueEnum::IntValue(l) = lhs.generate_ir(context)? else {
+                    return Err(CodegenError::BuildError("Bitwise operators only work on ints".into()));
+                };
+                let BasicValueEnum::IntValue(r) = rhs.generate_ir(context)? else {
+                    return Err(CodegenError::BuildError("Bitwise operators only work on ints".into()));
+                };
+                Ok(context.build_bitwise(op, l, r, is_signed(ty.as_ref()))?.into())
             }
-            Expression::LogicalOp(cond_1, op, cond_2) => {
+            Expression::Cast(inner, to, from) => {
+                let value = inner.generate_ir(context)?;
+                context.build_cast(value, from.as_ref(), to)
+            }
+            // The right side only runs when the left one doesn't already decide the result
+            Expression::LogicalOp(lhs, op @ (LogicalOperator::And | LogicalOperator::Or), rhs) => {
+                let l = lhs.generate_ir(context)?;
+                let l = context.build_condition(l)?;
+                let function = context.current_function()?;
+                let lhs_end = context.current_block()?;
+                let rhs_block = context.context.append_basic_block(function, "rhs");
+                let merge_block = context.context.append_basic_block(function, "logicmerge");
+                // What the whole thing is when the right side is skipped
+                let decided = match op {
+                    LogicalOperator::And => {
+                        context.builder.build_conditional_branch(l, rhs_block, merge_block)?;
+                        context.context.bool_type().const_zero()
+                    }
+                    _ => {
+                        context.builder.build_conditional_branch(l, merge_block, rhs_block)?;
+                        context.context.bool_type().const_all_ones()
+                    }
+                };
+
+                context.builder.position_at_end(rhs_block);
+                let r = rhs.generate_ir(context)?;
+                let r = context.build_condition(r)?;
+                let rhs_end = context.current_block()?;
+                context.builder.build_unconditional_branch(merge_block)?;
 
-                todo!()
+                context.builder.position_at_end(merge_block);
+                let result = context.builder.build_phi(context.context.bool_type(), "logictmp")?;
+                result.add_incoming(&[(&decided, lhs_end), (&r, rhs_end)]);
+                Ok(result.as_basic_value())
+            }
+            // Xor always needs both sides
+            Expression::LogicalOp(lhs, op, rhs) => {
+                let l = lhs.generate_ir(context)?;
+                let l = context.build_condition(l)?;
+                let r = rhs.generate_ir(context)?;
+                let r = context.build_condition(r)?;
+                match op {
+                    LogicalOperator::Not => Err(CodegenError::BuildError("'!' takes a single operand".into())),
+                    _ => Ok(context.builder.build_xor(l, r, "tmpxor")?.into()),
+                }
             }
             Expression::LiteralValue(literal) => literal.generate_ir(context),
-            Expression::Identifier(name) => {
-                if let Some(ptr) = context.get_variable(name) {
-                    // Determine the pointee type by using the pointer's type.
-                    let pointee_type = ptr.get_type().as_basic_type_enum();
+            Expression::Identifier(name, symbol) => {
+                if let Some((ptr, ty)) = context.get_variable(*symbol) {
                     // Load the value of the variable from memory
-                    let value = context.builder.build_load(pointee_type, ptr, name);
-
-                    match value {
-                        Ok(v) => Ok(v),
-                        Err(_) => Err(CodegenError::BuildError("I suck, identifier fail".into()))
-                    }
-                    
+                    Ok(context.builder.build_load(ty, ptr, name)?)
+                } else if let Some(func) = context.get_function(name) {
+                    context.closure_for_function(name, func)
                 } else {
                     Err(CodegenError::BuildError(format!("Undefined variable: {}", name)))
                 }
             }
-            Expression::MathOp(lhs, op, rhs) => {
-                let lhs_val = lhs.generate_ir(context)?.into_float_value();
-                let rhs_val = rhs.generate_ir(context)?.into_float_value();
+            Expression::MathOp(lhs, op, rhs, ty) => {
+                let lhs_val = lhs.generate_ir(context)?;
+                let rhs_val = rhs.generate_ir(context)?;
+                context.build_math(op, lhs_val, rhs_val, is_signed(ty.as_ref()))
+            }
+            Expression::ComparisonOp(lhs, op, rhs, ty) => {
+                let lhs_val = lhs.generate_ir(context)?;
+                let rhs_val = rhs.generate_ir(context)?;
+                context.build_comparison(op, lhs_val, rhs_val, is_signed(ty.as_ref()))
+            }
+            Expression::FunctionCall(callee, args)
+                if matches!(callee.as_ref(), Expression::Identifier(name, None) if name == "format") =>
+            {
+                // The type checker made every value after the format string a show() call
+                let Some((pieces, values)) = args.split_first().and_then(|(template, values)| Some((format_pieces(template)?, values))) else {
+                    return Err(CodegenError::BuildError("format(...) needs a string literal to format".into()));
+                };
+                let mut shown = Vec::new();
+                for value in values {
+                    shown.push(value.generate_ir(context)?);
+                }
+                Ok(context.build_format(pieces, &shown)?.into())
+            }
+            Expression::FunctionCall(callee, args) => {
+                // Calling a named function directly skips the closure machinery
+                if let Expression::Identifier(name, symbol) = callee.as_ref() {
+                    if context.get_variable(*symbol).is_none() {
+                        // Calling a struct's name constructs it, e.g. Point(1, 2)
+                        if let Some((struct_type, fields)) = context.get_struct(name) {
+                            if args.len() != fields.len() {
+                                return Err(CodegenError::BuildError(format!(
+                                    "'{}' has {} fields but {} were given",
+                                    name,
+                                    fields.len(),
+                                    args.len()
+                                )));
+                            }
+
+                            let mut instance = struct_type.get_undef();
+                            for (i, arg) in args.iter().enumerate() {
+                                let value = arg.generate_ir(context)?;
+                                let field_type = struct_type.get_field_type_at_index(i as u32).unwrap();
+                                let value = context.coerce(value, field_type)?;
+                                instance = context
+                                    .builder
+                                    .build_insert_value(instance, value, i as u32, name)?
+                                    .into_struct_value();
+                            }
+                            return Ok(instance.into());
+                        }
+
+                        // The prelude's building blocks, generated inline
+                        if intrinsics().iter().any(|(intrinsic, _)| intrinsic == name) {
+                            let mut values = Vec::new();
+                            for arg in args {
+                                values.push(arg.generate_ir(context)?);
+                            }
+                            return context.build_intrinsic(name, &values);
+                        }
+
+                        let Some(func) = context.get_function(name) else {
+                            return Err(CodegenError::BuildError(format!("Undefined function: {}", name)));
+                        };
+
+                        let param_types = func.get_type().get_param_types();
+                        let arg_values = context.generate_arguments(args, &param_types)?;
+
+                        // Perform the function call
+                        let call_site_value = context.builder.build_call(func, &arg_values, "calltmp")?;
+
+                        // Check if the function call has a return value
+                        let return_value = match func.get_type().get_return_type() {
+                            Some(_) => call_site_value.try_as_basic_value(),
+                            None => {
+                                return Err(CodegenError::BuildError(format!(
+                                    "Function '{}' does not return a value.",
+                                    name
+                                )))
+                            }
+                        };
+                        return if let Some(v) = return_value.left() {
+                            Ok(v)
+                        } else {
+                            Err(CodegenError::BuildError(format!(
+                                "Function '{}' does not return a value.",
+                                name
+                            )))
+                        };
+                    }
+                }
 
-                let result = match op {
-                    MathOperator::Add => context.builder.build_float_add(lhs_val, rhs_val, "tmpadd"),
-                    MathOperator::Subtract => context.builder.build_float_sub(lhs_val, rhs_val, "tmpsub"),
-                    MathOperator::Multiply => context.builder.build_float_mul(lhs_val, rhs_val, "tmpmul"),
-                    MathOperator::Divide => context.builder.build_float_div(lhs_val, rhs_val, "tmpdiv"),
-                    MathOperator::Modulus => context.builder.build_float_rem(lhs_val, rhs_val, "tmprem"),
+                // Anything else has to evaluate to a closure
+                let closure = match callee.generate_ir(context)? {
+                    BasicValueEnum::StructValue(closure) => closure,
+                    _ => return Err(CodegenError::BuildError("Called value is not a function".into())),
                 };
+                let fn_ptr = context.builder.build_extract_value(closure, 0, "fnptr")?.into_pointer_value();
+                let env_ptr = context.builder.build_extract_value(closure, 1, "env")?;
 
-                match result {
-                    Ok(v) => Ok(v.into()),
-                    Err(e) => Err(CodegenError::BuildError("Fail".into()))
+                let fn_type = context
+                    .closure_signature(closure.get_type())
+                    .ok_or_else(|| CodegenError::BuildError("Called value is not a function".into()))?;
+                let param_types = fn_type.get_param_types();
+                if param_types.len() != args.len() + 1 {
+                    return Err(CodegenError::BuildError(format!(
+                        "Expected {} arguments, found {}",
+                        param_types.len() - 1,
+                        args.len()
+                    )));
                 }
+
+                let mut arg_values: Vec<BasicMetadataValueEnum> = vec![env_ptr.into()];
+                arg_values.extend(context.generate_arguments(args, &param_types[1..])?);
+
+                let call_site_value = context.builder.build_indirect_call(
+                    fn_type,
+                    fn_ptr,
+                    &arg_values,
+                    "calltmp",
+                )?;
+                call_site_value
+                    .try_as_basic_value()
+                    .left()
+                    .ok_or_else(|| CodegenError::BuildError("Closure does not return a value.".into()))
             }
-            Expression::ComparisonOp(lhs, op, rhs) => {
-                let lhs_val = lhs.generate_ir(context)?.into_float_value();
-                let rhs_val = rhs.generate_ir(context)?.into_float_value();
+            Expression::MethodCall(receiver, method, args, ty) => {
+                let receiver_value = receiver.generate_ir(context)?;
+                // The type checker's type knows more than the value does, like whether an int is unsigned
+                let type_name = match ty {
+                    Some(ty) if !matches!(ty, Type::Var(_) | Type::Param(_)) => Some(ty.to_string()),
+                    _ => context.type_name_of(&receiver_value),
+                }
+                .ok_or_else(|| CodegenError::BuildError(format!("Cannot call '{}' on this value", method)))?;
 
-                let result = match op {
-                    ComparisonOperator::Equals => {
-                        context.builder.build_float_compare(FloatPredicate::OEQ, lhs_val, rhs_val, "cmpeq")
-                    }
-                    ComparisonOperator::NotEquals => {
-                        context.builder.build_float_compare(FloatPredicate::ONE, lhs_val, rhs_val, "cmpne")
-                    }
-                    ComparisonOperator::GreaterThan => {
-                        context.builder.build_float_compare(FloatPredicate::OGT, lhs_val, rhs_val, "cmpgt")
-                    }
-                    ComparisonOperator::LessThan => {
-                        context.builder.build_float_compare(FloatPredicate::OLT, lhs_val, rhs_val, "cmplt")
-                    }
-                    ComparisonOperator::GreaterThanEq => {
-                        context.builder.build_float_compare(FloatPredicate::OGE, lhs_val, rhs_val, "cmpge")
-                    }
-                    ComparisonOperator::LessThanEq => {
-                        context.builder.build_float_compare(FloatPredicate::OLE, lhs_val, rhs_val, "cmple")
+                // dyn values don't know their type, the vtable does
+                if let Some(trait_name) = type_name.strip_prefix("dyn ") {
+                    let mut arg_values = Vec::new();
+                    for arg in args {
+                        arg_values.push(arg.generate_ir(context)?);
                     }
+                    return context.build_dyn_call(receiver_value, trait_name, method, arg_values);
+                }
+                if method == "show" && args.is_empty() && context.methods.resolve(&type_name, method).is_none() {
+                    return Ok(context.build_show(receiver_value, ty.as_ref())?.into());
+                }
+                let mangled = context.methods.resolve(&type_name, method).ok_or_else(|| {
+                    CodegenError::BuildError(format!("No method '{}' on type '{}'", method, type_name))
+                })?;
+                let func = context
+                    .get_function(mangled)
+                    .ok_or_else(|| CodegenError::BuildError(format!("Undefined function: {}", mangled)))?;
+
+                // The receiver is passed as `self`, the rest like any other call's arguments
+                let param_types = func.get_type().get_param_types();
+                let receiver_value = match param_types.first() {
+                    Some(self_type) => context.coerce(receiver_value, *self_type)?,
+                    None => receiver_value,
                 };
+                let mut arg_values: Vec<BasicMetadataValueEnum> = vec![receiver_value.into()];
+                arg_values.extend(context.generate_arguments(args, param_types.get(1..).unwrap_or_default())?);
 
-                match result {
-                    Ok(v) => Ok(v.into()),
-                    Err(e) => Err(CodegenError::BuildError("Fail".into()))
-                }
+                let call_site_value = context.builder.build_call(func, &arg_values, "calltmp")?;
+                call_site_value.try_as_basic_value().left().ok_or_else(|| {
+                    CodegenError::BuildError(format!("Method '{}' does not return a value.", method))
+                })
             }
-            Expression::FunctionCall(name, args) => {
-                if let Some(func) = context.get_function(name) {
-                    let arg_values: Vec<BasicMetadataValueEnum> = args
-                        .iter()
-                        .map(|arg| arg.generate_ir(context))
-                        .collect::<Result<Vec<_>, _>>()?
-                        .into_iter()
-                        .map(|v| v.into())
-                        .collect();
-
-                    // Perform the function call
-                    let call_site_value = context.builder.build_call(func, &arg_values, "calltmp").unwrap();
-
-                    // Check if the function call has a return value
-                    let return_value = match func.get_type().get_return_type() {
-                        Some(_) => call_site_value.try_as_basic_value(),
-                        None => {
-                            return Err(CodegenError::BuildError(format!(
-                                "Function '{}' does not return a value.",
-                                name
-                            )))
+            Expression::FieldAccess(object, field) => {
+                let value = object.generate_ir(context)?;
+                let type_name = context.type_name_of(&value).unwrap_or_default();
+                let (BasicValueEnum::StructValue(instance), Some((_, fields))) = (value, context.get_struct(&type_name))
+                else {
+                    return Err(CodegenError::BuildError(format!("Cannot access field '{}' on this value", field)));
+                };
+                let index = fields.iter().position(|f| &f.name == field).ok_or_else(|| {
+                    CodegenError::BuildError(format!("'{}' has no field '{}'", type_name, field))
+                })?;
+
+                Ok(context.builder.build_extract_value(instance, index as u32, field)?)
+            }
+            // The text goes straight into the format string, the shown values where its %s are
+            Expression::StringInterpolation(parts) => {
+                let mut format = String::new();
+                let mut values = Vec::new();
+                for part in parts {
+                    match part {
+                        Expression::LiteralValue(LiteralValue::String(text)) => {
+                            format.push_str(&text.replace('%', "%%"))
+                        }
+                        shown => {
+                            format.push_str("%s");
+                            values.push(shown.generate_ir(context)?);
                         }
-                    };
-                    if let Some(v) = return_value.left() {
-                        Ok(v)
-                    } else {
-                        Err(CodegenError::BuildError(format!(
-                            "Function '{}' does not return a value.",
-                            name
-                        )))
                     }
-                    
+                }
+                Ok(context.build_sprintf(&format, &values)?.into())
+            }
+            Expression::List(elements) => {
+                let mut values = Vec::new();
+                for element in elements {
+                    values.push(element.generate_ir(context)?);
+                }
+                // Everything is stored as whatever the first element is
+                let element_type = match values.first() {
+                    Some(first) => first.get_type(),
+                    None => context.context.f64_type().into(),
+                };
+                let list_type = context.list_type(element_type);
+
+                let length = context.context.i64_type().const_int(values.len() as u64, false);
+                let data = context.builder.build_array_malloc(element_type, length, "listdata")?;
+                for (i, value) in values.into_iter().enumerate() {
+                    let value = context.coerce(value, element_type)?;
+                    let index = context.context.i64_type().const_int(i as u64, false);
+                    let slot = unsafe { context.builder.build_gep(element_type, data, &[index], "element")? };
+                    context.builder.build_store(slot, value)?;
+                }
+
+                let list = context.builder.build_insert_value(list_type.get_undef(), length, 0, "list")?;
+                let list = context.builder.build_insert_value(list, data, 1, "list")?;
+                Ok(list.into_struct_value().into())
+            }
+            Expression::Index(list, index) => {
+                let BasicValueEnum::StructValue(list) = list.generate_ir(context)? else {
+                    return Err(CodegenError::BuildError("Only lists can be indexed".into()));
+                };
+                let element_type = context
+                    .list_element(list.get_type())
+                    .ok_or_else(|| CodegenError::BuildError("Only lists can be indexed".into()))?;
+                let index = index.generate_ir(context)?;
+                let index = context.coerce(index, context.context.i64_type().into())?.into_int_value();
+
+                let data = context.builder.build_extract_value(list, 1, "listdata")?.into_pointer_value();
+                let slot = unsafe { context.builder.build_gep(element_type, data, &[index], "element")? };
+                Ok(context.builder.build_load(element_type, slot, "element")?)
+            }
+            Expression::DynCast(inner, trait_name) => {
+                let value = inner.generate_ir(context)?;
+                context.build_dyn(value, trait_name)
+            }
+            Expression::Specialized(name, _) => Err(CodegenError::BuildError(format!(
+                "Generic function '{}' should have been monomorphized",
+                name
+            ))),
+            Expression::Lambda(parameters, return_type, body) => {
+                // Captured variables are on the heap, the environment holds where each one is
+                let captures: Vec<(SymbolId, (PointerValue, BasicTypeEnum))> = referenced_variables(body)
+                    .into_iter()
+                    .filter_map(|symbol| Some((symbol, context.get_variable(Some(symbol))?)))
+                    .collect();
+
+                let ptr_type = context.context.ptr_type(AddressSpace::default());
+                let env_fields = vec![BasicTypeEnum::from(ptr_type); captures.len()];
+                let env_type = context.context.struct_type(&env_fields, false);
+                let env_ptr = if captures.is_empty() {
+                    ptr_type.const_null()
                 } else {
-                    Err(CodegenError::BuildError(format!("Undefined function: {}", name)))
+                    context.builder.build_malloc(env_type, "env")?
+                };
+                for (i, (_, (slot, _))) in captures.iter().enumerate() {
+                    let field = context.builder.build_struct_gep(env_type, env_ptr, i as u32, "envfield")?;
+                    context.builder.build_store(field, *slot)?;
+                }
+
+                let param_types = parameters
+                    .iter()
+                    .map(|param| context.llvm_type(param.ty.as_ref()))
+                    .collect::<Result<Vec<_>, _>>()?;
+                let returns = return_type.as_ref().unwrap_or(&Type::Null);
+                let return_type = context.llvm_type(return_type.as_ref())?;
+                let function = context.module.add_function(
+                    "lambda",
+                    context.closure_fn_type(&param_types, return_type),
+                    Some(Linkage::Internal),
+                );
+                let previous_block = context.builder.get_insert_block();
+                // The body only sees its captures and parameters
+                let outer_variables = std::mem::take(&mut context.variable_table);
+
+                let entry = context.context.append_basic_block(function, "entry");
+                context.builder.position_at_end(entry);
+                context.debug_enter_function(function, false);
+
+                let env_param = function.get_nth_param(0).unwrap().into_pointer_value();
+                for (i, (symbol, (_, ty))) in captures.iter().enumerate() {
+                    let field = context.builder.build_struct_gep(env_type, env_param, i as u32, "envfield")?;
+                    let slot = context.builder.build_load(ptr_type, field, "captured")?.into_pointer_value();
+                    context.insert_variable(*symbol, slot, *ty);
+                }
+                for (i, param) in parameters.iter().enumerate() {
+                    let value = function.get_nth_param(i as u32 + 1).unwrap();
+                    let symbol = resolved(param.symbol, &param.name)?;
+                    context.declare_variable(symbol, &param.name, value, param.ty.as_ref(), Some(i as u32 + 1))?;
+                }
+
+                let result = context.generate_block(body);
+                if let Ok(value) = &result {
+                    context.build_fall_through(*value, returns, return_type)?;
+                }
+                context.debug_leave_function();
+
+                context.variable_table = outer_variables;
+                if let Some(block) = previous_block {
+                    context.builder.position_at_end(block);
                 }
+                result?;
+                context.verify_function(function, "a lambda")?;
+
+                context.build_closure(function, env_ptr)
+            }
+        }
+    }
+}
+
+// Nothing branches to it and it isn't where its function starts, like the block code after
+// a return goes in
+fn is_unreachable(block: BasicBlock) -> bool {
+    block.get_first_use().is_none() && block.get_previous_basic_block().is_some()
+}
+
+// The verifier only says why for a whole module, and other functions may still be half
+// built. The function itself is what went wrong, so that's what gets reported
+fn check_function(function: FunctionValue, name: &str) -> Result<(), CodegenError> {
+    if function.verify(false) {
+        return Ok(());
+    }
+    Err(CodegenError::InvalidFunction(name.to_string(), function.print_to_string().to_string()))
+}
+
+// Every variable a lambda body refers to. Names are resolved to symbols, so the ones
+// that were declared outside the lambda are exactly the ones already in the variable table
+fn referenced_variables(body: &[Statement]) -> Vec<SymbolId> {
+    let mut found = Vec::new();
+    for stmt in body {
+        collect_in_statement(stmt, &mut found);
+    }
+    found
+}
+
+fn collect_in_statement(stmt: &Statement, found: &mut Vec<SymbolId>) {
+    match stmt {
+        Statement::Expression(expr) => collect_in_expression(expr, found),
+        Statement::Block(stmts) => {
+            for stmt in stmts {
+                collect_in_statement(stmt, found);
+            }
+        }
+        Statement::Return(value) => {
+            if let Some(expr) = value.as_ref() {
+                collect_in_expression(expr, found);
+            }
+        }
+        Statement::Let { value, .. } => collect_in_expression(value, found),
+        Statement::Spanned(_, inner) => collect_in_statement(inner, found),
+        // Named functions never capture
+        Statement::FunctionDefinition { .. }
+        | Statement::Comment(_)
+        | Statement::StructDefinition { .. }
+        | Statement::Impl { .. }
+        | Statement::TraitDefinition { .. }
+        | Statement::Import { .. } => {}
+    }
+}
+
+fn collect_in_expression(expr: &Expression, found: &mut Vec<SymbolId>) {
+    match expr {
+        Expression::Identifier(_, Some(symbol)) => {
+            if !found.contains(symbol) {
+                found.push(*symbol);
             }
         }
+        Expression::Identifier(_, None)
+        | Expression::LiteralValue(_)
+        | Expression::Keyword(_)
+        | Expression::Specialized(..) => {}
+        Expression::List(elements) | Expression::StringInterpolation(elements) => {
+            for element in elements {
+                collect_in_expression(element, found);
+            }
+        }
+        Expression::MathOp(lhs, _, rhs, _)
+        | Expression::ComparisonOp(lhs, _, rhs, _)
+        | Expression::LogicalOp(lhs, _, rhs)
+        | Expression::BitwiseOp(lhs, _, rhs, _)
+        | Expression::Index(lhs, rhs)
+        | Expression::Assignment(lhs, rhs) => {
+            collect_in_expression(lhs, found);
+            collect_in_expression(rhs, found);
+        }
+        Expression::Grouping(inner)
+        | Expression::FieldAccess(inner, _)
+        | Expression::DynCast(inner, _)
+        | Expression::Cast(inner, _, _) => collect_in_expression(inner, found),
+        Expression::FunctionCall(callee, args) | Expression::MethodCall(callee, _, args, _) => {
+            collect_in_expression(callee, found);
+            for arg in args {
+                collect_in_expression(arg, found);
+            }
+        }
+        Expression::If(cond, block) | Expression::While(cond, block) | Expression::For(_, cond, block) => {
+            collect_in_expression(cond, found);
+            for stmt in block {
+                collect_in_statement(stmt, found);
+            }
+        }
+        Expression::IfElse(cond, block, elif_branches, else_block) => {
+            collect_in_expression(cond, found);
+            for stmt in block {
+                collect_in_statement(stmt, found);
+            }
+            for (elif_cond, elif_block) in elif_branches {
+                collect_in_expression(elif_cond, found);
+                for stmt in elif_block {
+                    collect_in_statement(stmt, found);
+                }
+            }
+            for stmt in else_block.iter().flatten() {
+                collect_in_statement(stmt, found);
+            }
+        }
+        // Whatever a nested lambda captures has to come through us as well
+        Expression::Lambda(_, _, body) => {
+            for stmt in body {
+                collect_in_statement(stmt, found);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::Lexer;
+    use crate::modules::Module;
+    use crate::parser::{monomorphize, Parser};
+    use crate::semantic::{Resolver, TypeChecker};
+    use inkwell::execution_engine::ExecutionEngine;
+    use inkwell::targets::{InitializationConfig, Target, TargetData};
+    use inkwell::OptimizationLevel;
+    use std::ffi::{c_char, CStr};
+    use std::path::PathBuf;
+
+    fn generate<'ctx>(context: &'ctx Context, source: &str, overflow_checks: bool) -> CodegenContext<'ctx> {
+        generate_with(context, source, |codegen| codegen.overflow_checks = overflow_checks)
+    }
+
+    // `setup` gets to change the context before any code is generated
+    fn generate_with<'ctx>(
+        context: &'ctx Context,
+        source: &str,
+        setup: impl FnOnce(&mut CodegenContext<'ctx>),
+    ) -> CodegenContext<'ctx> {
+        let mut lexer = Lexer::new(source);
+        let tokens = lexer.tokenize().expect("test source should lex").to_vec();
+        let ast = Parser::new(tokens, lexer.spans().to_vec()).parse().expect("test source should parse");
+        let module = Module {
+            name: String::new(),
+            file: 0,
+            imports: HashMap::new(),
+            tokens: Vec::new(),
+            ast,
+        };
+        let mut resolver = Resolver::new();
+        let ast = resolver.resolve_program(Vec::new(), vec![module]).expect("test source should resolve");
+        let (mut ast, methods) = MethodTable::build(ast).expect("test source should have valid methods");
+        TypeChecker::new(&methods).check_program(&mut ast).expect("test source should type check");
+        let ast = monomorphize(ast);
+
+        let mut codegen = CodegenContext::new(context, "test", methods);
+        codegen.captured = resolver.captured().clone();
+        setup(&mut codegen);
+        codegen.generate_program(&ast).expect("test source should compile");
+        codegen
+    }
+
+    // The whole pipeline up to a JIT for this machine, so tests can call the program's functions
+    fn compile<'ctx>(context: &'ctx Context, source: &str, overflow_checks: bool) -> ExecutionEngine<'ctx> {
+        let codegen = generate(context, source, overflow_checks);
+        Target::initialize_native(&InitializationConfig::default()).expect("the host should be a target");
+        codegen
+            .module
+            .create_jit_execution_engine(OptimizationLevel::None)
+            .expect("the JIT should start")
+    }
+
+    fn call_int(source: &str, function: &str, arg: i64) -> i64 {
+        let context = Context::create();
+        let engine = compile(&context, source, false);
+        unsafe {
+            let function = engine
+                .get_function::<unsafe extern "C" fn(i64) -> i64>(function)
+                .expect("the function should be compiled");
+            function.call(arg)
+        }
+    }
+
+    fn call_str(source: &str, function: &str, arg: i64) -> String {
+        let context = Context::create();
+        let engine = compile(&context, source, false);
+        unsafe {
+            let function = engine
+                .get_function::<unsafe extern "C" fn(i64) -> *const c_char>(function)
+                .expect("the function should be compiled");
+            let text = function.call(arg);
+            assert!(!text.is_null(), "the function returned null");
+            CStr::from_ptr(text).to_string_lossy().into_owned()
+        }
+    }
+
+    #[test]
+    fn a_returning_branch_leaves_the_others_value() {
+        let source = "fn pick(n: int) -> str {\n let s = if n > 0 { \"yes\" } else { return \"no\" }\n return s\n}\n";
+        assert_eq!(call_str(source, "pick", 1), "yes");
+        assert_eq!(call_str(source, "pick", 0), "no");
+    }
+
+    #[test]
+    fn a_returning_branch_fits_any_other() {
+        let source = "fn clamp(n: int) -> int {\n let m = if n < 10 { n * 2 } else { return 10 }\n return m + 1\n}\n";
+        assert_eq!(call_int(source, "clamp", 3), 7);
+        assert_eq!(call_int(source, "clamp", 12), 10);
+    }
+
+    #[test]
+    fn a_body_returns_its_last_expression() {
+        let source = "fn inc(x: int) -> int { x + 1 }\n";
+        assert_eq!(call_int(source, "inc", 4), 5);
+    }
+
+    #[test]
+    fn a_lambda_body_returns_its_last_expression() {
+        let source = "fn apply(n: int) -> int {\n let inc = fn(x: int) -> int { x + 1 }\n return inc(n)\n}\n";
+        assert_eq!(call_int(source, "apply", 4), 5);
+    }
+
+    #[test]
+    fn division_is_checked_with_overflow_checks() {
+        let source = "fn half(n: int) -> int { n / 2 }\nfn rest(n: u8) -> u8 { n % 3 }\n";
+        let context = Context::create();
+        let checked = generate(&context, source, true).module.print_to_string().to_string();
+        assert!(checked.contains("attempt to divide by zero"));
+        assert!(checked.contains("attempt to divide with overflow"));
+        assert!(checked.contains("attempt to calculate the remainder with a divisor of zero"));
+        // u8 can't overflow dividing
+        assert!(!checked.contains("attempt to calculate the remainder with overflow"));
+        let unchecked = generate(&context, source, false).module.print_to_string().to_string();
+        assert!(!unchecked.contains("attempt to"));
+
+        let engine = compile(&context, source, true);
+        let half = unsafe { engine.get_function::<unsafe extern "C" fn(i64) -> i64>("half") }.expect("half is compiled");
+        assert_eq!(unsafe { half.call(-7) }, -3);
+    }
+
+    #[test]
+    fn debug_info_follows_the_target_and_the_checked_types() {
+        let source = "fn keep(n: u8) -> u8 {\n let m: u8 = n\n return m\n}\nlet text = \"s\"\n";
+        let files = [PathBuf::from("/src/main.lum")];
+        let context = Context::create();
+        let codegen = generate_with(&context, source, |codegen| {
+            // wasm32's, where pointers are 32 bits
+            let layout = TargetData::create("e-m:e-p:32:32-i64:64-n32:64-S128").get_data_layout();
+            codegen.module.set_data_layout(&layout);
+            codegen.enable_debug_info(&files, 0);
+        });
+        let ir = codegen.module.print_to_string().to_string();
+        assert!(ir.contains("name: \"u8\", size: 8, encoding: DW_ATE_unsigned"), "{}", ir);
+        let pointer = ir.lines().find(|line| line.contains("name: \"str\"")).expect("str has a type");
+        assert!(pointer.contains("size: 32, align: 32"), "{}", pointer);
     }
 }
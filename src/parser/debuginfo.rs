@@ -0,0 +1,327 @@
+use super::codegen::{is_signed, CodegenContext};
+use super::enums::Type;
+use crate::lexer::Span;
+use inkwell::debug_info::{
+    debug_metadata_version, AsDIScope, DICompileUnit, DIFile, DIFlags, DIFlagsConstants, DILocation, DIScope, DIType,
+    DWARFEmissionKind, DWARFSourceLanguage, DebugInfoBuilder,
+};
+use inkwell::module::FlagBehavior;
+use inkwell::targets::TargetData;
+use inkwell::types::{BasicType, BasicTypeEnum};
+use inkwell::values::{FunctionValue, PointerValue};
+use inkwell::AddressSpace;
+use std::path::{Path, PathBuf};
+
+// DW_ATE_* encodings for the basic types
+const DW_ATE_BOOLEAN: u32 = 0x02;
+const DW_ATE_FLOAT: u32 = 0x04;
+const DW_ATE_SIGNED: u32 = 0x05;
+const DW_ATE_SIGNED_CHAR: u32 = 0x06;
+const DW_ATE_UNSIGNED: u32 = 0x08;
+
+// DWARF for the module being built, following the statement spans codegen goes through
+pub struct DebugInfo<'ctx> {
+    builder: DebugInfoBuilder<'ctx>,
+    unit: DICompileUnit<'ctx>,
+    files: Vec<DIFile<'ctx>>,             // Same order as the file index in spans
+    scopes: Vec<(DIScope<'ctx>, usize)>, // Innermost last, with the file each one is in
+    functions: Vec<(usize, Span)>,       // Scope depth and statement to go back to after each function
+    span: Span,                          // The statement being generated
+    target: TargetData,                  // Where sizes and alignments come from
+}
+
+impl<'ctx> CodegenContext<'ctx> {
+    // Turns on debug info for everything generated from here on. `files` are the source
+    // files by span index, `main_file` is the one the top level code starts in. Types are laid
+    // out by the module's data layout, so that has to be set for the target first
+    pub fn enable_debug_info(&mut self, files: &[PathBuf], main_file: usize) {
+        let target = TargetData::create(&self.module.get_data_layout().as_str().to_string_lossy());
+        let (name, directory) = split_path(&files[main_file]);
+        let (builder, unit) = self.module.create_debug_info_builder(
+            true,
+            DWARFSourceLanguage::C,
+            &name,
+            &directory,
+            "lumina",
+            false,
+            "",
+            0,
+            "",
+            DWARFEmissionKind::Full,
+            0,
+            false,
+            false,
+            "",
+            "",
+        );
+        let version = self.context.i32_type().const_int(debug_metadata_version() as u64, false);
+        self.module.add_basic_value_flag("Debug Info Version", FlagBehavior::Warning, version);
+        let dwarf = self.context.i32_type().const_int(4, false);
+        self.module.add_basic_value_flag("Dwarf Version", FlagBehavior::Warning, dwarf);
+
+        let files = files
+            .iter()
+            .map(|file| {
+                let (name, directory) = split_path(file);
+                builder.create_file(&name, &directory)
+            })
+            .collect();
+        self.debug_info = Some(DebugInfo {
+            builder,
+            unit,
+            files,
+            scopes: vec![(unit.as_debug_info_scope(), main_file)],
+            functions: Vec::new(),
+            span: Span { line: 1, column: 1, file: main_file },
+            target,
+        });
+    }
+
+    // Every instruction from here until the next statement gets this statement's line
+    pub fn debug_statement(&mut self, span: Span) {
+        if let Some(debug) = self.debug_info.as_mut() {
+            debug.span = span;
+        }
+        self.set_debug_location();
+    }
+
+    // A subprogram for a function about to get its body, at the statement defining it.
+    // `artificial` ones are glue the compiler made up, like closure wrappers
+    pub fn debug_enter_function(&mut self, function: FunctionValue<'ctx>, artificial: bool) {
+        if self.debug_info.is_none() {
+            return;
+        }
+        let fn_type = function.get_type();
+        let return_type = fn_type.get_return_type().map(|ty| self.debug_type(ty, None));
+        let param_types: Vec<DIType<'ctx>> = fn_type
+            .get_param_types()
+            .into_iter()
+            .map(|ty| self.debug_type(ty, None))
+            .collect();
+
+        let debug = self.debug_info.as_mut().unwrap();
+        let span = debug.span;
+        let file = debug.files[span.file];
+        let name = function.get_name().to_string_lossy().into_owned();
+        let subroutine = debug.builder.create_subroutine_type(file, return_type, &param_types, DIFlags::ZERO);
+        let flags = if artificial { DIFlags::ARTIFICIAL } else { DIFlags::PROTOTYPED };
+        let subprogram = debug.builder.create_function(
+            debug.unit.as_debug_info_scope(),
+            &name,
+            None,
+            file,
+            span.line as u32,
+            subroutine,
+            artificial,
+            true,
+            span.line as u32,
+            flags,
+            false,
+        );
+        function.set_subprogram(subprogram);
+
+        debug.functions.push((debug.scopes.len(), span));
+        debug.scopes.push((subprogram.as_debug_info_scope(), span.file));
+        self.set_debug_location();
+    }
+
+    // Back to the statement that was being generated before the function
+    pub fn debug_leave_function(&mut self) {
+        let Some(debug) = self.debug_info.as_mut() else {
+            return;
+        };
+        if let Some((depth, span)) = debug.functions.pop() {
+            debug.scopes.truncate(depth);
+            debug.span = span;
+        }
+        self.set_debug_location();
+    }
+
+    pub fn debug_enter_block(&mut self) {
+        let Some(debug) = self.debug_info.as_mut() else {
+            return;
+        };
+        let (scope, _) = *debug.scopes.last().unwrap();
+        let span = debug.span;
+        let block = debug
+            .builder
+            .create_lexical_block(scope, debug.files[span.file], span.line as u32, span.column as u32);
+        debug.scopes.push((block.as_debug_info_scope(), span.file));
+    }
+
+    pub fn debug_leave_block(&mut self) {
+        if let Some(debug) = self.debug_info.as_mut() {
+            debug.scopes.pop();
+        }
+        self.set_debug_location();
+    }
+
+    // Describes the stack slot of a local, or of a parameter when it has a position. `checked`
+    // is the type checker's type for it, when there is one, which knows if ints are unsigned
+    pub fn debug_declare(
+        &mut self,
+        name: &str,
+        slot: PointerValue<'ctx>,
+        ty: BasicTypeEnum<'ctx>,
+        checked: Option<&Type>,
+        argument: Option<u32>,
+    ) {
+        if self.debug_info.is_none() {
+            return;
+        }
+        let Some(block) = self.builder.get_insert_block() else {
+            return;
+        };
+        let ty = self.debug_type(ty, checked);
+        let debug = self.debug_info.as_ref().unwrap();
+        let (scope, _) = *debug.scopes.last().unwrap();
+        let span = debug.span;
+        let file = debug.files[span.file];
+        let variable = match argument {
+            Some(argument) => debug.builder.create_parameter_variable(
+                scope,
+                name,
+                argument,
+                file,
+                span.line as u32,
+                ty,
+                true,
+                DIFlags::ZERO,
+            ),
+            None => debug
+                .builder
+                .create_auto_variable(scope, name, file, span.line as u32, ty, true, DIFlags::ZERO, 0),
+        };
+        let location = self.debug_location(span);
+        debug.builder.insert_declare_at_end(slot, Some(variable), None, location, block);
+    }
+
+    // Has to run once everything is generated, before the module is verified or written
+    pub fn finish_debug_info(&mut self) {
+        if let Some(debug) = self.debug_info.as_ref() {
+            debug.builder.finalize();
+        }
+    }
+
+    // Only code inside a function can have a location, the first one entered is the entry point
+    fn set_debug_location(&self) {
+        let Some(debug) = self.debug_info.as_ref() else {
+            return;
+        };
+        if debug.functions.is_empty() {
+            self.builder.unset_current_debug_location();
+        } else {
+            self.builder.set_current_debug_location(self.debug_location(debug.span));
+        }
+    }
+
+    // A location in the innermost scope, or in a block of the span's own file when the scope
+    // is in another, like top level code of an imported module ending up in the entry point
+    fn debug_location(&self, span: Span) -> DILocation<'ctx> {
+        let debug = self.debug_info.as_ref().unwrap();
+        let (scope, scope_file) = *debug.scopes.last().unwrap();
+        let scope = if scope_file == span.file {
+            scope
+        } else {
+            debug
+                .builder
+                .create_lexical_block(scope, debug.files[span.file], span.line as u32, span.column as u32)
+                .as_debug_info_scope()
+        };
+        debug
+            .builder
+            .create_debug_location(self.context, span.line as u32, span.column as u32, scope, None)
+    }
+
+    // What a debugger shows a value of this LLVM type as. Structs get their field names when
+    // they're user structs, everything else made of structs shows its fields by position.
+    // Ints are signed unless the type checker's type says otherwise
+    fn debug_type(&self, ty: BasicTypeEnum<'ctx>, checked: Option<&Type>) -> DIType<'ctx> {
+        let debug = self.debug_info.as_ref().unwrap();
+        let basic = |name: &str, bits: u64, encoding: u32| {
+            debug
+                .builder
+                .create_basic_type(name, bits, encoding, DIFlags::ZERO)
+                .unwrap()
+                .as_type()
+        };
+        match ty {
+            BasicTypeEnum::FloatType(_) => basic("float", 64, DW_ATE_FLOAT),
+            BasicTypeEnum::IntType(int) if int.get_bit_width() == 1 => basic("bool", 8, DW_ATE_BOOLEAN),
+            BasicTypeEnum::IntType(int) => {
+                let checked = checked.filter(|checked| checked.integer().is_some());
+                let name = checked.map_or_else(|| "int".to_string(), |checked| checked.to_string());
+                let encoding = if is_signed(checked) { DW_ATE_SIGNED } else { DW_ATE_UNSIGNED };
+                basic(&name, int.get_bit_width() as u64, encoding)
+            }
+            BasicTypeEnum::PointerType(_) => {
+                let char_type = basic("char", 8, DW_ATE_SIGNED_CHAR);
+                let (size, align) = self.layout(ty);
+                debug
+                    .builder
+                    .create_pointer_type("str", char_type, size, align, AddressSpace::default())
+                    .as_type()
+            }
+            BasicTypeEnum::ArrayType(array) => {
+                let element = array.get_element_type();
+                let (size, align) = self.layout(ty);
+                let subscript = 0..array.len() as i64;
+                debug
+                    .builder
+                    .create_array_type(self.debug_type(element, None), size, align, std::slice::from_ref(&subscript))
+                    .as_type()
+            }
+            BasicTypeEnum::StructType(struct_type) => {
+                let name = struct_type
+                    .get_name()
+                    .map(|name| name.to_string_lossy().into_owned())
+                    .unwrap_or_else(|| "struct".to_string());
+                let fields = self.get_struct(&name).map(|(_, fields)| fields).unwrap_or_default();
+                let file = debug.files[debug.span.file];
+                let scope = debug.unit.as_debug_info_scope();
+
+                let mut members = Vec::new();
+                for (i, field_type) in struct_type.get_field_types().into_iter().enumerate() {
+                    let (size, align) = self.layout(field_type);
+                    let offset = debug.target.offset_of_element(&struct_type, i as u32).unwrap_or(0) * 8;
+                    let field = fields.get(i);
+                    let field_name = field.map_or_else(|| i.to_string(), |field| field.name.clone());
+                    let member = debug.builder.create_member_type(
+                        scope,
+                        &field_name,
+                        file,
+                        0,
+                        size,
+                        align,
+                        offset,
+                        DIFlags::PUBLIC,
+                        self.debug_type(field_type, field.and_then(|field| field.ty.as_ref())),
+                    );
+                    members.push(member.as_type());
+                }
+
+                let (size, align) = self.layout(ty);
+                debug
+                    .builder
+                    .create_struct_type(scope, &name, file, 0, size, align, DIFlags::ZERO, None, &members, 0, None, &name)
+                    .as_type()
+            }
+            BasicTypeEnum::VectorType(_) => basic("vector", self.layout(ty).0, DW_ATE_UNSIGNED),
+        }
+    }
+
+    // Size and alignment in bits, the way the target lays the type out
+    fn layout(&self, ty: BasicTypeEnum<'ctx>) -> (u64, u32) {
+        if !ty.is_sized() {
+            return (0, 8);
+        }
+        let target = &self.debug_info.as_ref().unwrap().target;
+        (target.get_abi_size(&ty) * 8, target.get_abi_alignment(&ty) * 8)
+    }
+}
+
+fn split_path(path: &Path) -> (String, String) {
+    let name = path.file_name().map(|name| name.to_string_lossy().into_owned()).unwrap_or_default();
+    let directory = path.parent().map(|dir| dir.display().to_string()).unwrap_or_default();
+    (name, directory)
+}
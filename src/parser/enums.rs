@@ -1,15 +1,20 @@
 use crate::lexer::enums::*;
-use crate::lexer::Token;
+use crate::lexer::{Span, Token};
+use std::fmt;
 
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum Expression {
     LiteralValue(LiteralValue),
-    Identifier(String),
-    MathOp(Box<Expression>, MathOperator, Box<Expression>),
-    ComparisonOp(Box<Expression>, ComparisonOperator, Box<Expression>),
+    Identifier(String, Option<SymbolId>), // Filled in by the resolver
+    // The type of the operands is filled in by the type checker, ints need it to know
+    // whether they're signed
+    MathOp(Box<Expression>, MathOperator, Box<Expression>, Option<Type>),
+    ComparisonOp(Box<Expression>, ComparisonOperator, Box<Expression>, Option<Type>),
     LogicalOp(Box<Expression>, LogicalOperator, Box<Expression>),
-    BitwiseOp(Box<Expression>, BitwiseOperator, Box<Expression>),
+    BitwiseOp(Box<Expression>, BitwiseOperator, Box<Expression>, Option<Type>),
+    // `x as u8`, with the type it's converted from filled in by the type checker
+    Cast(Box<Expression>, Type, Option<Type>),
 
     IfElse(
         Box<Expression>,
@@ -20,24 +25,41 @@ pub enum Expression {
 
     Grouping(Box<Expression>),
     Keyword(String),
-    FunctionCall(String, Vec<Expression>),
+    FunctionCall(Box<Expression>, Vec<Expression>),
+    // With the receiver's type from the type checker, which picks the impl the method comes from
+    MethodCall(Box<Expression>, String, Vec<Expression>, Option<Type>),
+    FieldAccess(Box<Expression>, String),
+    Lambda(Vec<Parameter>, Option<Type>, Vec<Statement>),
+    List(Vec<Expression>),
+    // "Hello {name}", string literals for the text in between. The type checker makes the
+    // rest show() calls
+    StringInterpolation(Vec<Expression>),
+    Index(Box<Expression>, Box<Expression>),
+    // A generic function or struct with its type arguments worked out by the type checker
+    Specialized(String, Vec<Type>),
+    // A value turned into a `dyn Trait`, put in by the type checker wherever one is expected
+    DynCast(Box<Expression>, String),
     If(Box<Expression>, Vec<Statement>),
     Assignment(Box<Expression>, Box<Expression>),
     While(Box<Expression>, Vec<Statement>),
-    For(String, Box<Expression>, Vec<Statement>),
+    For(Parameter, Box<Expression>, Vec<Statement>),
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum LiteralValue {
     Number(f64),
+    Integer(u64, Option<Type>), // Whole numbers can be any number type, the type checker picks
     String(String),
+    // A string with {} placeholders in it, the text around them. Only format(...) fills them
+    // in, anywhere else the {}s are part of the text
+    Template(Vec<String>),
     Boolean(bool),
     Null,
 }
 
 
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum Statement {
     Expression(Expression),
     Block(Vec<Statement>),
@@ -45,7 +67,173 @@ pub enum Statement {
     Return(Box<Option<Expression>>),
     FunctionDefinition{
         name: String,
-        parameters: Vec<String>, 
+        public: bool, // Written with `pub`, so other modules can import it
+        generics: Vec<Generic>,
+        parameters: Vec<Parameter>, 
+        return_type: Option<Type>,
         body: Vec<Statement>
     },
+    StructDefinition {
+        name: String,
+        public: bool,
+        generics: Vec<Generic>,
+        fields: Vec<Parameter>,
+    },
+    Let {
+        name: String,
+        symbol: Option<SymbolId>,
+        ty: Option<Type>,
+        value: Expression,
+    },
+    // trait Show { fn show(self) -> str }
+    TraitDefinition {
+        name: String,
+        public: bool,
+        methods: Vec<TraitMethod>,
+    },
+    // Methods are plain function definitions taking `self` first,
+    // `impl Show for Point` also names the trait they implement
+    Impl {
+        type_name: String,
+        trait_name: Option<String>,
+        methods: Vec<Statement>,
+    },
+    // `import math` brings in the module as a name, `from util import parse` brings in
+    // single items. Nested modules are dotted, `import util.strings`
+    Import {
+        module: String,
+        items: Option<Vec<String>>,
+    },
+    // Every statement the parser produces is wrapped in where it started
+    Spanned(Span, Box<Statement>),
+}
+
+impl Statement {
+    // The statement itself, without its location
+    pub fn node(&self) -> &Statement {
+        match self {
+            Statement::Spanned(_, inner) => inner.node(),
+            other => other,
+        }
+    }
+
+    pub fn node_mut(&mut self) -> &mut Statement {
+        match self {
+            Statement::Spanned(_, inner) => inner.node_mut(),
+            other => other,
+        }
+    }
+
+    pub fn into_node(self) -> Statement {
+        match self {
+            Statement::Spanned(_, inner) => inner.into_node(),
+            other => other,
+        }
+    }
+
+    pub fn span(&self) -> Option<Span> {
+        match self {
+            Statement::Spanned(span, _) => Some(*span),
+            _ => None,
+        }
+    }
+}
+
+
+// Every declaration gets its own id from the resolver, so two variables with the same
+// name in different scopes never get mixed up
+pub type SymbolId = usize;
+
+// A name with an optional annotation, used for parameters, loop variables and struct fields
+#[derive(Debug, Clone, PartialEq)]
+pub struct Parameter {
+    pub name: String,
+    pub ty: Option<Type>,
+    pub symbol: Option<SymbolId>,
+}
+
+// A generic parameter and the traits it has to implement, the T: Show in fn show_all<T: Show>
+#[derive(Debug, Clone, PartialEq)]
+pub struct Generic {
+    pub name: String,
+    pub bounds: Vec<String>,
+}
+
+// A method signature in a trait, `self` comes first and is left unannotated
+#[derive(Debug, Clone, PartialEq)]
+pub struct TraitMethod {
+    pub name: String,
+    pub parameters: Vec<Parameter>,
+    pub return_type: Option<Type>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Type {
+    Int,
+    Integer(bool, u32), // Sized ints, signed or not and their bits. i64 is the same as int
+    Float,
+    Bool,
+    Str,
+    Null,
+    Named(String, Vec<Type>),       // Structs, with type arguments if they're generic
+    Function(Vec<Type>, Box<Type>), // fn(int, int) -> int
+    List(Box<Type>),                // [int]
+    Param(String),                  // A generic's type parameter, the T in fn first<T>
+    Dyn(String),                    // Any value implementing a trait, dyn Show
+    Var(usize),                     // Not known yet, only exists while type checking
+}
+
+impl Type {
+    pub fn from_name(name: &str) -> Type {
+        match name {
+            "int" | "i64" => Type::Int,
+            "i8" => Type::Integer(true, 8),
+            "i16" => Type::Integer(true, 16),
+            "i32" => Type::Integer(true, 32),
+            "u8" => Type::Integer(false, 8),
+            "u16" => Type::Integer(false, 16),
+            "u32" => Type::Integer(false, 32),
+            "u64" => Type::Integer(false, 64),
+            "float" => Type::Float,
+            "bool" => Type::Bool,
+            "str" => Type::Str,
+            _ => Type::Named(name.to_string(), Vec::new()),
+        }
+    }
+
+    // Whether an int type is signed and how many bits it has
+    pub fn integer(&self) -> Option<(bool, u32)> {
+        match self {
+            Type::Int => Some((true, 64)),
+            Type::Integer(signed, bits) => Some((*signed, *bits)),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Type {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Type::Int => write!(f, "int"),
+            Type::Integer(true, bits) => write!(f, "i{}", bits),
+            Type::Integer(false, bits) => write!(f, "u{}", bits),
+            Type::Float => write!(f, "float"),
+            Type::Bool => write!(f, "bool"),
+            Type::Str => write!(f, "str"),
+            Type::Null => write!(f, "null"),
+            Type::Named(name, args) if args.is_empty() => write!(f, "{}", name),
+            Type::Named(name, args) => {
+                let args: Vec<String> = args.iter().map(|ty| ty.to_string()).collect();
+                write!(f, "{}<{}>", name, args.join(", "))
+            }
+            Type::Function(parameters, return_type) => {
+                let parameters: Vec<String> = parameters.iter().map(|ty| ty.to_string()).collect();
+                write!(f, "fn({}) -> {}", parameters.join(", "), return_type)
+            }
+            Type::List(element) => write!(f, "[{}]", element),
+            Type::Param(name) => write!(f, "{}", name),
+            Type::Dyn(trait_name) => write!(f, "dyn {}", trait_name),
+            Type::Var(_) => write!(f, "_"),
+        }
+    }
 }
@@ -0,0 +1,278 @@
+use super::codegen::{is_signed, CodegenContext, CodegenError};
+use super::enums::{Expression, LiteralValue, Type};
+use crate::semantic::{mangle_method, SHOW};
+use inkwell::module::Linkage;
+use inkwell::types::BasicTypeEnum;
+use inkwell::values::{BasicMetadataValueEnum, BasicValueEnum, FunctionValue, PointerValue, StructValue};
+use inkwell::IntPredicate;
+
+// Turning values into text for print and format. A type's Show impl decides how it looks,
+// anything without one is shown by what it's made of, like Point { x: 1, y: 2 } or [1, 2, 3]
+impl<'ctx> CodegenContext<'ctx> {
+    // `ty` is the type checker's type for the value. Without it there's only the LLVM type
+    // to go on, and that can't tell unsigned ints from signed ones
+    pub fn build_show(
+        &mut self,
+        value: BasicValueEnum<'ctx>,
+        ty: Option<&Type>,
+    ) -> Result<PointerValue<'ctx>, CodegenError> {
+        let ty = ty.filter(|ty| !matches!(ty, Type::Var(_) | Type::Param(_)));
+        let type_name = match ty {
+            Some(ty) => Some(ty.to_string()),
+            None => self.type_name_of(&value),
+        };
+        if let Some(mangled) = type_name.as_deref().and_then(|name| self.methods.resolve(name, "show")) {
+            let function = self
+                .get_function(mangled)
+                .ok_or_else(|| CodegenError::BuildError(format!("Undefined function: {}", mangled)))?;
+            return self.call_show(function, value);
+        }
+
+        match value {
+            BasicValueEnum::IntValue(v) if v.get_type().get_bit_width() == 1 => {
+                let yes = self.build_text("true")?;
+                let no = self.build_text("false")?;
+                Ok(self.builder.build_select(v, yes, no, "showbool")?.into_pointer_value())
+            }
+            BasicValueEnum::IntValue(v) => {
+                // printf only takes whole 64 bit ints
+                let signed = is_signed(ty);
+                let wide = self.builder.build_int_cast_sign_flag(v, self.context.i64_type(), signed, "wide")?;
+                self.build_sprintf(if signed { "%lld" } else { "%llu" }, &[wide.into()])
+            }
+            BasicValueEnum::FloatValue(v) => self.build_sprintf("%g", &[v.into()]),
+            // Strings, or null, which is a null pointer
+            BasicValueEnum::PointerValue(v) => {
+                let null = self.build_text("null")?;
+                let is_null = self.builder.build_is_null(v, "isnull")?;
+                Ok(self.builder.build_select(is_null, null, v, "showstr")?.into_pointer_value())
+            }
+            BasicValueEnum::StructValue(v) => {
+                let struct_type = v.get_type();
+                let name = struct_type
+                    .get_name()
+                    .map(|name| name.to_string_lossy().into_owned())
+                    .unwrap_or_default();
+                if let Some(trait_name) = name.strip_prefix("dyn ") {
+                    // A dyn Show has show in its vtable, values of other traits can't say what they are
+                    if trait_name == SHOW {
+                        let shown = self.build_dyn_call(value, SHOW, "show", Vec::new())?;
+                        return Ok(shown.into_pointer_value());
+                    }
+                    return self.build_text(&format!("<{}>", name));
+                }
+                if self.closure_signature(struct_type).is_some() {
+                    return self.build_text("<fn>");
+                }
+                let function = self.show_function(ty, struct_type.into())?;
+                self.call_show(function, value)
+            }
+            _ => Err(CodegenError::BuildError("Cannot show this value".into())),
+        }
+    }
+
+    // What format(...) turns into, the already shown values put in place of the {}s
+    pub fn build_format(
+        &mut self,
+        pieces: &[String],
+        values: &[BasicValueEnum<'ctx>],
+    ) -> Result<PointerValue<'ctx>, CodegenError> {
+        if pieces.len() - 1 != values.len() {
+            return Err(CodegenError::BuildError(format!(
+                "The format string has {} placeholders but {} values were given",
+                pieces.len() - 1,
+                values.len()
+            )));
+        }
+        let pieces: Vec<String> = pieces.iter().map(|piece| piece.replace('%', "%%")).collect();
+        self.build_sprintf(&pieces.join("%s"), values)
+    }
+
+    // The show method for a struct or list type without a Show impl, made the first time it's
+    // needed. It takes `self` by value like a written one would, so vtables can use it too
+    pub fn show_function(
+        &mut self,
+        ty: Option<&Type>,
+        self_type: BasicTypeEnum<'ctx>,
+    ) -> Result<FunctionValue<'ctx>, CodegenError> {
+        let BasicTypeEnum::StructType(struct_type) = self_type else {
+            return Err(CodegenError::BuildError("Only structs and lists are shown by a function".into()));
+        };
+        let type_name = match ty {
+            Some(ty) => ty.to_string(),
+            None => struct_type
+                .get_name()
+                .map(|name| name.to_string_lossy().into_owned())
+                .unwrap_or_default(),
+        };
+        let name = mangle_method(&type_name, "show");
+        if let Some(existing) = self.module.get_function(&name) {
+            return Ok(existing);
+        }
+
+        let fn_type = self.ptr_type().fn_type(&[self_type.into()], false);
+        let function = self.module.add_function(&name, fn_type, Some(Linkage::Internal));
+        let previous_block = self.builder.get_insert_block();
+        let entry = self.context.append_basic_block(function, "entry");
+        self.builder.position_at_end(entry);
+        self.debug_enter_function(function, true);
+
+        let value = function.get_nth_param(0).unwrap().into_struct_value();
+        let text = match (self.list_element(struct_type), ty) {
+            (Some(element_type), Some(Type::List(element))) => {
+                self.build_show_list(value, element_type, Some(element))?
+            }
+            (Some(element_type), _) => self.build_show_list(value, element_type, None)?,
+            (None, _) => self.build_show_struct(value, &type_name)?,
+        };
+        self.builder.build_return(Some(&text))?;
+        self.debug_leave_function();
+
+        if let Some(block) = previous_block {
+            self.builder.position_at_end(block);
+        }
+        self.verify_function(function, &name)?;
+        Ok(function)
+    }
+
+    // snprintf into a fresh heap buffer, sized by asking snprintf how long the text is first
+    pub fn build_sprintf(
+        &mut self,
+        format: &str,
+        args: &[BasicValueEnum<'ctx>],
+    ) -> Result<PointerValue<'ctx>, CodegenError> {
+        let i64_type = self.context.i64_type();
+        let ptr_type = self.ptr_type();
+        let snprintf = self.libc_function("snprintf", &[ptr_type.into(), i64_type.into(), ptr_type.into()], true);
+
+        let format = self.builder.build_global_string_ptr(format, "fmt")?;
+        let mut call_args: Vec<BasicMetadataValueEnum> = vec![
+            ptr_type.const_null().into(),
+            i64_type.const_zero().into(),
+            format.as_pointer_value().into(),
+        ];
+        call_args.extend(args.iter().map(|arg| BasicMetadataValueEnum::from(*arg)));
+        let length = self
+            .builder
+            .build_call(snprintf, &call_args, "length")?
+            .try_as_basic_value()
+            .left()
+            .ok_or_else(|| CodegenError::BuildError("snprintf returned nothing".into()))?
+            .into_int_value();
+
+        // One more for the terminating zero
+        let length = self.builder.build_int_z_extend(length, i64_type, "length")?;
+        let size = self.builder.build_int_add(length, i64_type.const_int(1, false), "size")?;
+        let buffer = self.builder.build_array_malloc(self.context.i8_type(), size, "buffer")?;
+        call_args[0] = buffer.into();
+        call_args[1] = size.into();
+        self.builder.build_call(snprintf, &call_args, "format")?;
+        Ok(buffer)
+    }
+
+    // Point { x: 1, y: 2 }, each field shown the way its own type is
+    fn build_show_struct(
+        &mut self,
+        value: StructValue<'ctx>,
+        type_name: &str,
+    ) -> Result<PointerValue<'ctx>, CodegenError> {
+        let fields = self.get_struct(type_name).map(|(_, fields)| fields).unwrap_or_default();
+        if fields.is_empty() {
+            return self.build_text(type_name);
+        }
+
+        let mut shown = Vec::new();
+        let mut pieces = Vec::new();
+        for (i, field) in fields.iter().enumerate() {
+            let field_value = self.builder.build_extract_value(value, i as u32, &field.name)?;
+            shown.push(self.build_show(field_value, field.ty.as_ref())?.into());
+            pieces.push(format!("{}: %s", field.name));
+        }
+        let format = format!("{} {{ {} }}", type_name.replace('%', "%%"), pieces.join(", "));
+        self.build_sprintf(&format, &shown)
+    }
+
+    // [1, 2, 3], built up one element at a time
+    fn build_show_list(
+        &mut self,
+        list: StructValue<'ctx>,
+        element_type: BasicTypeEnum<'ctx>,
+        element: Option<&Type>,
+    ) -> Result<PointerValue<'ctx>, CodegenError> {
+        let i64_type = self.context.i64_type();
+        let ptr_type = self.ptr_type();
+        let length = self.builder.build_extract_value(list, 0, "length")?.into_int_value();
+        let data = self.builder.build_extract_value(list, 1, "data")?.into_pointer_value();
+
+        let text = self.build_entry_alloca(ptr_type.into(), "text")?;
+        let open = self.build_text("[")?;
+        self.builder.build_store(text, open)?;
+        let index = self.build_entry_alloca(i64_type.into(), "index")?;
+        self.builder.build_store(index, i64_type.const_zero())?;
+
+        let function = self.current_function()?;
+        let check = self.context.append_basic_block(function, "check");
+        let body = self.context.append_basic_block(function, "element");
+        let done = self.context.append_basic_block(function, "done");
+        self.builder.build_unconditional_branch(check)?;
+
+        self.builder.position_at_end(check);
+        let i = self.builder.build_load(i64_type, index, "i")?.into_int_value();
+        let more = self.builder.build_int_compare(IntPredicate::ULT, i, length, "more")?;
+        self.builder.build_conditional_branch(more, body, done)?;
+
+        self.builder.position_at_end(body);
+        let slot = unsafe { self.builder.build_gep(element_type, data, &[i], "slot")? };
+        let value = self.builder.build_load(element_type, slot, "element")?;
+        let shown = self.build_show(value, element)?;
+        let first = self.builder.build_int_compare(IntPredicate::EQ, i, i64_type.const_zero(), "first")?;
+        let none = self.build_text("")?;
+        let comma = self.build_text(", ")?;
+        let separator = self.builder.build_select(first, none, comma, "separator")?;
+        let so_far = self.builder.build_load(ptr_type, text, "sofar")?;
+        let longer = self.build_sprintf("%s%s%s", &[so_far, separator, shown.into()])?;
+        self.builder.build_store(text, longer)?;
+        let next = self.builder.build_int_add(i, i64_type.const_int(1, false), "next")?;
+        self.builder.build_store(index, next)?;
+        self.builder.build_unconditional_branch(check)?;
+
+        self.builder.position_at_end(done);
+        let so_far = self.builder.build_load(ptr_type, text, "sofar")?;
+        self.build_sprintf("%s]", &[so_far])
+    }
+
+    fn call_show(
+        &mut self,
+        function: FunctionValue<'ctx>,
+        value: BasicValueEnum<'ctx>,
+    ) -> Result<PointerValue<'ctx>, CodegenError> {
+        let self_type = function
+            .get_type()
+            .get_param_types()
+            .first()
+            .copied()
+            .ok_or_else(|| CodegenError::BuildError("show takes no self".into()))?;
+        let value = self.coerce(value, self_type)?;
+        let shown = self.builder.build_call(function, &[value.into()], "shown")?;
+        shown
+            .try_as_basic_value()
+            .left()
+            .map(|shown| shown.into_pointer_value())
+            .ok_or_else(|| CodegenError::BuildError("show returned nothing".into()))
+    }
+
+    fn build_text(&self, text: &str) -> Result<PointerValue<'ctx>, CodegenError> {
+        Ok(self.builder.build_global_string_ptr(text, "text")?.as_pointer_value())
+    }
+}
+
+// The text between a format string's {} placeholders, one more piece than there are
+// placeholders. The lexer has already made {{ and }} plain braces, so they're never one.
+// None if the template isn't a string literal
+pub fn format_pieces(template: &Expression) -> Option<&[String]> {
+    match template {
+        Expression::LiteralValue(LiteralValue::String(text)) => Some(std::slice::from_ref(text)),
+        Expression::LiteralValue(LiteralValue::Template(pieces)) => Some(pieces),
+        _ => None,
+    }
+}
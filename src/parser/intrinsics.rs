@@ -0,0 +1,65 @@
+use super::codegen::{CodegenContext, CodegenError};
+use inkwell::module::Linkage;
+use inkwell::types::BasicMetadataTypeEnum;
+use inkwell::values::{BasicValueEnum, FunctionValue};
+use inkwell::AddressSpace;
+
+// Writes a string to stdout. Declared here and defined by the backend for each target, wasm
+// has its own way of getting text out
+pub const RUNTIME_PRINT: &str = "lumina_print";
+
+// The functions the prelude is built on, lowered to libc or runtime calls
+impl<'ctx> CodegenContext<'ctx> {
+    pub fn build_intrinsic(
+        &mut self,
+        name: &str,
+        args: &[BasicValueEnum<'ctx>],
+    ) -> Result<BasicValueEnum<'ctx>, CodegenError> {
+        let [value] = args else {
+            return Err(CodegenError::BuildError(format!("{} takes exactly one argument", name)));
+        };
+
+        match name {
+            "__print" => {
+                let print = self.runtime_function(RUNTIME_PRINT, &[self.ptr_type().into()]);
+                self.builder.build_call(print, &[(*value).into()], "")?;
+                Ok(self.ptr_type().const_null().into())
+            }
+            "__show_int" => Ok(self.build_sprintf("%lld", &[*value])?.into()),
+            "__show_float" => Ok(self.build_sprintf("%g", &[*value])?.into()),
+            "__show_bool" => {
+                let BasicValueEnum::IntValue(flag) = value else {
+                    return Err(CodegenError::BuildError("__show_bool takes a bool".into()));
+                };
+                let yes = self.builder.build_global_string_ptr("true", "true")?;
+                let no = self.builder.build_global_string_ptr("false", "false")?;
+                Ok(self.builder.build_select(
+                    *flag,
+                    yes.as_pointer_value(),
+                    no.as_pointer_value(),
+                    "showbool",
+                )?)
+            }
+            _ => Err(CodegenError::BuildError(format!("Unknown intrinsic: {}", name))),
+        }
+    }
+
+    pub fn libc_function(&self, name: &str, params: &[BasicMetadataTypeEnum<'ctx>], variadic: bool) -> FunctionValue<'ctx> {
+        self.module.get_function(name).unwrap_or_else(|| {
+            let fn_type = self.context.i32_type().fn_type(params, variadic);
+            self.module.add_function(name, fn_type, Some(Linkage::External))
+        })
+    }
+
+    // Runtime functions return nothing, the backend fills in their bodies
+    pub fn runtime_function(&self, name: &str, params: &[BasicMetadataTypeEnum<'ctx>]) -> FunctionValue<'ctx> {
+        self.module.get_function(name).unwrap_or_else(|| {
+            let fn_type = self.context.void_type().fn_type(params, false);
+            self.module.add_function(name, fn_type, Some(Linkage::External))
+        })
+    }
+
+    pub fn ptr_type(&self) -> inkwell::types::PointerType<'ctx> {
+        self.context.ptr_type(AddressSpace::default())
+    }
+}
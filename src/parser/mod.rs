@@ -1,5 +1,14 @@
 pub mod enums;
 mod parser;
 mod codegen;
+mod debuginfo;
+mod format;
+mod intrinsics;
+mod monomorphize;
+mod numbers;
 
+pub use codegen::{Codegen, CodegenContext, CodegenError, ENTRY_POINT};
+pub use format::format_pieces;
+pub use intrinsics::RUNTIME_PRINT;
+pub use monomorphize::monomorphize;
 pub use parser::Parser;
@@ -0,0 +1,368 @@
+use super::enums::*;
+use std::collections::{HashMap, HashSet};
+
+// Turns generic functions and structs into one plain copy per set of type arguments they're
+// used with, so codegen only ever sees concrete types. `first<T>` used with ints becomes a
+// function called `first<int>`, `Pair<int, float>` becomes a struct with that name.
+pub fn monomorphize(ast: Vec<Statement>) -> Vec<Statement> {
+    let mut monomorphizer = Monomorphizer::default();
+    let mut program = ast;
+    monomorphizer.rewrite_block(&mut program);
+
+    // Struct types have to exist before anything that uses them, so every top level struct,
+    // specialized or not, goes first in dependency order
+    let (structs, rest): (Vec<_>, Vec<_>) = program
+        .into_iter()
+        .partition(|stmt| matches!(stmt.node(), Statement::StructDefinition { .. }));
+    let mut structs: Vec<Statement> = monomorphizer.structs.into_iter().chain(structs).collect();
+    structs = order_structs(structs);
+
+    structs
+        .into_iter()
+        .chain(monomorphizer.functions)
+        .chain(rest)
+        .collect()
+}
+
+#[derive(Default)]
+struct Monomorphizer {
+    generic_functions: HashMap<String, Statement>,
+    generic_structs: HashMap<String, Statement>,
+    done: HashSet<String>,          // Specializations that have already been made
+    params: HashMap<String, Type>,  // What each generic parameter is in the copy being made
+    functions: Vec<Statement>,
+    structs: Vec<Statement>,
+}
+
+impl Monomorphizer {
+    // Generic definitions are taken out of the block, they only exist through their copies
+    fn rewrite_block(&mut self, stmts: &mut Vec<Statement>) {
+        let mut kept = Vec::with_capacity(stmts.len());
+        for stmt in std::mem::take(stmts) {
+            match stmt.node() {
+                Statement::FunctionDefinition { name, generics, .. } if !generics.is_empty() => {
+                    self.generic_functions.insert(name.clone(), stmt);
+                }
+                Statement::StructDefinition { name, generics, .. } if !generics.is_empty() => {
+                    self.generic_structs.insert(name.clone(), stmt);
+                }
+                _ => kept.push(stmt),
+            }
+        }
+
+        for stmt in kept.iter_mut() {
+            self.rewrite_statement(stmt);
+        }
+        *stmts = kept;
+    }
+
+    fn rewrite_statement(&mut self, stmt: &mut Statement) {
+        match stmt {
+            Statement::Spanned(_, inner) => self.rewrite_statement(inner),
+            Statement::Expression(expr) => self.rewrite_expression(expr),
+            Statement::Block(stmts) => self.rewrite_block(stmts),
+            Statement::Return(value) => {
+                if let Some(expr) = value.as_mut() {
+                    self.rewrite_expression(expr);
+                }
+            }
+            Statement::FunctionDefinition { parameters, return_type, body, .. } => {
+                for param in parameters {
+                    self.rewrite_slot(&mut param.ty);
+                }
+                self.rewrite_slot(return_type);
+                self.rewrite_block(body);
+            }
+            Statement::StructDefinition { fields, .. } => {
+                for field in fields {
+                    self.rewrite_slot(&mut field.ty);
+                }
+            }
+            Statement::Let { ty, value, .. } => {
+                self.rewrite_slot(ty);
+                self.rewrite_expression(value);
+            }
+            Statement::Comment(_)
+            | Statement::Impl { .. }
+            | Statement::TraitDefinition { .. }
+            | Statement::Import { .. } => {}
+        }
+    }
+
+    fn rewrite_expression(&mut self, expr: &mut Expression) {
+        match expr {
+            Expression::Specialized(name, args) => {
+                for arg in args.iter_mut() {
+                    self.rewrite_type(arg);
+                }
+                // Generic struct names only show up here as constructors
+                let mangled = if self.generic_structs.contains_key(name.as_str()) {
+                    self.instantiate_struct(name, args)
+                } else {
+                    self.instantiate_function(name, args)
+                };
+                *expr = Expression::Identifier(mangled, None);
+            }
+            Expression::LiteralValue(_) | Expression::Identifier(..) | Expression::Keyword(_) => {}
+            Expression::List(elements) | Expression::StringInterpolation(elements) => {
+                for element in elements {
+                    self.rewrite_expression(element);
+                }
+            }
+            Expression::MathOp(lhs, _, rhs, ty)
+            | Expression::ComparisonOp(lhs, _, rhs, ty)
+            | Expression::BitwiseOp(lhs, _, rhs, ty) => {
+                self.rewrite_expression(lhs);
+                self.rewrite_expression(rhs);
+                self.rewrite_slot(ty);
+            }
+            Expression::LogicalOp(lhs, _, rhs)
+            | Expression::Assignment(lhs, rhs)
+            | Expression::Index(lhs, rhs) => {
+                self.rewrite_expression(lhs);
+                self.rewrite_expression(rhs);
+            }
+            Expression::Grouping(inner) | Expression::FieldAccess(inner, _) | Expression::DynCast(inner, _) => {
+                self.rewrite_expression(inner)
+            }
+            Expression::Cast(inner, _, from) => {
+                self.rewrite_expression(inner);
+                self.rewrite_slot(from);
+            }
+            Expression::For(var, iterable, block) => {
+                self.rewrite_slot(&mut var.ty);
+                self.rewrite_expression(iterable);
+                self.rewrite_block(block);
+            }
+            Expression::If(cond, block) | Expression::While(cond, block) => {
+                self.rewrite_expression(cond);
+                self.rewrite_block(block);
+            }
+            Expression::IfElse(cond, block, elif_branches, else_block) => {
+                self.rewrite_expression(cond);
+                self.rewrite_block(block);
+                for (elif_cond, elif_block) in elif_branches {
+                    self.rewrite_expression(elif_cond);
+                    self.rewrite_block(elif_block);
+                }
+                if let Some(else_block) = else_block {
+                    self.rewrite_block(else_block);
+                }
+            }
+            Expression::FunctionCall(callee, args) => {
+                self.rewrite_expression(callee);
+                for arg in args {
+                    self.rewrite_expression(arg);
+                }
+            }
+            Expression::MethodCall(receiver, _, args, ty) => {
+                self.rewrite_expression(receiver);
+                for arg in args {
+                    self.rewrite_expression(arg);
+                }
+                self.rewrite_slot(ty);
+            }
+            Expression::Lambda(parameters, return_type, body) => {
+                for param in parameters {
+                    self.rewrite_slot(&mut param.ty);
+                }
+                self.rewrite_slot(return_type);
+                self.rewrite_block(body);
+            }
+        }
+    }
+
+    fn rewrite_slot(&mut self, ty: &mut Option<Type>) {
+        if let Some(ty) = ty {
+            self.rewrite_type(ty);
+        }
+    }
+
+    fn rewrite_type(&mut self, ty: &mut Type) {
+        match ty {
+            Type::Param(name) => {
+                if let Some(concrete) = self.params.get(name.as_str()) {
+                    *ty = concrete.clone();
+                }
+            }
+            Type::Named(name, args) if !args.is_empty() => {
+                for arg in args.iter_mut() {
+                    self.rewrite_type(arg);
+                }
+                let mangled = self.instantiate_struct(name, args);
+                *ty = Type::Named(mangled, Vec::new());
+            }
+            Type::List(element) => self.rewrite_type(element),
+            Type::Function(parameters, return_type) => {
+                for param in parameters {
+                    self.rewrite_type(param);
+                }
+                self.rewrite_type(return_type);
+            }
+            _ => {}
+        }
+    }
+
+    fn instantiate_function(&mut self, name: &str, args: &[Type]) -> String {
+        let mangled = mangle(name, args);
+        if !self.done.insert(mangled.clone()) {
+            return mangled;
+        }
+        let Some(mut function) = self.generic_functions.get(name).cloned() else {
+            return mangled;
+        };
+
+        // Marked as done first, so recursive generic functions reuse this copy
+        let outer_params = self.specialize(&mut function, &mangled, args);
+        self.rewrite_statement(&mut function);
+        self.params = outer_params;
+        self.functions.push(function);
+        mangled
+    }
+
+    fn instantiate_struct(&mut self, name: &str, args: &[Type]) -> String {
+        let mangled = mangle(name, args);
+        if !self.done.insert(mangled.clone()) {
+            return mangled;
+        }
+        let Some(mut definition) = self.generic_structs.get(name).cloned() else {
+            return mangled;
+        };
+
+        let outer_params = self.specialize(&mut definition, &mangled, args);
+        self.rewrite_statement(&mut definition);
+        self.params = outer_params;
+        self.structs.push(definition);
+        mangled
+    }
+
+    // Renames a copy of a generic definition and makes its parameters stand for `args`,
+    // handing back whatever they stood for before
+    fn specialize(&mut self, definition: &mut Statement, mangled: &str, args: &[Type]) -> HashMap<String, Type> {
+        let definition = match definition {
+            Statement::Spanned(_, inner) => inner.as_mut(),
+            other => other,
+        };
+        let (Statement::FunctionDefinition { name, generics, .. } | Statement::StructDefinition { name, generics, .. }) =
+            definition
+        else {
+            return self.params.clone();
+        };
+
+        let params = std::mem::take(generics)
+            .into_iter()
+            .map(|generic| generic.name)
+            .zip(args.iter().cloned())
+            .collect();
+        *name = mangled.to_string();
+        std::mem::replace(&mut self.params, params)
+    }
+}
+
+fn mangle(name: &str, args: &[Type]) -> String {
+    let args: Vec<String> = args.iter().map(|arg| arg.to_string()).collect();
+    format!("{}<{}>", name, args.join(", "))
+}
+
+// Puts structs after every struct their fields hold
+fn order_structs(structs: Vec<Statement>) -> Vec<Statement> {
+    let mut by_name: HashMap<String, Statement> = HashMap::new();
+    let mut names = Vec::new();
+    for stmt in structs {
+        if let Statement::StructDefinition { name, .. } = stmt.node() {
+            names.push(name.clone());
+            by_name.insert(name.clone(), stmt);
+        }
+    }
+
+    fn visit(name: &str, by_name: &mut HashMap<String, Statement>, ordered: &mut Vec<Statement>) {
+        let Some(stmt) = by_name.remove(name) else {
+            return;
+        };
+        if let Statement::StructDefinition { fields, .. } = stmt.node() {
+            for field in fields {
+                if let Some(ty) = &field.ty {
+                    for dependency in struct_names(ty) {
+                        visit(&dependency, by_name, ordered);
+                    }
+                }
+            }
+        }
+        ordered.push(stmt);
+    }
+
+    let mut ordered = Vec::with_capacity(names.len());
+    for name in names {
+        visit(&name, &mut by_name, &mut ordered);
+    }
+    ordered
+}
+
+// Every struct named anywhere in a type, list and closure types are named after theirs too
+fn struct_names(ty: &Type) -> Vec<String> {
+    match ty {
+        Type::Named(name, _) => vec![name.clone()],
+        Type::List(element) => struct_names(element),
+        Type::Function(parameters, return_type) => parameters
+            .iter()
+            .chain(std::iter::once(return_type.as_ref()))
+            .flat_map(struct_names)
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::Lexer;
+    use crate::modules::Module;
+    use crate::parser::Parser;
+    use crate::semantic::{MethodTable, Resolver, TypeChecker};
+
+    fn specialize(source: &str) -> Vec<Statement> {
+        let mut lexer = Lexer::new(source);
+        let tokens = lexer.tokenize().expect("test source should lex").to_vec();
+        let ast = Parser::new(tokens, lexer.spans().to_vec()).parse().expect("test source should parse");
+        let module = Module {
+            name: String::new(),
+            file: 0,
+            imports: HashMap::new(),
+            tokens: Vec::new(),
+            ast,
+        };
+        let ast = Resolver::new().resolve_program(Vec::new(), vec![module]).expect("test source should resolve");
+        let (mut ast, methods) = MethodTable::build(ast).expect("test source should have valid methods");
+        TypeChecker::new(&methods).check_program(&mut ast).expect("test source should type check");
+        monomorphize(ast)
+    }
+
+    fn definitions(ast: &[Statement]) -> Vec<String> {
+        ast.iter()
+            .filter_map(|stmt| match stmt.node() {
+                Statement::FunctionDefinition { name, .. } | Statement::StructDefinition { name, .. } => {
+                    Some(name.clone())
+                }
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn one_copy_per_type_argument() {
+        let ast = specialize(
+            "fn first<T>(xs: [T]) -> T { return xs[0] }\n\
+             let a: int = first([1])\nlet b = first([\"x\"])\nlet c: int = first([2])\n",
+        );
+        let mut names = definitions(&ast);
+        names.sort();
+        assert_eq!(names, vec!["first<int>", "first<str>"]);
+    }
+
+    #[test]
+    fn specialized_structs_come_first() {
+        let ast = specialize("struct Pair<A, B> { left: A, right: B }\nlet p = Pair(true, \"s\")\n");
+        assert_eq!(definitions(&ast), vec!["Pair<bool, str>"]);
+        assert!(matches!(ast[0].node(), Statement::StructDefinition { .. }));
+    }
+}
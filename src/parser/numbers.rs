@@ -0,0 +1,222 @@
+use super::codegen::{is_signed, CodegenContext, CodegenError};
+use super::enums::Type;
+use crate::lexer::enums::{BitwiseOperator, MathOperator};
+use inkwell::intrinsics::Intrinsic;
+use inkwell::types::{BasicTypeEnum, IntType};
+use inkwell::values::{BasicValueEnum, FunctionValue, IntValue};
+use inkwell::IntPredicate;
+
+// Integer arithmetic and conversions between number types. LLVM ints don't know whether
+// they're signed, so everything here is told by the type checker's types
+impl<'ctx> CodegenContext<'ctx> {
+    pub fn int_type(&self, bits: u32) -> IntType<'ctx> {
+        self.context.custom_width_int_type(bits)
+    }
+
+    // A whole number literal as whatever number type the type checker decided it is. There's
+    // no unary minus, negative numbers are written as 0 - n, so the most negative value of a
+    // signed type has no literal. i64's is 0 - 9223372036854775807 - 1
+    pub fn build_integer(&self, value: u64, ty: Option<&Type>) -> Result<BasicValueEnum<'ctx>, CodegenError> {
+        let (signed, bits) = match ty {
+            Some(Type::Float) => return Ok(self.context.f64_type().const_float(value as f64).into()),
+            Some(ty) => ty.integer().unwrap_or((true, 64)),
+            None => (true, 64),
+        };
+        let max = match signed {
+            true => u64::MAX >> (65 - bits),
+            false => u64::MAX >> (64 - bits),
+        };
+        if value > max {
+            let ty = ty.map_or_else(|| "int".to_string(), |ty| ty.to_string());
+            return Err(CodegenError::BuildError(format!("{} doesn't fit in {}", value, ty)));
+        }
+        Ok(self.int_type(bits).const_int(value, false).into())
+    }
+
+    // Division, remainder and right shifts depend on the sign. With overflow checks on, the
+    // rest panic where they'd otherwise wrap around
+    pub fn build_int_math(
+        &mut self,
+        op: &MathOperator,
+        l: IntValue<'ctx>,
+        r: IntValue<'ctx>,
+        signed: bool,
+    ) -> Result<IntValue<'ctx>, CodegenError> {
+        let checked = match op {
+            MathOperator::Add => "add",
+            MathOperator::Subtract => "sub",
+            MathOperator::Multiply => "mul",
+            MathOperator::Divide | MathOperator::Modulus => return self.build_int_division(op, l, r, signed),
+        };
+        if !self.overflow_checks {
+            return Ok(match op {
+                MathOperator::Add => self.builder.build_int_add(l, r, "tmpadd")?,
+                MathOperator::Subtract => self.builder.build_int_sub(l, r, "tmpsub")?,
+                _ => self.builder.build_int_mul(l, r, "tmpmul")?,
+            });
+        }
+
+        // llvm.sadd.with.overflow and friends give back { result, overflowed }
+        let name = format!("llvm.{}{}.with.overflow", if signed { "s" } else { "u" }, checked);
+        let intrinsic = self.intrinsic(&name, &[l.get_type().into()])?;
+        let pair = self
+            .builder
+            .build_call(intrinsic, &[l.into(), r.into()], "checked")?
+            .try_as_basic_value()
+            .left()
+            .ok_or_else(|| CodegenError::BuildError(format!("{} returned nothing", name)))?
+            .into_struct_value();
+        let result = self.builder.build_extract_value(pair, 0, &format!("tmp{}", checked))?.into_int_value();
+        let overflowed = self.builder.build_extract_value(pair, 1, "overflowed")?.into_int_value();
+
+        let verb = match op {
+            MathOperator::Add => "add",
+            MathOperator::Subtract => "subtract",
+            _ => "multiply",
+        };
+        self.build_panic_if(overflowed, &format!("attempt to {} with overflow", verb))?;
+        Ok(result)
+    }
+
+    // Dividing by zero is undefined in LLVM, and so is the most negative int divided by -1,
+    // which doesn't fit. With overflow checks on, both panic before they can happen
+    fn build_int_division(
+        &mut self,
+        op: &MathOperator,
+        l: IntValue<'ctx>,
+        r: IntValue<'ctx>,
+        signed: bool,
+    ) -> Result<IntValue<'ctx>, CodegenError> {
+        let divide = matches!(op, MathOperator::Divide);
+        if self.overflow_checks {
+            let int_type = r.get_type();
+            let by_zero = self.builder.build_int_compare(IntPredicate::EQ, r, int_type.const_zero(), "byzero")?;
+            self.build_panic_if(
+                by_zero,
+                match divide {
+                    true => "attempt to divide by zero",
+                    false => "attempt to calculate the remainder with a divisor of zero",
+                },
+            )?;
+
+            if signed {
+                let min = int_type.const_int(1 << (int_type.get_bit_width() - 1), false);
+                let is_min = self.builder.build_int_compare(IntPredicate::EQ, l, min, "ismin")?;
+                let by_minus_one =
+                    self.builder.build_int_compare(IntPredicate::EQ, r, int_type.const_all_ones(), "byminusone")?;
+                let overflows = self.builder.build_and(is_min, by_minus_one, "overflows")?;
+                self.build_panic_if(
+                    overflows,
+                    match divide {
+                        true => "attempt to divide with overflow",
+                        false => "attempt to calculate the remainder with overflow",
+                    },
+                )?;
+            }
+        }
+
+        Ok(match (divide, signed) {
+            (true, true) => self.builder.build_int_signed_div(l, r, "tmpdiv")?,
+            (true, false) => self.builder.build_int_unsigned_div(l, r, "tmpdiv")?,
+            (false, true) => self.builder.build_int_signed_rem(l, r, "tmprem")?,
+            (false, false) => self.builder.build_int_unsigned_rem(l, r, "tmprem")?,
+        })
+    }
+
+    // Carries on in a new block when `failed` doesn't hold, and panics with `message` when it does
+    fn build_panic_if(&mut self, failed: IntValue<'ctx>, message: &str) -> Result<(), CodegenError> {
+        let function = self.current_function()?;
+        let panic_block = self.context.append_basic_block(function, "panic");
+        let ok_block = self.context.append_basic_block(function, "ok");
+        self.builder.build_conditional_branch(failed, panic_block, ok_block)?;
+        self.builder.position_at_end(panic_block);
+        self.build_panic(message)?;
+        self.builder.position_at_end(ok_block);
+        Ok(())
+    }
+
+    pub fn build_bitwise(
+        &self,
+        op: &BitwiseOperator,
+        l: IntValue<'ctx>,
+        r: IntValue<'ctx>,
+        signed: bool,
+    ) -> Result<IntValue<'ctx>, CodegenError> {
+        Ok(match op {
+            BitwiseOperator::And => self.builder.build_and(l, r, "tmpand")?,
+            BitwiseOperator::Or => self.builder.build_or(l, r, "tmpor")?,
+            BitwiseOperator::Xor => self.builder.build_xor(l, r, "tmpxor")?,
+            BitwiseOperator::LeftShift => self.builder.build_left_shift(l, r, "tmpshl")?,
+            // Signed ints keep their sign
+            BitwiseOperator::RightShift => self.builder.build_right_shift(l, r, signed, "tmpshr")?,
+        })
+    }
+
+    // `as`. Ints get cut down or extended by their sign, floats going to ints saturate at
+    // the int's limits like Rust does instead of being undefined
+    pub fn build_cast(
+        &self,
+        value: BasicValueEnum<'ctx>,
+        from: Option<&Type>,
+        to: &Type,
+    ) -> Result<BasicValueEnum<'ctx>, CodegenError> {
+        let from_signed = is_signed(from);
+        let target = match to.integer() {
+            Some((_, bits)) => BasicTypeEnum::from(self.int_type(bits)),
+            None => self.context.f64_type().into(),
+        };
+
+        match (value, target) {
+            // Bools are ints one bit wide, they always count up from zero
+            (BasicValueEnum::IntValue(v), BasicTypeEnum::IntType(t)) if v.get_type().get_bit_width() == 1 => {
+                Ok(self.builder.build_int_z_extend(v, t, "booltoint")?.into())
+            }
+            (BasicValueEnum::IntValue(v), BasicTypeEnum::IntType(t)) => {
+                let (from_bits, to_bits) = (v.get_type().get_bit_width(), t.get_bit_width());
+                Ok(if from_bits > to_bits {
+                    self.builder.build_int_truncate(v, t, "trunc")?
+                } else if from_bits == to_bits {
+                    v
+                } else if from_signed {
+                    self.builder.build_int_s_extend(v, t, "sext")?
+                } else {
+                    self.builder.build_int_z_extend(v, t, "zext")?
+                }
+                .into())
+            }
+            (BasicValueEnum::IntValue(v), BasicTypeEnum::FloatType(t)) if from_signed => {
+                Ok(self.builder.build_signed_int_to_float(v, t, "itof")?.into())
+            }
+            (BasicValueEnum::IntValue(v), BasicTypeEnum::FloatType(t)) => {
+                Ok(self.builder.build_unsigned_int_to_float(v, t, "utof")?.into())
+            }
+            (BasicValueEnum::FloatValue(v), BasicTypeEnum::IntType(t)) => {
+                let name = if is_signed(Some(to)) { "llvm.fptosi.sat" } else { "llvm.fptoui.sat" };
+                let intrinsic = self.intrinsic(name, &[t.into(), v.get_type().into()])?;
+                self.builder
+                    .build_call(intrinsic, &[v.into()], "ftoi")?
+                    .try_as_basic_value()
+                    .left()
+                    .ok_or_else(|| CodegenError::BuildError(format!("{} returned nothing", name)))
+            }
+            (BasicValueEnum::FloatValue(v), BasicTypeEnum::FloatType(_)) => Ok(v.into()),
+            _ => Err(CodegenError::BuildError(format!("Cannot cast this value to {}", to))),
+        }
+    }
+
+    // Prints why and stops the program, there's no unwinding
+    fn build_panic(&mut self, message: &str) -> Result<(), CodegenError> {
+        let text = self.builder.build_global_string_ptr(&format!("panic: {}\n", message), "panic")?;
+        self.build_intrinsic("__print", &[text.as_pointer_value().into()])?;
+        let trap = self.intrinsic("llvm.trap", &[])?;
+        self.builder.build_call(trap, &[], "")?;
+        self.builder.build_unreachable()?;
+        Ok(())
+    }
+
+    fn intrinsic(&self, name: &str, types: &[BasicTypeEnum<'ctx>]) -> Result<FunctionValue<'ctx>, CodegenError> {
+        Intrinsic::find(name)
+            .and_then(|intrinsic| intrinsic.get_declaration(&self.module, types))
+            .ok_or_else(|| CodegenError::BuildError(format!("LLVM has no intrinsic {}", name)))
+    }
+}
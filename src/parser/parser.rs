@@ -1,18 +1,29 @@
 use super::enums::*;
 use crate::lexer::enums::*;
-use crate::lexer::Token;
+use crate::lexer::{Span, StringPart, Token};
 
 #[derive(Clone)]
 pub struct Parser {
     tokens: Vec<Token>,
+    spans: Vec<Span>,
     current: usize,
+    type_params: Vec<String>, // Generic parameters of the definitions we're inside
 }
 
 // Converting our tokens into an AST basically
 // Our tokens are turned into actual syntax
 impl Parser {
-    pub fn new(tokens: Vec<Token>) -> Self {
-        Self { tokens, current: 0 }
+    pub fn new(tokens: Vec<Token>, spans: Vec<Span>) -> Self {
+        Self {
+            tokens,
+            spans,
+            current: 0,
+            type_params: Vec::new(),
+        }
+    }
+
+    fn current_span(&self) -> Span {
+        self.spans.get(self.current).copied().unwrap_or_default()
     }
 
     fn peek(&self) -> Option<&Token> {
@@ -24,28 +35,51 @@ impl Parser {
         self.tokens.get(self.current - 1)
     }
 
-    pub fn parse(&mut self) -> Option<Vec<Statement>> {
+    // Stops at the first statement that doesn't parse, with where that statement starts
+    pub fn parse(&mut self) -> Result<Vec<Statement>, Span> {
         let mut statements = Vec::new();
 
-        while self.peek().is_some() && self.peek()? != &Token::EndOfInput {
-            if let Some(statement) = self.parse_statement() {
-                statements.push(statement);
-            } else {
+        while self.peek().is_some_and(|token| token != &Token::EndOfInput) {
+            let start = self.current;
+            let span = self.current_span();
+            match self.parse_statement() {
+                // A statement that took no tokens would be parsed again forever
+                Some(statement) if self.current > start => statements.push(statement),
+                _ => return Err(span),
             }
         }
 
-        Some(statements)
+        Ok(statements)
     }
 
     fn parse_statement(&mut self) -> Option<Statement> {
+        let span = self.current_span();
+        let statement = self.parse_statement_kind()?;
+        Some(Statement::Spanned(span, Box::new(statement)))
+    }
+
+    fn parse_statement_kind(&mut self) -> Option<Statement> {
         match self.peek()? {
             Token::Keyword(keyword) => match keyword.as_str() {
                 "if" => return self.parse_if_statement(),
-                "print" => return self.parse_print_statement(),
                 "while" => return self.parse_while_statement(),
                 "for" => return self.parse_for_statement(),
                 "return" => return self.parse_return_statement(),
-                "fn" => return self.parse_function_definition(),
+                "fn" => {
+                    // `fn(x) { ... }` without a name is a lambda, not a definition
+                    if let Some(Token::LeftParen) = self.tokens.get(self.current + 1) {
+                        return self.parse_expression().map(Statement::Expression);
+                    }
+                    return self.parse_function_definition();
+                }
+                "struct" => return self.parse_struct_definition(),
+                "impl" => return self.parse_impl_block(),
+                "trait" => return self.parse_trait_definition(),
+                "let" => return self.parse_let_statement(),
+                "import" => return self.parse_import(),
+                "from" => return self.parse_from_import(),
+                "pub" => return self.parse_public(),
+                "true" | "false" | "null" => self.parse_expression().map(Statement::Expression),
                 _ => None,
             },
             Token::Identifier(_) => self.parse_assignment_or_expression(),
@@ -65,6 +99,79 @@ impl Parser {
         }
     }
 
+    // import math, import util.strings
+    fn parse_import(&mut self) -> Option<Statement> {
+        self.advance(); // consume 'import'
+        let module = self.parse_path()?;
+        Some(Statement::Import { module, items: None })
+    }
+
+    // from util import parse, format
+    fn parse_from_import(&mut self) -> Option<Statement> {
+        self.advance(); // consume 'from'
+        let module = self.parse_path()?;
+
+        match self.advance()? {
+            Token::Keyword(keyword) if keyword == "import" => {}
+            _ => return None,
+        }
+
+        let mut items = Vec::new();
+        loop {
+            match self.advance()? {
+                Token::Identifier(item) => items.push(item.clone()),
+                _ => return None,
+            }
+            if let Some(Token::Comma) = self.peek() {
+                self.advance();
+            } else {
+                break;
+            }
+        }
+
+        Some(Statement::Import { module, items: Some(items) })
+    }
+
+    // pub fn, pub struct and pub trait can be imported by other modules
+    fn parse_public(&mut self) -> Option<Statement> {
+        self.advance(); // consume 'pub'
+
+        let mut definition = match self.peek()? {
+            Token::Keyword(keyword) if keyword == "fn" => self.parse_function_definition()?,
+            Token::Keyword(keyword) if keyword == "struct" => self.parse_struct_definition()?,
+            Token::Keyword(keyword) if keyword == "trait" => self.parse_trait_definition()?,
+            _ => return None,
+        };
+
+        match &mut definition {
+            Statement::FunctionDefinition { public, .. }
+            | Statement::StructDefinition { public, .. }
+            | Statement::TraitDefinition { public, .. } => *public = true,
+            _ => {}
+        }
+        Some(definition)
+    }
+
+    // A name that can be qualified by the modules it's in, geo.Point or util.strings
+    fn parse_path(&mut self) -> Option<String> {
+        let Token::Identifier(first) = self.advance()? else {
+            return None;
+        };
+        let mut path = first.clone();
+        self.continue_path(&mut path);
+        Some(path)
+    }
+
+    fn continue_path(&mut self, path: &mut String) {
+        while let (Some(Token::Dot), Some(Token::Identifier(part))) =
+            (self.peek(), self.tokens.get(self.current + 1))
+        {
+            path.push('.');
+            path.push_str(part);
+            self.current += 2;
+        }
+    }
+
     fn parse_function_definition(&mut self) -> Option<Statement> {
         self.advance();
 
@@ -74,15 +181,107 @@ impl Parser {
             return None;
         };
 
+        let generics = self.parse_generics()?;
+        let outer_type_params = self.type_params.len();
+        self.type_params.extend(generics.iter().map(|generic| generic.name.clone()));
+
+        let definition = self.parse_function_signature_and_body(func_name, generics);
+        self.type_params.truncate(outer_type_params);
+        definition
+    }
+
+    fn parse_function_signature_and_body(&mut self, name: String, generics: Vec<Generic>) -> Option<Statement> {
         if self.advance()? != &Token::LeftParen {
             return None;
         }
 
+        let parameters = self.parse_parameters()?;
+
+        if self.advance()? != &Token::RightParen {
+            return None;
+        }
+
+        let return_type = self.parse_return_type()?;
+
+        let body = match self.parse_block()? {
+            Statement::Block(statements) => statements,
+            _ => return None,
+        };
+
+        Some(Statement::FunctionDefinition {
+            name,
+            public: false,
+            generics,
+            parameters,
+            return_type,
+            body,
+        })
+    }
+
+    // Optional <T, U: Show + Eq> after the name of a function or struct
+    fn parse_generics(&mut self) -> Option<Vec<Generic>> {
+        let mut generics = Vec::new();
+        if let Some(Token::ComparisonOperatorToken(ComparisonOperator::LessThan)) = self.peek() {
+            self.advance(); // consume '<'
+            while let Some(Token::Identifier(name)) = self.peek() {
+                let name = name.clone();
+                self.advance();
+
+                let mut bounds = Vec::new();
+                if let Some(Token::Colon) = self.peek() {
+                    self.advance();
+                    loop {
+                        bounds.push(self.parse_path()?);
+                        if let Some(Token::MathOperatorToken(MathOperator::Add)) = self.peek() {
+                            self.advance();
+                        } else {
+                            break;
+                        }
+                    }
+                }
+                generics.push(Generic { name, bounds });
+
+                if let Some(Token::Comma) = self.peek() {
+                    self.advance();
+                } else {
+                    break;
+                }
+            }
+            self.expect_closing_angle()?;
+        }
+        Some(generics)
+    }
+
+    // `>>` is a shift as far as the lexer knows, but closes two lists in Pair<int, Pair<int, int>>
+    fn expect_closing_angle(&mut self) -> Option<()> {
+        match self.peek()? {
+            Token::ComparisonOperatorToken(ComparisonOperator::GreaterThan) => {
+                self.advance();
+                Some(())
+            }
+            Token::BitwiseOperatorToken(BitwiseOperator::RightShift) => {
+                self.tokens[self.current] = Token::ComparisonOperatorToken(ComparisonOperator::GreaterThan);
+                Some(())
+            }
+            _ => None,
+        }
+    }
+
+    // a, b: int, c: fn(int) -> int
+    fn parse_parameters(&mut self) -> Option<Vec<Parameter>> {
         let mut parameters = Vec::new();
         while let Some(Token::Identifier(param)) = self.peek() {
-            parameters.push(param.clone());
+            let name = param.clone();
             self.advance();
 
+            let ty = if let Some(Token::Colon) = self.peek() {
+                self.advance(); // consume ':'
+                Some(self.parse_type()?)
+            } else {
+                None
+            };
+            parameters.push(Parameter { name, ty, symbol: None });
+
             if let Some(Token::Comma) = self.peek() {
                 self.advance();
             } else {
@@ -90,20 +289,231 @@ impl Parser {
             }
         }
 
-        if self.advance()? != &Token::RightParen {
+        Some(parameters)
+    }
+
+    // Optional `-> type` after a parameter list
+    fn parse_return_type(&mut self) -> Option<Option<Type>> {
+        if let Some(Token::Arrow) = self.peek() {
+            self.advance(); // consume '->'
+            Some(Some(self.parse_type()?))
+        } else {
+            Some(None)
+        }
+    }
+
+    fn parse_type(&mut self) -> Option<Type> {
+        match self.advance()?.clone() {
+            Token::Identifier(name) if self.type_params.contains(&name) => Some(Type::Param(name)),
+            Token::Identifier(mut name) => {
+                self.continue_path(&mut name);
+                let mut ty = Type::from_name(&name);
+
+                // Pair<int, float>
+                if let Type::Named(_, args) = &mut ty {
+                    if let Some(Token::ComparisonOperatorToken(ComparisonOperator::LessThan)) = self.peek() {
+                        self.advance(); // consume '<'
+                        loop {
+                            args.push(self.parse_type()?);
+                            if let Some(Token::Comma) = self.peek() {
+                                self.advance();
+                            } else {
+                                break;
+                            }
+                        }
+                        self.expect_closing_angle()?;
+                    }
+                }
+
+                Some(ty)
+            }
+            // [int]
+            Token::LeftBracket => {
+                let element = self.parse_type()?;
+                if self.advance()? != &Token::RightBracket {
+                    return None;
+                }
+                Some(Type::List(Box::new(element)))
+            }
+            Token::Keyword(keyword) if keyword == "null" => Some(Type::Null),
+            // dyn Show
+            Token::Keyword(keyword) if keyword == "dyn" => Some(Type::Dyn(self.parse_path()?)),
+            // fn(int, float) -> bool
+            Token::Keyword(keyword) if keyword == "fn" => {
+                if self.advance()? != &Token::LeftParen {
+                    return None;
+                }
+
+                let mut parameters = Vec::new();
+                while self.peek()? != &Token::RightParen {
+                    parameters.push(self.parse_type()?);
+                    if let Some(Token::Comma) = self.peek() {
+                        self.advance();
+                    } else {
+                        break;
+                    }
+                }
+
+                if self.advance()? != &Token::RightParen {
+                    return None;
+                }
+
+                let return_type = self.parse_return_type()?.unwrap_or(Type::Null);
+                Some(Type::Function(parameters, Box::new(return_type)))
+            }
+            _ => None,
+        }
+    }
+
+    // let x: float = 1.0
+    fn parse_let_statement(&mut self) -> Option<Statement> {
+        self.advance(); // consume 'let'
+
+        let name = if let Token::Identifier(name) = self.advance()? {
+            name.clone()
+        } else {
+            return None;
+        };
+
+        let ty = if let Some(Token::Colon) = self.peek() {
+            self.advance(); // consume ':'
+            Some(self.parse_type()?)
+        } else {
+            None
+        };
+
+        if self.advance()? != &Token::OperatorToken(Operator::Equals) {
             return None;
         }
 
-        let body = match self.parse_block()? {
-            Statement::Block(statements) => statements,
-            _ => return None,
+        let value = self.parse_expression()?;
+        Some(Statement::Let { name, symbol: None, ty, value })
+    }
+
+    // struct Point { x: float, y: float }
+    fn parse_struct_definition(&mut self) -> Option<Statement> {
+        self.advance(); // consume 'struct'
+
+        let name = if let Token::Identifier(name) = self.advance()? {
+            name.clone()
+        } else {
+            return None;
         };
 
-        Some(Statement::FunctionDefinition {
-            name: func_name,
-            parameters,
-            body,
-        })
+        let generics = self.parse_generics()?;
+
+        if self.advance()? != &Token::LeftBrace {
+            return None;
+        }
+
+        // Fields are written just like parameters, x: float, y: float
+        let outer_type_params = self.type_params.len();
+        self.type_params.extend(generics.iter().map(|generic| generic.name.clone()));
+        let fields = self.parse_parameters();
+        self.type_params.truncate(outer_type_params);
+        let fields = fields?;
+
+        if self.advance()? != &Token::RightBrace {
+            return None;
+        }
+
+        Some(Statement::StructDefinition { name, public: false, generics, fields })
+    }
+
+    // impl Point { fn norm(self) { ... } }
+    fn parse_impl_block(&mut self) -> Option<Statement> {
+        self.advance(); // consume 'impl'
+
+        let mut type_name = self.parse_path()?;
+
+        // impl Show for Point, the first name was the trait
+        let mut trait_name = None;
+        if let Some(Token::Keyword(keyword)) = self.peek() {
+            if keyword == "for" {
+                self.advance();
+                let implementor = self.parse_path()?;
+                trait_name = Some(std::mem::replace(&mut type_name, implementor));
+            }
+        }
+
+        if self.advance()? != &Token::LeftBrace {
+            return None;
+        }
+
+        let mut methods = Vec::new();
+        loop {
+            match self.peek()? {
+                Token::RightBrace => {
+                    self.advance();
+                    break;
+                }
+                Token::Comment(_) => {
+                    self.advance();
+                }
+                Token::Keyword(keyword) if keyword == "fn" => {
+                    let span = self.current_span();
+                    let method = self.parse_function_definition()?;
+                    methods.push(Statement::Spanned(span, Box::new(method)));
+                }
+                _ => return None,
+            }
+        }
+
+        Some(Statement::Impl { type_name, trait_name, methods })
+    }
+
+    // trait Show { fn show(self) -> str }
+    fn parse_trait_definition(&mut self) -> Option<Statement> {
+        self.advance(); // consume 'trait'
+
+        let name = if let Token::Identifier(name) = self.advance()? {
+            name.clone()
+        } else {
+            return None;
+        };
+
+        if self.advance()? != &Token::LeftBrace {
+            return None;
+        }
+
+        // Only signatures, every impl brings its own bodies
+        let mut methods = Vec::new();
+        loop {
+            match self.peek()? {
+                Token::RightBrace => {
+                    self.advance();
+                    break;
+                }
+                Token::Comment(_) => {
+                    self.advance();
+                }
+                Token::Keyword(keyword) if keyword == "fn" => {
+                    self.advance();
+                    let Token::Identifier(method_name) = self.advance()? else {
+                        return None;
+                    };
+                    let method_name = method_name.clone();
+
+                    if self.advance()? != &Token::LeftParen {
+                        return None;
+                    }
+                    let parameters = self.parse_parameters()?;
+                    if self.advance()? != &Token::RightParen {
+                        return None;
+                    }
+                    let return_type = self.parse_return_type()?;
+
+                    methods.push(TraitMethod {
+                        name: method_name,
+                        parameters,
+                        return_type,
+                    });
+                }
+                _ => return None,
+            }
+        }
+
+        Some(Statement::TraitDefinition { name, public: false, methods })
     }
 
     fn parse_block(&mut self) -> Option<Statement> {
@@ -117,10 +527,10 @@ impl Parser {
                     break;
                 }
 
-                if let Some(statement) = self.parse_statement() {
-                    statements.push(statement);
-                } else {
-                    return None;
+                let start = self.current;
+                match self.parse_statement() {
+                    Some(statement) if self.current > start => statements.push(statement),
+                    _ => return None,
                 }
             }
 
@@ -130,21 +540,6 @@ impl Parser {
         }
     }
 
-    fn parse_print_statement(&mut self) -> Option<Statement> {
-        if let Some(Token::Keyword(ref keyword)) = self.peek() {
-            if keyword == "print" {
-                self.advance();
-                let condition: Expression = self.parse_expression()?;
-
-                return Some(Statement::Expression(Expression::FunctionCall(
-                    "print".into(),
-                    vec![condition],
-                )));
-            }
-        }
-        None
-    }
-
     fn parse_if_statement(&mut self) -> Option<Statement> {
         if let Some(Token::Keyword(ref keyword)) = self.peek() {
             if keyword == "if" {
@@ -199,6 +594,8 @@ impl Parser {
     }
 
     fn parse_assignment_or_expression(&mut self) -> Option<Statement> {
+        let start = self.current;
+
         // Parse the first identifier (function name or variable name)
         let name = if let Token::Identifier(name) = self.advance()? {
             name.clone()
@@ -210,7 +607,11 @@ impl Parser {
 
         // Parse potential parameters
         while let Some(Token::Identifier(param)) = self.peek() {
-            parameters.push(param.clone());
+            parameters.push(Parameter {
+                name: param.clone(),
+                ty: None,
+                symbol: None,
+            });
             self.advance(); // Consume the parameter
 
             if let Some(Token::OperatorToken(Operator::WalrusEqual)) = self.peek() {
@@ -221,7 +622,10 @@ impl Parser {
 
                 return Some(Statement::FunctionDefinition {
                     name,
+                    public: false,
+                    generics: Vec::new(),
                     parameters,
+                    return_type: None,
                     body: vec![Statement::Return(Box::new(Some(body)))],
                 });
             }
@@ -233,11 +637,26 @@ impl Parser {
 
             let expr = self.parse_expression()?;
             Some(Statement::Expression(Expression::Assignment(
-                Box::new(Expression::Identifier(name)),
+                Box::new(Expression::Identifier(name, None)),
                 Box::new(expr),
             )))
         } else {
+            // Not an assignment, so rewind and parse the whole thing as an expression
+            self.current = start;
             let expr = self.parse_expression()?;
+
+            // Field or element assignment, e.g. p.x = 1 or xs[0] = 1
+            if let Expression::FieldAccess(..) | Expression::Index(..) = expr {
+                if let Some(Token::OperatorToken(Operator::Equals)) = self.peek() {
+                    self.advance(); // Consume `=`
+                    let value = self.parse_expression()?;
+                    return Some(Statement::Expression(Expression::Assignment(
+                        Box::new(expr),
+                        Box::new(value),
+                    )));
+                }
+            }
+
             Some(Statement::Expression(expr))
         }
     }
@@ -252,7 +671,8 @@ impl Parser {
         while let Some(token) = self.peek() {
             match token {
                 Token::LogicalOperatorToken(op @ LogicalOperator::And)
-                | Token::LogicalOperatorToken(op @ LogicalOperator::Or) => {
+                | Token::LogicalOperatorToken(op @ LogicalOperator::Or)
+                | Token::LogicalOperatorToken(op @ LogicalOperator::Xor) => {
                     let other_op = op.to_owned();
                     self.advance(); // consume the operator
                     let right = self.parse_bitwise()?;
@@ -277,7 +697,7 @@ impl Parser {
                     let other_op = op.to_owned();
                     self.advance(); // consume the operator
                     let right = self.parse_comparison()?;
-                    left = Expression::BitwiseOp(Box::new(left), other_op, Box::new(right));
+                    left = Expression::BitwiseOp(Box::new(left), other_op, Box::new(right), None);
                 }
                 _ => break,
             }
@@ -318,30 +738,25 @@ impl Parser {
         if let Some(Token::Keyword(ref keyword)) = self.peek() {
             if keyword == "for" {
                 self.advance(); // consume 'for'
-                println!("here");
                 let var = if let Token::Identifier(name) = self.advance()? {
                     name.clone()
                 } else {
                     return None;
                 };
 
-                println!("here");
                 if self.advance()? != &Token::Keyword("in".to_string()) {
                     return None;
                 }
 
-                println!("here");
                 let iterable = self.parse_expression()?; // parse iterable expression
 
-                println!("here");
                 let body = match self.parse_block()? {
                     Statement::Block(stmts) => stmts,
                     _ => return None,
                 };
 
-                println!("here");
                 return Some(Statement::Expression(Expression::For(
-                    var,
+                    Parameter { name: var, ty: None, symbol: None },
                     Box::new(iterable),
                     body,
                 )));
@@ -364,7 +779,7 @@ impl Parser {
                     let other_op = op.to_owned();
                     self.advance(); // consume the operator
                     let right = self.parse_math()?;
-                    left = Expression::ComparisonOp(Box::new(left), other_op, Box::new(right));
+                    left = Expression::ComparisonOp(Box::new(left), other_op, Box::new(right), None);
                 }
                 _ => {}
             }
@@ -385,7 +800,7 @@ impl Parser {
                     let other_op = op.to_owned();
                     self.advance(); // consume the operator
                     let right = self.parse_factor()?;
-                    left = Expression::MathOp(Box::new(left), other_op, Box::new(right));
+                    left = Expression::MathOp(Box::new(left), other_op, Box::new(right), None);
                 }
                 _ => break,
             }
@@ -394,32 +809,99 @@ impl Parser {
     }
 
     fn parse_factor(&mut self) -> Option<Expression> {
-        let token = self.advance()?;
-        match token {
-            Token::Number(n) => Some(Expression::LiteralValue(LiteralValue::Number(*n))),
-            Token::Identifier(name) => {
-                let other_name = name.clone();
-                if let Some(Token::LeftParen) = self.peek() {
-                    self.advance(); // consume '('
-                    let mut args = Vec::new();
+        let mut expr = self.parse_primary()?;
 
-                    // Parse function call arguments
-                    while let Some(arg) = self.parse_expression() {
-                        args.push(arg);
-                        if let Some(Token::Comma) = self.peek() {
-                            self.advance(); // consume ','
-                        } else {
-                            break;
-                        }
+        // Calls, member accesses and indexing can be chained onto anything,
+        // e.g. make_adder(1)(2), (|x| x)(3), p.scale(2).x or grid[0][1]
+        loop {
+            match self.peek() {
+                Some(Token::LeftBracket) => {
+                    self.advance(); // consume '['
+                    let index = self.parse_expression()?;
+                    if self.advance()? != &Token::RightBracket {
+                        return None;
                     }
+                    expr = Expression::Index(Box::new(expr), Box::new(index));
+                }
+                Some(Token::LeftParen) => {
+                    self.advance(); // consume '('
+                    let args = self.parse_arguments()?;
+                    expr = Expression::FunctionCall(Box::new(expr), args);
+                }
+                Some(Token::Dot) => {
+                    self.advance(); // consume '.'
+                    let member = if let Token::Identifier(name) = self.advance()? {
+                        name.clone()
+                    } else {
+                        return None;
+                    };
 
-                    if self.advance()? != &Token::RightParen {
-                        return None; // expect closing parenthesis
+                    if let Some(Token::LeftParen) = self.peek() {
+                        self.advance(); // consume '('
+                        let args = self.parse_arguments()?;
+                        expr = Expression::MethodCall(Box::new(expr), member, args, None);
+                    } else {
+                        expr = Expression::FieldAccess(Box::new(expr), member);
                     }
+                }
+                // x as u8
+                Some(Token::Keyword(keyword)) if keyword == "as" => {
+                    self.advance(); // consume 'as'
+                    let ty = self.parse_type()?;
+                    expr = Expression::Cast(Box::new(expr), ty, None);
+                }
+                _ => break,
+            }
+        }
 
-                    Some(Expression::FunctionCall(other_name, args))
-                } else {
-                    Some(Expression::Identifier(other_name))
+        Some(expr)
+    }
+
+    fn parse_arguments(&mut self) -> Option<Vec<Expression>> {
+        let mut args = Vec::new();
+
+        if let Some(Token::RightParen) = self.peek() {
+            self.advance();
+            return Some(args);
+        }
+
+        // Parse function call arguments
+        while let Some(arg) = self.parse_expression() {
+            args.push(arg);
+            if let Some(Token::Comma) = self.peek() {
+                self.advance(); // consume ','
+            } else {
+                break;
+            }
+        }
+
+        if self.advance()? != &Token::RightParen {
+            return None; // expect closing parenthesis
+        }
+
+        Some(args)
+    }
+
+    fn parse_primary(&mut self) -> Option<Expression> {
+        let token = self.advance()?;
+        match token {
+            Token::Number(n) => Some(Expression::LiteralValue(LiteralValue::Number(*n))),
+            Token::Integer(n) => Some(Expression::LiteralValue(LiteralValue::Integer(*n, None))),
+            Token::StringLiteral(value) => Some(Expression::LiteralValue(LiteralValue::String(value.clone()))),
+            Token::InterpolatedString(parts) => {
+                let parts = parts.clone();
+                self.parse_interpolation(parts)
+            }
+            Token::Keyword(keyword) if keyword == "true" => Some(Expression::LiteralValue(LiteralValue::Boolean(true))),
+            Token::Keyword(keyword) if keyword == "false" => Some(Expression::LiteralValue(LiteralValue::Boolean(false))),
+            Token::Keyword(keyword) if keyword == "null" => Some(Expression::LiteralValue(LiteralValue::Null)),
+            Token::Identifier(name) => Some(Expression::Identifier(name.clone(), None)),
+            // `x = if a > b { a } else { b }`
+            Token::Keyword(keyword) if keyword == "if" => {
+                self.current -= 1;
+                match self.parse_if_statement()? {
+                    Statement::Expression(expr) => Some(expr),
+                    _ => None,
                 }
             }
             Token::LeftParen => {
@@ -429,7 +911,194 @@ impl Parser {
                 }
                 Some(Expression::Grouping(Box::new(expr)))
             }
+            // [1, 2, 3]
+            Token::LeftBracket => {
+                let mut elements = Vec::new();
+                while self.peek()? != &Token::RightBracket {
+                    elements.push(self.parse_expression()?);
+                    if let Some(Token::Comma) = self.peek() {
+                        self.advance();
+                    } else {
+                        break;
+                    }
+                }
+
+                if self.advance()? != &Token::RightBracket {
+                    return None;
+                }
+                Some(Expression::List(elements))
+            }
+            // |x, y| x + y
+            Token::BitwiseOperatorToken(BitwiseOperator::Or) => {
+                let parameters = self.parse_parameters()?;
+
+                if self.advance()? != &Token::BitwiseOperatorToken(BitwiseOperator::Or) {
+                    return None;
+                }
+
+                self.parse_lambda_body(parameters)
+            }
+            // || 42, the lexer hands us a logical or here
+            Token::LogicalOperatorToken(LogicalOperator::Or) => self.parse_lambda_body(Vec::new()),
+            // fn(x, y) { return x + y }
+            Token::Keyword(keyword) if keyword == "fn" => {
+                if self.advance()? != &Token::LeftParen {
+                    return None;
+                }
+
+                let parameters = self.parse_parameters()?;
+
+                if self.advance()? != &Token::RightParen {
+                    return None;
+                }
+
+                let return_type = self.parse_return_type()?;
+
+                let body = match self.parse_block()? {
+                    Statement::Block(statements) => statements,
+                    _ => return None,
+                };
+
+                Some(Expression::Lambda(parameters, return_type, body))
+            }
             _ => None,
         }
     }
+
+    // Each {expression} has its own tokens, and has to be exactly one expression. A string
+    // with only {} placeholders is a format template
+    fn parse_interpolation(&mut self, parts: Vec<StringPart>) -> Option<Expression> {
+        if !parts.iter().any(|part| matches!(part, StringPart::Code(..))) {
+            let mut pieces = vec![String::new()];
+            for part in parts {
+                match part {
+                    StringPart::Text(text) => pieces.last_mut()?.push_str(&text),
+                    _ => pieces.push(String::new()),
+                }
+            }
+            return Some(Expression::LiteralValue(LiteralValue::Template(pieces)));
+        }
+
+        let mut pieces = Vec::new();
+        for part in parts {
+            match part {
+                StringPart::Text(text) => pieces.push(Expression::LiteralValue(LiteralValue::String(text))),
+                StringPart::Placeholder => pieces.push(Expression::LiteralValue(LiteralValue::String("{}".into()))),
+                StringPart::Code(tokens, spans) => {
+                    let mut parser = Parser::new(tokens, spans);
+                    parser.type_params = self.type_params.clone();
+                    pieces.push(parser.parse_expression()?);
+                    if parser.peek()? != &Token::EndOfInput {
+                        return None;
+                    }
+                }
+            }
+        }
+        Some(Expression::StringInterpolation(pieces))
+    }
+
+    fn parse_lambda_body(&mut self, parameters: Vec<Parameter>) -> Option<Expression> {
+        // |x: int| -> int { ... }
+        let return_type = self.parse_return_type()?;
+
+        // A block body is used as is, a bare expression is its own return value
+        let body = if let Some(Token::LeftBrace) = self.peek() {
+            match self.parse_block()? {
+                Statement::Block(statements) => statements,
+                _ => return None,
+            }
+        } else {
+            vec![Statement::Return(Box::new(Some(self.parse_expression()?)))]
+        };
+
+        Some(Expression::Lambda(parameters, return_type, body))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::Lexer;
+
+    fn parse(source: &str) -> Vec<Statement> {
+        let mut lexer = Lexer::new(source);
+        let tokens = lexer.tokenize().expect("test source should lex").to_vec();
+        Parser::new(tokens, lexer.spans().to_vec()).parse().expect("test source should parse")
+    }
+
+    #[test]
+    fn annotations_are_optional() {
+        let ast = parse("fn add(a: int, b) -> float { return a + b }\n");
+        let Statement::FunctionDefinition { parameters, return_type, .. } = ast[0].node() else {
+            panic!("expected a function");
+        };
+        assert_eq!(parameters[0].ty, Some(Type::Int));
+        assert_eq!(parameters[1].ty, None);
+        assert_eq!(*return_type, Some(Type::Float));
+    }
+
+    #[test]
+    fn variables_can_be_annotated() {
+        let ast = parse("let xs: [u8] = [1]\nlet f: fn(int) -> str = g\nlet y = 2\n");
+        let types: Vec<Option<Type>> = ast
+            .iter()
+            .map(|stmt| match stmt.node() {
+                Statement::Let { ty, .. } => ty.clone(),
+                other => panic!("expected a let, got {:?}", other),
+            })
+            .collect();
+        assert_eq!(
+            types,
+            vec![
+                Some(Type::List(Box::new(Type::Integer(false, 8)))),
+                Some(Type::Function(vec![Type::Int], Box::new(Type::Str))),
+                None,
+            ]
+        );
+    }
+
+    fn let_value(source: &str) -> Expression {
+        match parse(source)[0].node() {
+            Statement::Let { value, .. } => value.clone(),
+            other => panic!("expected a let, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn placeholders_make_a_template() {
+        let Expression::LiteralValue(LiteralValue::Template(pieces)) = let_value("let t = \"{{}} = {}!\"\n") else {
+            panic!("expected a template");
+        };
+        assert_eq!(pieces, vec!["{} = ".to_string(), "!".to_string()]);
+    }
+
+    #[test]
+    fn placeholders_next_to_expressions_are_text() {
+        let Expression::StringInterpolation(parts) = let_value("let t = \"{a} {}\"\n") else {
+            panic!("expected an interpolation");
+        };
+        assert!(matches!(&parts[0], Expression::Identifier(name, _) if name == "a"));
+        assert!(matches!(&parts[2], Expression::LiteralValue(LiteralValue::String(text)) if text == "{}"));
+    }
+
+    fn parse_error(source: &str) -> Span {
+        let mut lexer = Lexer::new(source);
+        let tokens = lexer.tokenize().expect("test source should lex").to_vec();
+        Parser::new(tokens, lexer.spans().to_vec()).parse().unwrap_err()
+    }
+
+    #[test]
+    fn a_failed_statement_is_an_error() {
+        let span = parse_error("let x = 1\nlet = 2\nlet y = 3\n");
+        assert_eq!((span.line, span.column), (2, 1));
+    }
+
+    #[test]
+    fn stray_keywords_do_not_hang() {
+        for keyword in ["else", "in", "as", "dyn"] {
+            let span = parse_error(&format!("{} x\n", keyword));
+            assert_eq!((span.line, span.column), (1, 1), "{}", keyword);
+        }
+    }
 }
+
@@ -0,0 +1,287 @@
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+pub const MANIFEST_NAME: &str = "lumina.toml";
+
+#[derive(Debug)]
+pub enum ProjectError {
+    NotFound(PathBuf), // Where we started looking
+    Io(PathBuf, std::io::Error),
+    Invalid(PathBuf, String),
+    UnknownProfile(String),
+    OptLevel(String, u8),             // Profile, the level it asked for
+    DependencyCycle(Vec<String>),
+    DependencyName(String, PathBuf, PathBuf), // Two different packages with one name
+}
+
+impl fmt::Display for ProjectError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ProjectError::NotFound(start) => write!(
+                f,
+                "could not find {} in {} or any directory above it",
+                MANIFEST_NAME,
+                start.display()
+            ),
+            ProjectError::Io(path, err) => write!(f, "{}: {}", path.display(), err),
+            ProjectError::Invalid(path, message) => write!(f, "{}: {}", path.display(), message),
+            ProjectError::UnknownProfile(name) => write!(f, "no profile called '{}'", name),
+            ProjectError::OptLevel(name, level) => {
+                write!(f, "profile '{}' asks for opt-level {}, it only goes up to 3", name, level)
+            }
+            ProjectError::DependencyCycle(names) => write!(f, "dependency cycle: {}", names.join(" -> ")),
+            ProjectError::DependencyName(name, first, second) => write!(
+                f,
+                "two packages are called '{}': {} and {}",
+                name,
+                first.display(),
+                second.display()
+            ),
+        }
+    }
+}
+
+// lumina.toml, as written
+//
+// [package]
+// name = "hello"
+// version = "0.1.0"
+// entry = "src/main.lum"    # the default
+// source-dirs = ["src"]     # the default, searched for imports
+//
+// [dependencies]
+// geometry = { path = "../geometry" }
+//
+// [profile.release]
+// opt-level = 2
+// overflow-checks = true    # int overflow panics, on by default outside release
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Manifest {
+    pub package: Package,
+    #[serde(default)]
+    pub dependencies: HashMap<String, Dependency>,
+    #[serde(default)]
+    pub profile: HashMap<String, ProfileOverrides>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct Package {
+    pub name: String,
+    pub version: String,
+    #[serde(default = "default_entry")]
+    pub entry: PathBuf,
+    #[serde(default = "default_source_dirs")]
+    pub source_dirs: Vec<PathBuf>,
+}
+
+fn default_entry() -> PathBuf {
+    PathBuf::from("src/main.lum")
+}
+
+fn default_source_dirs() -> Vec<PathBuf> {
+    vec![PathBuf::from("src")]
+}
+
+// Only local packages for now, relative to the manifest naming them
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Dependency {
+    pub path: PathBuf,
+}
+
+// Whatever a profile in the manifest changes, the rest comes from the built in profile
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct ProfileOverrides {
+    pub opt_level: Option<u8>,
+    pub debug: Option<bool>,
+    pub overflow_checks: Option<bool>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Profile {
+    pub name: String,
+    pub opt_level: u8,
+    pub debug: bool, // Emit debug info
+    pub overflow_checks: bool,
+}
+
+impl Profile {
+    // `debug` and `release` always exist, any other profile starts out like `debug`
+    pub fn builtin(name: &str) -> Profile {
+        let release = name == "release";
+        Profile {
+            name: name.to_string(),
+            opt_level: if release { 3 } else { 0 },
+            debug: !release,
+            overflow_checks: !release,
+        }
+    }
+}
+
+// A package on disk, its manifest and the directory the manifest is in
+#[derive(Debug)]
+pub struct Project {
+    pub root: PathBuf,
+    pub manifest: Manifest,
+}
+
+impl Project {
+    // The closest lumina.toml in `start` or a directory above it
+    pub fn find(start: &Path) -> Result<Project, ProjectError> {
+        let start = fs::canonicalize(start).map_err(|err| ProjectError::Io(start.to_path_buf(), err))?;
+        match start.ancestors().map(|dir| dir.join(MANIFEST_NAME)).find(|path| path.is_file()) {
+            Some(path) => Project::load(&path),
+            None => Err(ProjectError::NotFound(start)),
+        }
+    }
+
+    pub fn load(manifest_path: &Path) -> Result<Project, ProjectError> {
+        let source =
+            fs::read_to_string(manifest_path).map_err(|err| ProjectError::Io(manifest_path.to_path_buf(), err))?;
+        let manifest: Manifest = toml::from_str(&source)
+            .map_err(|err| ProjectError::Invalid(manifest_path.to_path_buf(), err.to_string()))?;
+        let root = manifest_path.parent().unwrap_or(Path::new(".")).to_path_buf();
+        Ok(Project { root, manifest })
+    }
+
+    pub fn name(&self) -> &str {
+        &self.manifest.package.name
+    }
+
+    pub fn entry(&self) -> PathBuf {
+        self.root.join(&self.manifest.package.entry)
+    }
+
+    // Where imports that aren't next to the importing file are looked for
+    pub fn source_dirs(&self) -> Vec<PathBuf> {
+        self.manifest.package.source_dirs.iter().map(|dir| self.root.join(dir)).collect()
+    }
+
+    pub fn target_dir(&self, profile: &Profile) -> PathBuf {
+        self.root.join("target").join(&profile.name)
+    }
+
+    pub fn profile(&self, name: &str) -> Result<Profile, ProjectError> {
+        let overrides = self.manifest.profile.get(name);
+        if overrides.is_none() && name != "debug" && name != "release" {
+            return Err(ProjectError::UnknownProfile(name.to_string()));
+        }
+
+        let mut profile = Profile::builtin(name);
+        if let Some(overrides) = overrides {
+            profile.opt_level = overrides.opt_level.unwrap_or(profile.opt_level);
+            profile.debug = overrides.debug.unwrap_or(profile.debug);
+            profile.overflow_checks = overrides.overflow_checks.unwrap_or(profile.overflow_checks);
+        }
+        if profile.opt_level > 3 {
+            return Err(ProjectError::OptLevel(name.to_string(), profile.opt_level));
+        }
+        Ok(profile)
+    }
+
+    // Every package this one depends on, directly or not, by the name it's imported under
+    pub fn dependencies(&self) -> Result<Vec<(String, Project)>, ProjectError> {
+        let mut found = Vec::new();
+        let mut path = vec![self.name().to_string()];
+        self.collect_dependencies(&mut found, &mut path)?;
+        Ok(found)
+    }
+
+    fn collect_dependencies(
+        &self,
+        found: &mut Vec<(String, Project)>,
+        path: &mut Vec<String>,
+    ) -> Result<(), ProjectError> {
+        let mut names: Vec<&String> = self.manifest.dependencies.keys().collect();
+        names.sort();
+
+        for name in names {
+            let dependency = &self.manifest.dependencies[name];
+            if path.contains(name) {
+                path.push(name.clone());
+                return Err(ProjectError::DependencyCycle(path.clone()));
+            }
+
+            let manifest_path = self.root.join(&dependency.path).join(MANIFEST_NAME);
+            let project = Project::load(&manifest_path)?;
+            let root = fs::canonicalize(&project.root).map_err(|err| ProjectError::Io(project.root.clone(), err))?;
+
+            if let Some((_, existing)) = found.iter().find(|(existing, _)| existing == name) {
+                let existing_root = fs::canonicalize(&existing.root).unwrap_or_else(|_| existing.root.clone());
+                if existing_root != root {
+                    return Err(ProjectError::DependencyName(name.clone(), existing_root, root));
+                }
+                continue;
+            }
+
+            path.push(name.clone());
+            project.collect_dependencies(found, path)?;
+            path.pop();
+            found.push((name.clone(), project));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn project(manifest: &str) -> Project {
+        Project {
+            root: PathBuf::from("/work/hello"),
+            manifest: toml::from_str(manifest).expect("test manifest should be valid"),
+        }
+    }
+
+    #[test]
+    fn package_paths_have_defaults() {
+        let project = project("[package]\nname = \"hello\"\nversion = \"0.1.0\"\n");
+        assert_eq!(project.name(), "hello");
+        assert_eq!(project.entry(), PathBuf::from("/work/hello/src/main.lum"));
+        assert_eq!(project.source_dirs(), vec![PathBuf::from("/work/hello/src")]);
+        assert!(toml::from_str::<Manifest>("[package]\nname = \"x\"\nversion = \"1\"\nauthor = \"me\"\n").is_err());
+    }
+
+    #[test]
+    fn profiles_override_the_builtin_ones() {
+        let project = project(
+            "[package]\nname = \"hello\"\nversion = \"0.1.0\"\n\n\
+             [profile.release]\noverflow-checks = true\n\n\
+             [profile.fast]\nopt-level = 2\n\n\
+             [profile.silly]\nopt-level = 9\n",
+        );
+        let release = project.profile("release").unwrap();
+        assert_eq!((release.opt_level, release.debug, release.overflow_checks), (3, false, true));
+        let fast = project.profile("fast").unwrap();
+        assert_eq!((fast.opt_level, fast.debug, fast.overflow_checks), (2, true, true));
+        assert_eq!(project.profile("debug").unwrap(), Profile::builtin("debug"));
+        assert!(matches!(project.profile("silly"), Err(ProjectError::OptLevel(_, 9))));
+        assert!(matches!(project.profile("nope"), Err(ProjectError::UnknownProfile(_))));
+    }
+
+    #[test]
+    fn dependency_cycles_are_errors() {
+        let root = std::env::temp_dir().join(format!("lumina-manifest-cycle-{}", std::process::id()));
+        for (name, other) in [("a", "b"), ("b", "a")] {
+            fs::create_dir_all(root.join(name)).unwrap();
+            let manifest = format!(
+                "[package]\nname = \"{}\"\nversion = \"0.1.0\"\n\n[dependencies]\n{} = {{ path = \"../{}\" }}\n",
+                name, other, other
+            );
+            fs::write(root.join(name).join(MANIFEST_NAME), manifest).unwrap();
+        }
+        let result = Project::load(&root.join("a").join(MANIFEST_NAME)).and_then(|project| project.dependencies());
+        let _ = fs::remove_dir_all(&root);
+        match result {
+            Err(ProjectError::DependencyCycle(names)) => assert_eq!(names, vec!["a", "b", "a"]),
+            other => panic!("expected a cycle, got {:?}", other.map(|_| ())),
+        }
+    }
+}
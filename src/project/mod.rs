@@ -0,0 +1,3 @@
+mod manifest;
+
+pub use manifest::{Manifest, Profile, Project, ProjectError, MANIFEST_NAME};
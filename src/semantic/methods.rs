@@ -0,0 +1,231 @@
+use crate::parser::enums::{Statement, TraitMethod, Type};
+use std::collections::{HashMap, HashSet};
+
+// Types that exist without a struct definition and can still get impl blocks
+pub const BUILTIN_TYPES: [&str; 4] = ["int", "float", "bool", "str"];
+
+// Every type implements Show, the ones without an impl are shown by what they're made of
+pub const SHOW: &str = "Show";
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum MethodError {
+    UnknownType(String),
+    DuplicateMethod(String, String),
+    NotAMethod(String),
+    GenericType(String),
+    GenericMethod(String, String),
+    UnknownTrait(String),
+    DuplicateTrait(String),
+    MissingTraitMethod(String, String, String), // Type, trait, method
+    NotInTrait(String, String),                 // Trait, method
+}
+
+// Methods live in the module as ordinary functions under a mangled name
+pub fn mangle_method(type_name: &str, method: &str) -> String {
+    format!("{}.{}", type_name, method)
+}
+
+// Which methods each type has, by type name then method name, and which traits they implement
+#[derive(Debug, Default)]
+pub struct MethodTable {
+    methods: HashMap<String, HashMap<String, String>>,
+    traits: HashMap<String, Vec<TraitMethod>>,
+    implementations: HashSet<(String, String)>, // (type, trait)
+}
+
+impl MethodTable {
+    // Pulls every trait and impl block out of the program, checks them and turns the
+    // methods into top level functions with mangled names
+    pub fn build(ast: Vec<Statement>) -> Result<(Vec<Statement>, MethodTable), MethodError> {
+        let mut struct_names = Vec::new();
+        let mut generic_structs = Vec::new();
+        for stmt in ast.iter() {
+            if let Statement::StructDefinition { name, generics, .. } = stmt.node() {
+                struct_names.push(name.clone());
+                if !generics.is_empty() {
+                    generic_structs.push(name.clone());
+                }
+            }
+        }
+
+        let mut table = MethodTable::default();
+        let mut lowered = Vec::with_capacity(ast.len());
+
+        // Traits first, impls can come before the trait they implement
+        let mut rest = Vec::with_capacity(ast.len());
+        for stmt in ast {
+            if !matches!(stmt.node(), Statement::TraitDefinition { .. }) {
+                rest.push(stmt);
+                continue;
+            }
+            let Statement::TraitDefinition { name, methods, .. } = stmt.into_node() else {
+                unreachable!()
+            };
+            if table.traits.insert(name.clone(), methods).is_some() {
+                return Err(MethodError::DuplicateTrait(name));
+            }
+        }
+
+        for stmt in rest {
+            if !matches!(stmt.node(), Statement::Impl { .. }) {
+                lowered.push(stmt);
+                continue;
+            }
+            let Statement::Impl { type_name, trait_name, methods } = stmt.into_node() else {
+                unreachable!()
+            };
+
+            if !struct_names.contains(&type_name) && !BUILTIN_TYPES.contains(&type_name.as_str()) {
+                return Err(MethodError::UnknownType(type_name));
+            }
+            // Methods are looked up by plain type name, so there's no way to pick a specialization yet
+            if generic_structs.contains(&type_name) {
+                return Err(MethodError::GenericType(type_name));
+            }
+
+            if let Some(trait_name) = &trait_name {
+                let Some(required) = table.traits.get(trait_name) else {
+                    return Err(MethodError::UnknownTrait(trait_name.clone()));
+                };
+
+                let provided: Vec<&str> = methods
+                    .iter()
+                    .filter_map(|method| match method.node() {
+                        Statement::FunctionDefinition { name, .. } => Some(name.as_str()),
+                        _ => None,
+                    })
+                    .collect();
+                if let Some(missing) = required.iter().find(|method| !provided.contains(&method.name.as_str())) {
+                    return Err(MethodError::MissingTraitMethod(
+                        type_name,
+                        trait_name.clone(),
+                        missing.name.clone(),
+                    ));
+                }
+                if let Some(extra) = provided.iter().find(|name| !required.iter().any(|method| &method.name == *name)) {
+                    return Err(MethodError::NotInTrait(trait_name.clone(), extra.to_string()));
+                }
+
+                table.implementations.insert((type_name.clone(), trait_name.clone()));
+            }
+
+            for method in methods {
+                let span = method.span().unwrap_or_default();
+                let Statement::FunctionDefinition { name, generics, mut parameters, return_type, body, .. } = method.into_node() else {
+                    return Err(MethodError::NotAMethod(type_name));
+                };
+                if !generics.is_empty() {
+                    return Err(MethodError::GenericMethod(type_name, name));
+                }
+
+                // `self` is always the type the impl is for
+                if let Some(receiver) = parameters.first_mut() {
+                    if receiver.ty.is_none() {
+                        receiver.ty = Some(Type::from_name(&type_name));
+                    }
+                }
+
+                let mangled = mangle_method(&type_name, &name);
+                let type_methods = table.methods.entry(type_name.clone()).or_default();
+                if type_methods.insert(name.clone(), mangled.clone()).is_some() {
+                    return Err(MethodError::DuplicateMethod(type_name, name));
+                }
+
+                let function = Statement::FunctionDefinition {
+                    name: mangled,
+                    public: false,
+                    generics,
+                    parameters,
+                    return_type,
+                    body,
+                };
+                lowered.push(Statement::Spanned(span, Box::new(function)));
+            }
+        }
+
+        Ok((lowered, table))
+    }
+
+    // Mangled function name for `method` on values of `type_name`
+    pub fn resolve(&self, type_name: &str, method: &str) -> Option<&str> {
+        self.methods.get(type_name)?.get(method).map(String::as_str)
+    }
+
+    pub fn trait_methods(&self, trait_name: &str) -> Option<&[TraitMethod]> {
+        self.traits.get(trait_name).map(Vec::as_slice)
+    }
+
+    pub fn implements(&self, type_name: &str, trait_name: &str) -> bool {
+        self.implementations
+            .contains(&(type_name.to_string(), trait_name.to_string()))
+    }
+
+    // Every (type, trait) pair that has an impl
+    pub fn implementations(&self) -> impl Iterator<Item = &(String, String)> {
+        self.implementations.iter()
+    }
+
+    // Every type that has a method called `method`
+    pub fn types_with_method(&self, method: &str) -> Vec<&str> {
+        self.methods
+            .iter()
+            .filter(|(_, methods)| methods.contains_key(method))
+            .map(|(type_name, _)| type_name.as_str())
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::Lexer;
+    use crate::parser::Parser;
+
+    fn build(source: &str) -> Result<(Vec<Statement>, MethodTable), MethodError> {
+        let mut lexer = Lexer::new(source);
+        let tokens = lexer.tokenize().expect("test source should lex").to_vec();
+        let ast = Parser::new(tokens, lexer.spans().to_vec()).parse().expect("test source should parse");
+        MethodTable::build(ast)
+    }
+
+    #[test]
+    fn methods_become_mangled_functions() {
+        let (ast, table) = build(
+            "struct Point { x: int, y: int }\nimpl Point {\n fn sum(self) -> int { return self.x + self.y }\n}\n",
+        )
+        .unwrap();
+        assert_eq!(table.resolve("Point", "sum"), Some("Point.sum"));
+        assert_eq!(table.types_with_method("sum"), vec!["Point"]);
+        let Statement::FunctionDefinition { name, parameters, .. } = ast[1].node() else {
+            panic!("the method should be a function now");
+        };
+        assert_eq!(name, "Point.sum");
+        assert_eq!(parameters[0].ty, Some(Type::from_name("Point")));
+    }
+
+    #[test]
+    fn impls_are_checked() {
+        assert_eq!(
+            build("impl Nothing {\n fn f(self) -> int { return 1 }\n}\n").unwrap_err(),
+            MethodError::UnknownType("Nothing".into())
+        );
+        assert_eq!(
+            build("impl int {\n fn f(self) -> int { return 1 }\n fn f(self) -> int { return 2 }\n}\n").unwrap_err(),
+            MethodError::DuplicateMethod("int".into(), "f".into())
+        );
+    }
+
+    #[test]
+    fn impls_of_traits_have_to_match_them() {
+        let area = "trait Area { fn area(self) -> float }\nstruct Square { side: float }\n";
+        assert_eq!(
+            build(&format!("{}impl Area for Square {{\n fn perimeter(self) -> float {{ return 1.0 }}\n}}\n", area))
+                .unwrap_err(),
+            MethodError::MissingTraitMethod("Square".into(), "Area".into(), "area".into())
+        );
+        let (_, table) =
+            build(&format!("{}impl Area for Square {{\n fn area(self) -> float {{ return 1.0 }}\n}}\n", area)).unwrap();
+        assert!(table.implements("Square", "Area"));
+        assert_eq!(table.trait_methods("Area").map(|methods| methods.len()), Some(1));
+    }
+}
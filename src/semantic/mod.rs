@@ -0,0 +1,9 @@
+mod methods;
+mod prelude;
+mod resolve;
+mod typecheck;
+
+pub use methods::{mangle_method, MethodError, MethodTable, SHOW};
+pub use prelude::{intrinsics, PRELUDE};
+pub use resolve::{ResolveError, Resolver};
+pub use typecheck::{TypeChecker, TypeError};
@@ -0,0 +1,15 @@
+use crate::parser::enums::Type;
+
+// The standard library every program starts with
+pub const PRELUDE: &str = include_str!("prelude.lum");
+
+// Functions codegen provides itself, the prelude is written on top of these
+pub fn intrinsics() -> Vec<(&'static str, Type)> {
+    let to_str = |ty: Type| Type::Function(vec![ty], Box::new(Type::Str));
+    vec![
+        ("__print", Type::Function(vec![Type::Str], Box::new(Type::Null))),
+        ("__show_int", to_str(Type::Int)),
+        ("__show_float", to_str(Type::Float)),
+        ("__show_bool", to_str(Type::Bool)),
+    ]
+}
@@ -0,0 +1,620 @@
+use super::methods::BUILTIN_TYPES;
+use super::prelude;
+use crate::lexer::Span;
+use crate::modules::Module;
+use crate::parser::enums::*;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ResolveError {
+    pub message: String,
+    pub span: Span,
+}
+
+impl fmt::Display for ResolveError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}: {}", self.span.line, self.span.column, self.message)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum SymbolKind {
+    Variable,
+    Function,
+    Struct,
+    Trait,
+    Module,
+}
+
+struct Symbol {
+    kind: SymbolKind,
+    // What the rest of the compiler calls it. Functions, structs and traits from imported
+    // modules are prefixed with the module, `geo::Point`, so every module gets its own names.
+    // For a module it's the name the loader gave it
+    name: String,
+}
+
+struct Scope {
+    names: HashMap<String, SymbolId>,
+    // Function bodies can't see the variables around them, only functions and structs
+    barrier: bool,
+    // A lambda's own scope. Variables found past it are captured
+    closure: bool,
+}
+
+// Works out which declaration every name refers to. Blocks, functions, lambdas and loop
+// bodies each get their own scope, every declaration gets a fresh symbol id, and every
+// identifier is tagged with the id of the declaration it means.
+//
+// Every module is resolved in a scope of its own on top of the prelude, and only sees what
+// it imports. All of them come out as one program, with their names made unique
+pub struct Resolver {
+    symbols: Vec<Symbol>,
+    scopes: Vec<Scope>,
+    exports: HashMap<String, HashMap<String, (SymbolId, bool)>>, // Module, item, (symbol, pub)
+    module: String,                                              // The module being resolved
+    module_depth: usize,                                         // How many scopes deep its top level is
+    imports: HashMap<String, String>,
+    captured: HashSet<SymbolId>, // Variables some lambda uses from around it
+    span: Span,
+}
+
+impl Resolver {
+    pub fn new() -> Self {
+        Self {
+            symbols: Vec::new(),
+            scopes: Vec::new(),
+            exports: HashMap::new(),
+            module: String::new(),
+            module_depth: 0,
+            imports: HashMap::new(),
+            captured: HashSet::new(),
+            span: Span::default(),
+        }
+    }
+
+    // Modules have to come after the modules they import, like the loader hands them out
+    pub fn resolve_program(
+        &mut self,
+        mut prelude: Vec<Statement>,
+        modules: Vec<Module>,
+    ) -> Result<Vec<Statement>, ResolveError> {
+        // Intrinsics sit below everything, so nothing can redefine them but anything can shadow them
+        self.push_scope(false);
+        self.module_depth = self.scopes.len();
+        for (name, _) in prelude::intrinsics() {
+            self.declare(name, SymbolKind::Function)?;
+        }
+
+        // The entry module shares the prelude's scope, like the prelude was written at its top
+        self.push_scope(false);
+        self.module_depth = self.scopes.len();
+        self.resolve_statements(&mut prelude)?;
+
+        let mut program = prelude;
+        for mut module in modules {
+            self.module = module.name;
+            self.imports = module.imports;
+
+            let own_scope = !self.module.is_empty();
+            if own_scope {
+                self.push_scope(false);
+            }
+            self.module_depth = self.scopes.len();
+            self.resolve_statements(&mut module.ast)?;
+            if own_scope {
+                self.pop_scope();
+            }
+
+            program.extend(
+                module
+                    .ast
+                    .into_iter()
+                    .filter(|stmt| !matches!(stmt.node(), Statement::Import { .. })),
+            );
+        }
+
+        self.scopes.clear();
+        Ok(program)
+    }
+
+    // Codegen keeps these on the heap, so a lambda and the code around it share one variable
+    pub fn captured(&self) -> &HashSet<SymbolId> {
+        &self.captured
+    }
+
+    fn error<T>(&self, message: String) -> Result<T, ResolveError> {
+        Err(ResolveError {
+            message,
+            span: self.span,
+        })
+    }
+
+    fn push_scope(&mut self, barrier: bool) {
+        self.scopes.push(Scope {
+            names: HashMap::new(),
+            barrier,
+            closure: false,
+        });
+    }
+
+    fn pop_scope(&mut self) {
+        self.scopes.pop();
+    }
+
+    fn declare(&mut self, name: &str, kind: SymbolKind) -> Result<SymbolId, ResolveError> {
+        let id = self.symbols.len();
+        self.bind(name, id)?;
+
+        let top_level = self.scopes.len() == self.module_depth;
+        let name = match kind {
+            // Functions inside other functions or blocks get the id too, two `helper`s in
+            // different functions are different functions
+            SymbolKind::Function if !top_level => format!("{}#{}", name, id),
+            SymbolKind::Function | SymbolKind::Struct | SymbolKind::Trait if !self.module.is_empty() => {
+                format!("{}::{}", self.module, name)
+            }
+            _ => name.to_string(),
+        };
+        self.symbols.push(Symbol { kind, name });
+        Ok(id)
+    }
+
+    // A name can only be declared once per scope, shadowing takes a new one
+    fn bind(&mut self, name: &str, id: SymbolId) -> Result<(), ResolveError> {
+        let scope = self.scopes.last_mut().expect("there is always a scope");
+        if scope.names.contains_key(name) {
+            return self.error(format!("'{}' is already defined in this scope", name));
+        }
+        scope.names.insert(name.to_string(), id);
+        Ok(())
+    }
+
+    fn lookup(&self, name: &str) -> Option<SymbolId> {
+        let mut crossed_barrier = false;
+        for scope in self.scopes.iter().rev() {
+            if let Some(&id) = scope.names.get(name) {
+                if !crossed_barrier || self.symbols[id].kind != SymbolKind::Variable {
+                    return Some(id);
+                }
+            }
+            crossed_barrier |= scope.barrier;
+        }
+        None
+    }
+
+    // Notes `id` as captured when the variable `name` was declared outside the innermost lambda
+    fn note_capture(&mut self, name: &str, id: SymbolId) {
+        if self.kind(id) != SymbolKind::Variable {
+            return;
+        }
+        for scope in self.scopes.iter().rev() {
+            if scope.names.get(name) == Some(&id) {
+                return;
+            }
+            if scope.closure {
+                self.captured.insert(id);
+                return;
+            }
+        }
+    }
+
+    fn kind(&self, id: SymbolId) -> SymbolKind {
+        self.symbols[id].kind
+    }
+
+    fn resolve_block(&mut self, stmts: &mut [Statement]) -> Result<(), ResolveError> {
+        self.push_scope(false);
+        let result = self.resolve_statements(stmts);
+        self.pop_scope();
+        result
+    }
+
+    // Functions, structs and traits can be used anywhere in the block they're defined in,
+    // and so can imports anywhere in their module
+    fn resolve_statements(&mut self, stmts: &mut [Statement]) -> Result<(), ResolveError> {
+        let top_level = self.scopes.len() == self.module_depth;
+        for stmt in stmts.iter_mut() {
+            if let Some(span) = stmt.span() {
+                self.span = span;
+            }
+            let (name, public, kind) = match stmt.node_mut() {
+                Statement::FunctionDefinition { name, public, .. } => (name, *public, SymbolKind::Function),
+                Statement::StructDefinition { name, public, .. } => (name, *public, SymbolKind::Struct),
+                Statement::TraitDefinition { name, public, .. } => (name, *public, SymbolKind::Trait),
+                Statement::Import { module, items } => {
+                    if !top_level {
+                        return self.error(format!("'{}' can only be imported at the top of a module", module));
+                    }
+                    self.import(module, items.as_deref())?;
+                    continue;
+                }
+                _ => continue,
+            };
+
+            let id = self.declare(name, kind)?;
+            if top_level {
+                self.exports
+                    .entry(self.module.clone())
+                    .or_default()
+                    .insert(name.clone(), (id, public));
+            }
+            *name = self.symbols[id].name.clone();
+        }
+
+        for stmt in stmts.iter_mut() {
+            self.resolve_statement(stmt)?;
+        }
+        Ok(())
+    }
+
+    // `import util.strings` binds `strings`, `from util import parse` binds `parse`
+    fn import(&mut self, module: &str, items: Option<&[String]>) -> Result<(), ResolveError> {
+        let Some(target) = self.imports.get(module).cloned() else {
+            return self.error(format!("Module '{}' was never loaded", module));
+        };
+
+        match items {
+            None => {
+                let alias = module.rsplit('.').next().unwrap_or(module);
+                let id = self.declare(alias, SymbolKind::Module)?;
+                self.symbols[id].name = target;
+            }
+            Some(items) => {
+                for item in items {
+                    let id = self.exported(&target, item)?;
+                    self.bind(item, id)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn exported(&self, module: &str, item: &str) -> Result<SymbolId, ResolveError> {
+        match self.exports.get(module).and_then(|items| items.get(item)) {
+            Some(&(id, true)) => Ok(id),
+            Some(_) => self.error(format!("'{}' is private to module '{}'", item, module)),
+            None => self.error(format!("Module '{}' has no '{}'", module, item)),
+        }
+    }
+
+    // The item `receiver.item` means when the receiver is an imported module, like math.sqrt
+    fn module_item(&self, receiver: &Expression, item: &str) -> Result<Option<SymbolId>, ResolveError> {
+        let Expression::Identifier(name, _) = receiver else {
+            return Ok(None);
+        };
+        match self.lookup(name) {
+            Some(id) if self.kind(id) == SymbolKind::Module => self.exported(&self.symbols[id].name, item).map(Some),
+            _ => Ok(None),
+        }
+    }
+
+    // Struct and trait names in types, which can be qualified as geo.Point
+    fn resolve_type_name(&self, name: &str, kind: SymbolKind) -> Result<String, ResolveError> {
+        let what = if kind == SymbolKind::Trait { "trait" } else { "type" };
+        let id = match name.split_once('.') {
+            Some((module, item)) => match self.lookup(module) {
+                Some(id) if self.kind(id) == SymbolKind::Module => self.exported(&self.symbols[id].name, item)?,
+                _ => return self.error(format!("Unknown module '{}'", module)),
+            },
+            None => match self.lookup(name) {
+                Some(id) => id,
+                None => return self.error(format!("Unknown {} '{}'", what, name)),
+            },
+        };
+        if self.kind(id) != kind {
+            return self.error(format!("'{}' is not a {}", name, what));
+        }
+        Ok(self.symbols[id].name.clone())
+    }
+
+    fn resolve_type(&self, ty: &mut Type) -> Result<(), ResolveError> {
+        match ty {
+            Type::Named(name, args) => {
+                *name = self.resolve_type_name(name, SymbolKind::Struct)?;
+                args.iter_mut().try_for_each(|arg| self.resolve_type(arg))
+            }
+            Type::Dyn(trait_name) => {
+                *trait_name = self.resolve_type_name(trait_name, SymbolKind::Trait)?;
+                Ok(())
+            }
+            Type::List(element) => self.resolve_type(element),
+            Type::Function(parameters, return_type) => {
+                parameters.iter_mut().try_for_each(|param| self.resolve_type(param))?;
+                self.resolve_type(return_type)
+            }
+            Type::Int
+            | Type::Integer(..)
+            | Type::Float
+            | Type::Bool
+            | Type::Str
+            | Type::Null
+            | Type::Param(_)
+            | Type::Var(_) => Ok(()),
+        }
+    }
+
+    fn resolve_slot(&self, ty: &mut Option<Type>) -> Result<(), ResolveError> {
+        match ty {
+            Some(ty) => self.resolve_type(ty),
+            None => Ok(()),
+        }
+    }
+
+    fn resolve_generics(&self, generics: &mut [Generic]) -> Result<(), ResolveError> {
+        for generic in generics {
+            for bound in generic.bounds.iter_mut() {
+                *bound = self.resolve_type_name(bound, SymbolKind::Trait)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn resolve_statement(&mut self, stmt: &mut Statement) -> Result<(), ResolveError> {
+        match stmt {
+            Statement::Spanned(span, inner) => {
+                self.span = *span;
+                self.resolve_statement(inner)
+            }
+            Statement::Expression(expr) => self.resolve_expression(expr),
+            Statement::Block(stmts) => self.resolve_block(stmts),
+            Statement::Comment(_) | Statement::Import { .. } => Ok(()),
+            Statement::Return(value) => match value.as_mut() {
+                Some(expr) => self.resolve_expression(expr),
+                None => Ok(()),
+            },
+            Statement::FunctionDefinition { generics, parameters, return_type, body, .. } => {
+                self.resolve_generics(generics)?;
+                self.resolve_slot(return_type)?;
+                self.push_scope(true);
+                let result = self
+                    .declare_parameters(parameters)
+                    .and_then(|_| self.resolve_block(body));
+                self.pop_scope();
+                result
+            }
+            Statement::StructDefinition { name, generics, fields, .. } => {
+                self.resolve_generics(generics)?;
+                for (i, field) in fields.iter().enumerate() {
+                    if fields[..i].iter().any(|other| other.name == field.name) {
+                        return self.error(format!("'{}' has more than one field called '{}'", name, field.name));
+                    }
+                }
+                fields.iter_mut().try_for_each(|field| self.resolve_slot(&mut field.ty))
+            }
+            Statement::TraitDefinition { methods, .. } => {
+                for method in methods {
+                    method.parameters.iter_mut().try_for_each(|param| self.resolve_slot(&mut param.ty))?;
+                    self.resolve_slot(&mut method.return_type)?;
+                }
+                Ok(())
+            }
+            Statement::Impl { type_name, trait_name, methods } => {
+                if !BUILTIN_TYPES.contains(&type_name.as_str()) {
+                    *type_name = self.resolve_type_name(type_name, SymbolKind::Struct)?;
+                }
+                if let Some(trait_name) = trait_name {
+                    *trait_name = self.resolve_type_name(trait_name, SymbolKind::Trait)?;
+                }
+                // Methods aren't names in scope, they're found through the value they're called on
+                methods.iter_mut().try_for_each(|method| self.resolve_statement(method))
+            }
+            Statement::Let { name, symbol, ty, value } => {
+                self.resolve_slot(ty)?;
+                // The value is resolved first, so `let x = x + 1` reads the outer x
+                self.resolve_expression(value)?;
+                *symbol = Some(self.declare(name, SymbolKind::Variable)?);
+                Ok(())
+            }
+        }
+    }
+
+    fn declare_parameters(&mut self, parameters: &mut [Parameter]) -> Result<(), ResolveError> {
+        for param in parameters.iter_mut() {
+            self.resolve_slot(&mut param.ty)?;
+            param.symbol = Some(self.declare(&param.name, SymbolKind::Variable)?);
+        }
+        Ok(())
+    }
+
+    fn resolve_expression(&mut self, expr: &mut Expression) -> Result<(), ResolveError> {
+        match expr {
+            Expression::Identifier(name, symbol) => match self.lookup(name) {
+                Some(id) if self.kind(id) == SymbolKind::Module => {
+                    self.error(format!("'{}' is a module, not a value", name))
+                }
+                Some(id) => {
+                    self.note_capture(name, id);
+                    *symbol = Some(id);
+                    *name = self.symbols[id].name.clone();
+                    Ok(())
+                }
+                None => self.error(format!("Undefined variable: {}", name)),
+            },
+            Expression::LiteralValue(_) | Expression::Keyword(_) | Expression::Specialized(..) => Ok(()),
+            Expression::MathOp(lhs, _, rhs, _)
+            | Expression::ComparisonOp(lhs, _, rhs, _)
+            | Expression::LogicalOp(lhs, _, rhs)
+            | Expression::BitwiseOp(lhs, _, rhs, _)
+            | Expression::Index(lhs, rhs) => {
+                self.resolve_expression(lhs)?;
+                self.resolve_expression(rhs)
+            }
+            Expression::FieldAccess(inner, field) => match self.module_item(inner, field)? {
+                Some(id) => {
+                    *expr = Expression::Identifier(self.symbols[id].name.clone(), Some(id));
+                    Ok(())
+                }
+                None => self.resolve_expression(inner),
+            },
+            Expression::Grouping(inner) | Expression::DynCast(inner, _) => self.resolve_expression(inner),
+            Expression::Cast(inner, ty, _) => {
+                self.resolve_type(ty)?;
+                self.resolve_expression(inner)
+            }
+            Expression::List(elements) | Expression::StringInterpolation(elements) => {
+                elements.iter_mut().try_for_each(|element| self.resolve_expression(element))
+            }
+            // math.sqrt(2) is a call to whatever sqrt is in math, not a method
+            Expression::MethodCall(receiver, method, args, _) => {
+                args.iter_mut().try_for_each(|arg| self.resolve_expression(arg))?;
+                match self.module_item(receiver, method)? {
+                    Some(id) => {
+                        let callee = Expression::Identifier(self.symbols[id].name.clone(), Some(id));
+                        *expr = Expression::FunctionCall(Box::new(callee), std::mem::take(args));
+                        Ok(())
+                    }
+                    None => self.resolve_expression(receiver),
+                }
+            }
+            // format(...) takes any number of values, so it's built in like range is
+            Expression::FunctionCall(callee, args)
+                if matches!(callee.as_ref(), Expression::Identifier(name, _) if name == "format")
+                    && self.lookup("format").is_none() =>
+            {
+                args.iter_mut().try_for_each(|arg| self.resolve_expression(arg))
+            }
+            Expression::FunctionCall(callee, args) => {
+                self.resolve_expression(callee)?;
+                args.iter_mut().try_for_each(|arg| self.resolve_expression(arg))
+            }
+            Expression::Assignment(target, value) => {
+                self.resolve_expression(value)?;
+                match target.as_mut() {
+                    // The first assignment to a name declares it
+                    Expression::Identifier(name, symbol) => {
+                        let id = match self.lookup(name) {
+                            Some(id) if self.kind(id) == SymbolKind::Variable => {
+                                self.note_capture(name, id);
+                                id
+                            }
+                            Some(_) => return self.error(format!("Cannot assign to '{}'", name)),
+                            None => self.declare(name, SymbolKind::Variable)?,
+                        };
+                        *symbol = Some(id);
+                        Ok(())
+                    }
+                    other => self.resolve_expression(other),
+                }
+            }
+            Expression::If(cond, block) | Expression::While(cond, block) => {
+                self.resolve_expression(cond)?;
+                self.resolve_block(block)
+            }
+            Expression::IfElse(cond, block, elif_branches, else_block) => {
+                self.resolve_expression(cond)?;
+                self.resolve_block(block)?;
+                for (elif_cond, elif_block) in elif_branches.iter_mut() {
+                    self.resolve_expression(elif_cond)?;
+                    self.resolve_block(elif_block)?;
+                }
+                match else_block {
+                    Some(else_block) => self.resolve_block(else_block),
+                    None => Ok(()),
+                }
+            }
+            Expression::For(var, iterable, block) => {
+                match iterable.as_mut() {
+                    // range(...) is part of the loop, not a function
+                    Expression::FunctionCall(callee, args)
+                        if matches!(callee.as_ref(), Expression::Identifier(name, _) if name == "range")
+                            && self.lookup("range").is_none() =>
+                    {
+                        args.iter_mut().try_for_each(|arg| self.resolve_expression(arg))?;
+                    }
+                    other => self.resolve_expression(other)?,
+                }
+
+                self.push_scope(false);
+                let result = self
+                    .declare_parameters(std::slice::from_mut(var))
+                    .and_then(|_| self.resolve_statements(block));
+                self.pop_scope();
+                result
+            }
+            Expression::Lambda(parameters, return_type, body) => {
+                self.resolve_slot(return_type)?;
+                // Lambdas see everything around them, that's what they capture
+                self.push_scope(false);
+                self.scopes.last_mut().expect("just pushed").closure = true;
+                let result = self
+                    .declare_parameters(parameters)
+                    .and_then(|_| self.resolve_statements(body));
+                self.pop_scope();
+                result
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::Lexer;
+    use crate::parser::Parser;
+
+    fn resolve(source: &str) -> (Vec<Statement>, HashSet<SymbolId>) {
+        let mut lexer = Lexer::new(source);
+        let tokens = lexer.tokenize().expect("test source should lex").to_vec();
+        let ast = Parser::new(tokens, lexer.spans().to_vec()).parse().expect("test source should parse");
+        let module = Module {
+            name: String::new(),
+            file: 0,
+            imports: HashMap::new(),
+            tokens: Vec::new(),
+            ast,
+        };
+        let mut resolver = Resolver::new();
+        let ast = resolver.resolve_program(Vec::new(), vec![module]).expect("test source should resolve");
+        (ast, resolver.captured().clone())
+    }
+
+    fn assigned_symbol(stmt: &Statement) -> SymbolId {
+        match stmt.node() {
+            Statement::Expression(Expression::Assignment(target, _)) => match target.as_ref() {
+                Expression::Identifier(_, Some(symbol)) => *symbol,
+                other => panic!("not a resolved name: {:?}", other),
+            },
+            other => panic!("not an assignment: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn lambdas_capture_the_variables_they_assign() {
+        let (ast, captured) = resolve("count = 0\ninc = || { count = count + 1 }\nother = 1\n");
+        assert!(captured.contains(&assigned_symbol(&ast[0])));
+        assert!(!captured.contains(&assigned_symbol(&ast[1])));
+        assert!(!captured.contains(&assigned_symbol(&ast[2])));
+    }
+
+    #[test]
+    fn lambda_locals_are_not_captured() {
+        let (_, captured) = resolve("f = |x: int| { y = x\n return y }\n");
+        assert!(captured.is_empty());
+    }
+
+    fn function_names(stmts: &[Statement], found: &mut Vec<String>) {
+        for stmt in stmts {
+            if let Statement::FunctionDefinition { name, body, .. } = stmt.node() {
+                found.push(name.clone());
+                function_names(body, found);
+            }
+        }
+    }
+
+    #[test]
+    fn nested_functions_get_their_own_names() {
+        let (ast, _) = resolve(
+            "fn a() -> int {\n fn helper() -> int { return 1 }\n return helper()\n}\n\
+             fn b() -> int {\n fn helper() -> int { return 2 }\n return helper()\n}\n",
+        );
+        let mut names = Vec::new();
+        function_names(&ast, &mut names);
+        assert_eq!(names.len(), 4);
+        assert_eq!(&names[0], "a");
+        assert_eq!(&names[2], "b");
+        assert!(names[1].starts_with("helper#") && names[3].starts_with("helper#"));
+        assert_ne!(names[1], names[3]);
+    }
+}
@@ -0,0 +1,1308 @@
+use super::methods::{mangle_method, MethodTable, SHOW};
+use super::prelude;
+use crate::lexer::enums::*;
+use crate::lexer::Span;
+use crate::parser::enums::*;
+use crate::parser::format_pieces;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TypeError {
+    pub message: String,
+    pub span: Span,
+}
+
+impl fmt::Display for TypeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}: {}", self.span.line, self.span.column, self.message)
+    }
+}
+
+// Works out the type of everything before codegen sees it. Anything left unannotated gets
+// an inference variable, which is solved by unification and written back into the AST,
+// so codegen always has concrete types on parameters, returns, fields and lets.
+pub struct TypeChecker<'a> {
+    methods: &'a MethodTable,
+    substitution: Vec<Option<Type>>, // What each Type::Var has been solved to
+    numeric: HashSet<usize>,         // Vars that can only become int or float
+    integral: HashSet<usize>,        // Numeric vars that can only become an int, for bitwise operators
+    variables: HashMap<SymbolId, Type>, // Names are already resolved, so one table does
+    functions: HashMap<String, (Vec<Generic>, Type)>, // Generic parameters and signature
+    // Non-generic functions with a parameter left unannotated. The parameter's type is
+    // inferred once, so every call has to pass the same type
+    unannotated: HashSet<String>,
+    structs: HashMap<String, (Vec<Generic>, Vec<(String, Type)>)>,
+    bounds: HashMap<String, Vec<String>>, // Traits each generic parameter in scope implements
+    obligations: Vec<Obligation>,
+    return_types: Vec<Type>,
+    span: Span,
+}
+
+// A type that has to implement a trait, checked once everything has been inferred
+struct Obligation {
+    ty: Type,
+    trait_name: String,
+    bounds: HashMap<String, Vec<String>>,
+    span: Span,
+}
+
+impl<'a> TypeChecker<'a> {
+    pub fn new(methods: &'a MethodTable) -> Self {
+        Self {
+            methods,
+            substitution: Vec::new(),
+            numeric: HashSet::new(),
+            integral: HashSet::new(),
+            variables: HashMap::new(),
+            functions: prelude::intrinsics()
+                .into_iter()
+                .map(|(name, ty)| (name.to_string(), (Vec::new(), ty)))
+                .collect(),
+            unannotated: HashSet::new(),
+            structs: HashMap::new(),
+            bounds: HashMap::new(),
+            obligations: Vec::new(),
+            return_types: Vec::new(),
+            span: Span::default(),
+        }
+    }
+
+    pub fn check_program(&mut self, ast: &mut [Statement]) -> Result<(), TypeError> {
+        let top_level_return = self.fresh();
+        self.return_types.push(top_level_return);
+
+        self.declare(ast)?;
+        self.check_implementations()?;
+        for stmt in ast.iter_mut() {
+            self.check_statement(stmt)?;
+        }
+        self.check_obligations()?;
+
+        for stmt in ast.iter_mut() {
+            self.finalize_statement(stmt);
+        }
+        Ok(())
+    }
+
+    // Every impl of a trait has to match the signatures the trait gives
+    fn check_implementations(&mut self) -> Result<(), TypeError> {
+        let mut implementations: Vec<(String, String)> = self.methods.implementations().cloned().collect();
+        implementations.sort();
+
+        for (type_name, trait_name) in implementations {
+            let required = self.methods.trait_methods(&trait_name).unwrap_or_default().to_vec();
+            for method in required {
+                let mangled = mangle_method(&type_name, &method.name);
+                let Some((_, found)) = self.functions.get(&mangled).cloned() else {
+                    return self.error(format!("Undefined function: {}", mangled));
+                };
+                let expected = self.trait_method_type(&method, Type::from_name(&type_name));
+                self.unify(&expected, &found).map_err(|err| TypeError {
+                    message: format!("In impl {} for {}, '{}': {}", trait_name, type_name, method.name, err.message),
+                    span: err.span,
+                })?;
+            }
+        }
+        Ok(())
+    }
+
+    fn check_obligations(&mut self) -> Result<(), TypeError> {
+        for obligation in std::mem::take(&mut self.obligations) {
+            let ty = self.finalize(&obligation.ty);
+            let satisfied = match &ty {
+                _ if obligation.trait_name == SHOW => true,
+                Type::Param(name) => obligation
+                    .bounds
+                    .get(name)
+                    .map_or(false, |bounds| bounds.contains(&obligation.trait_name)),
+                Type::Dyn(trait_name) => trait_name == &obligation.trait_name,
+                Type::Named(name, _) => self.methods.implements(name, &obligation.trait_name),
+                other => self.methods.implements(&other.to_string(), &obligation.trait_name),
+            };
+            if !satisfied {
+                return Err(TypeError {
+                    message: format!("{} does not implement {}", ty, obligation.trait_name),
+                    span: obligation.span,
+                });
+            }
+        }
+        Ok(())
+    }
+
+    fn require_trait(&mut self, ty: Type, trait_name: &str) {
+        self.obligations.push(Obligation {
+            ty,
+            trait_name: trait_name.to_string(),
+            bounds: self.bounds.clone(),
+            span: self.span,
+        });
+    }
+
+    // A trait method as a function type, with `self` being whatever it's called on
+    fn trait_method_type(&mut self, method: &TraitMethod, self_type: Type) -> Type {
+        let mut parameters = vec![self_type];
+        for param in method.parameters.iter().skip(1) {
+            let ty = match &param.ty {
+                Some(ty) => ty.clone(),
+                None => self.fresh(),
+            };
+            parameters.push(ty);
+        }
+        let return_type = method.return_type.clone().unwrap_or(Type::Null);
+        Type::Function(parameters, Box::new(return_type))
+    }
+
+    fn error<T>(&self, message: String) -> Result<T, TypeError> {
+        Err(TypeError {
+            message,
+            span: self.span,
+        })
+    }
+
+    fn fresh(&mut self) -> Type {
+        self.substitution.push(None);
+        Type::Var(self.substitution.len() - 1)
+    }
+
+    fn fresh_numeric(&mut self) -> Type {
+        let var = self.fresh();
+        if let Type::Var(id) = var {
+            self.numeric.insert(id);
+        }
+        var
+    }
+
+    // Follows solved vars all the way down
+    fn resolve(&self, ty: &Type) -> Type {
+        match ty {
+            Type::Var(id) => match &self.substitution[*id] {
+                Some(solved) => self.resolve(solved),
+                None => ty.clone(),
+            },
+            Type::Function(parameters, return_type) => Type::Function(
+                parameters.iter().map(|param| self.resolve(param)).collect(),
+                Box::new(self.resolve(return_type)),
+            ),
+            Type::Named(name, args) => Type::Named(name.clone(), args.iter().map(|arg| self.resolve(arg)).collect()),
+            Type::List(element) => Type::List(Box::new(self.resolve(element))),
+            other => other.clone(),
+        }
+    }
+
+    // Like resolve, but anything still unknown becomes a float, which is what every value
+    // was before there were types. That goes for whole number literals too, so 7 / 2 is
+    // still 3.5 unless something says they're ints. Bitwise operators do, they only take ints
+    fn finalize(&self, ty: &Type) -> Type {
+        match self.resolve(ty) {
+            Type::Var(id) if self.integral.contains(&id) => Type::Int,
+            Type::Var(_) => Type::Float,
+            Type::Function(parameters, return_type) => Type::Function(
+                parameters.iter().map(|param| self.finalize(param)).collect(),
+                Box::new(self.finalize(&return_type)),
+            ),
+            Type::Named(name, args) => Type::Named(name, args.iter().map(|arg| self.finalize(arg)).collect()),
+            Type::List(element) => Type::List(Box::new(self.finalize(&element))),
+            other => other,
+        }
+    }
+
+    fn occurs(&self, id: usize, ty: &Type) -> bool {
+        match self.resolve(ty) {
+            Type::Var(other) => other == id,
+            Type::Function(parameters, return_type) => {
+                parameters.iter().any(|param| self.occurs(id, param)) || self.occurs(id, &return_type)
+            }
+            Type::Named(_, args) => args.iter().any(|arg| self.occurs(id, arg)),
+            Type::List(element) => self.occurs(id, &element),
+            _ => false,
+        }
+    }
+
+    // `expected` says which side of the unification the var came from, for the error message
+    fn bind(&mut self, id: usize, ty: Type, expected: bool) -> Result<(), TypeError> {
+        if self.occurs(id, &ty) {
+            return self.error(format!("Cannot construct the infinite type {}", self.resolve(&ty)));
+        }
+
+        if self.numeric.contains(&id) {
+            match &ty {
+                Type::Var(other) => {
+                    self.numeric.insert(*other);
+                }
+                Type::Int | Type::Integer(..) | Type::Float => {}
+                _ if expected => return self.error(format!("Expected a number, found {}", ty)),
+                _ => return self.error(format!("Expected {}, found a number", ty)),
+            }
+        }
+        if self.integral.contains(&id) {
+            match &ty {
+                Type::Var(other) => {
+                    self.integral.insert(*other);
+                }
+                Type::Int | Type::Integer(..) => {}
+                _ if expected => return self.error(format!("Expected an integer, found {}", ty)),
+                _ => return self.error(format!("Expected {}, found an integer", ty)),
+            }
+        }
+
+        self.substitution[id] = Some(ty);
+        Ok(())
+    }
+
+    fn unify(&mut self, expected: &Type, found: &Type) -> Result<(), TypeError> {
+        let expected = self.resolve(expected);
+        let found = self.resolve(found);
+
+        match (&expected, &found) {
+            (Type::Var(a), Type::Var(b)) if a == b => Ok(()),
+            (Type::Var(id), other) => self.bind(*id, other.clone(), true),
+            (other, Type::Var(id)) => self.bind(*id, other.clone(), false),
+            (Type::Function(expected_params, expected_return), Type::Function(found_params, found_return)) => {
+                if expected_params.len() != found_params.len() {
+                    return self.error(format!("Expected {}, found {}", expected, found));
+                }
+                for (expected_param, found_param) in expected_params.iter().zip(found_params) {
+                    self.unify(expected_param, found_param)?;
+                }
+                self.unify(expected_return, found_return)
+            }
+            (Type::Named(expected_name, expected_args), Type::Named(found_name, found_args))
+                if expected_name == found_name && expected_args.len() == found_args.len() =>
+            {
+                for (expected_arg, found_arg) in expected_args.iter().zip(found_args) {
+                    self.unify(expected_arg, found_arg)?;
+                }
+                Ok(())
+            }
+            (Type::List(expected_element), Type::List(found_element)) => self.unify(expected_element, found_element),
+            // Generic parameters are rigid, inside `fn f<T>` a T is only ever equal to T
+            _ if expected == found => Ok(()),
+            _ => self.error(format!("Expected {}, found {}", expected, found)),
+        }
+    }
+
+    fn require_numeric(&mut self, ty: &Type) -> Result<(), TypeError> {
+        match self.resolve(ty) {
+            Type::Var(id) => {
+                self.numeric.insert(id);
+                Ok(())
+            }
+            Type::Int | Type::Integer(..) | Type::Float => Ok(()),
+            other => self.error(format!("Expected a number, found {}", other)),
+        }
+    }
+
+    fn require_integer(&mut self, ty: &Type) -> Result<(), TypeError> {
+        match self.resolve(ty) {
+            Type::Var(id) => {
+                self.numeric.insert(id);
+                self.integral.insert(id);
+                Ok(())
+            }
+            Type::Int | Type::Integer(..) => Ok(()),
+            other => self.error(format!("Expected an integer, found {}", other)),
+        }
+    }
+
+    // Annotations can only name types that exist, with as many arguments as they take
+    fn check_annotation(&self, ty: &Type) -> Result<(), TypeError> {
+        match ty {
+            Type::Named(name, args) => {
+                let Some((generics, _)) = self.structs.get(name) else {
+                    return self.error(format!("Unknown type: {}", name));
+                };
+                if generics.len() != args.len() {
+                    return self.error(format!(
+                        "'{}' takes {} type arguments but {} were given",
+                        name,
+                        generics.len(),
+                        args.len()
+                    ));
+                }
+                args.iter().try_for_each(|arg| self.check_annotation(arg))
+            }
+            Type::List(element) => self.check_annotation(element),
+            Type::Dyn(trait_name) if self.methods.trait_methods(trait_name).is_none() => {
+                self.error(format!("Unknown trait: {}", trait_name))
+            }
+            Type::Function(parameters, return_type) => {
+                for param in parameters {
+                    self.check_annotation(param)?;
+                }
+                self.check_annotation(return_type)
+            }
+            _ => Ok(()),
+        }
+    }
+
+    // Fresh vars for each generic parameter, one per use of a generic function or struct,
+    // each of which has to end up implementing the parameter's bounds
+    fn instantiate(&mut self, generics: &[Generic]) -> (HashMap<String, Type>, Vec<Type>) {
+        let mut mapping = HashMap::new();
+        let mut args = Vec::new();
+        for generic in generics {
+            let arg = self.fresh();
+            for bound in &generic.bounds {
+                self.require_trait(arg.clone(), bound);
+            }
+            mapping.insert(generic.name.clone(), arg.clone());
+            args.push(arg);
+        }
+        (mapping, args)
+    }
+
+    // Checks an expression against the type it has to be. Anything going where a `dyn Trait`
+    // is expected gets wrapped in a DynCast, so codegen knows to build a vtable for it
+    fn check_against(&mut self, expected: &Type, expr: &mut Expression) -> Result<(), TypeError> {
+        let found = self.infer(expr)?;
+        let Type::Dyn(trait_name) = self.resolve(expected) else {
+            return self.unify(expected, &found);
+        };
+        if let Type::Dyn(_) = self.resolve(&found) {
+            return self.unify(expected, &found);
+        }
+
+        self.require_trait(found, &trait_name);
+        let value = std::mem::replace(expr, Expression::LiteralValue(LiteralValue::Null));
+        *expr = Expression::DynCast(Box::new(value), trait_name);
+        Ok(())
+    }
+
+    // Calling a trait's method on something only known to implement it, a bounded
+    // generic parameter or a dyn value
+    fn check_trait_call(
+        &mut self,
+        trait_name: &str,
+        method: &str,
+        receiver_type: Type,
+        args: &mut [Expression],
+    ) -> Result<Type, TypeError> {
+        let Some(signature) = self
+            .methods
+            .trait_methods(trait_name)
+            .and_then(|methods| methods.iter().find(|m| m.name == method))
+            .cloned()
+        else {
+            return self.error(format!("No method '{}' in trait {}", method, trait_name));
+        };
+        let Type::Function(parameters, return_type) = self.trait_method_type(&signature, receiver_type) else {
+            unreachable!()
+        };
+        if parameters.len() != args.len() + 1 {
+            return self.error(format!(
+                "Expected {} arguments, found {}",
+                parameters.len() - 1,
+                args.len()
+            ));
+        }
+
+        for (arg, param_type) in args.iter_mut().zip(&parameters[1..]) {
+            self.check_against(param_type, arg)?;
+        }
+        Ok(*return_type)
+    }
+
+    // format("x = {}", x) puts each value, shown, where the next {} is
+    fn check_format(&mut self, args: &mut [Expression]) -> Result<Type, TypeError> {
+        let Some((template, values)) = args.split_first_mut() else {
+            return self.error("format(...) needs a string literal to format".into());
+        };
+        let Some(pieces) = format_pieces(template) else {
+            return self.error("format(...) needs a string literal to format".into());
+        };
+        if pieces.len() - 1 != values.len() {
+            return self.error(format!(
+                "The format string has {} placeholders but {} values were given",
+                pieces.len() - 1,
+                values.len()
+            ));
+        }
+
+        for value in values.iter_mut() {
+            self.check_shown(value)?;
+        }
+        Ok(Type::Str)
+    }
+
+    // A value going into a string gets turned into a show() call here, so all codegen has
+    // left to do is put strings together
+    fn check_shown(&mut self, value: &mut Expression) -> Result<(), TypeError> {
+        let inner = std::mem::replace(value, Expression::LiteralValue(LiteralValue::Null));
+        *value = Expression::MethodCall(Box::new(inner), "show".to_string(), Vec::new(), None);
+        let shown = self.infer(value)?;
+        self.unify(&Type::Str, &shown)
+    }
+
+    fn struct_type(&mut self, name: &str) -> Type {
+        let generics = self.structs.get(name).map(|(generics, _)| generics.clone()).unwrap_or_default();
+        let (_, args) = self.instantiate(&generics);
+        Type::Named(name.to_string(), args)
+    }
+
+    fn annotated_or_fresh(&mut self, ty: &mut Option<Type>) -> Result<Type, TypeError> {
+        match ty {
+            Some(ty) => {
+                self.check_annotation(ty)?;
+                Ok(ty.clone())
+            }
+            None => {
+                let fresh = self.fresh();
+                *ty = Some(fresh.clone());
+                Ok(fresh)
+            }
+        }
+    }
+
+    fn lookup_variable(&self, symbol: Option<SymbolId>) -> Option<Type> {
+        self.variables.get(&symbol?).cloned()
+    }
+
+    fn bind_variable(&mut self, symbol: Option<SymbolId>, ty: Type) -> Result<(), TypeError> {
+        match symbol {
+            Some(symbol) => {
+                self.variables.insert(symbol, ty);
+                Ok(())
+            }
+            None => self.error("Variable was not resolved before type checking".into()),
+        }
+    }
+
+    // Structs and functions can be used anywhere in the block they're defined in
+    fn declare(&mut self, stmts: &mut [Statement]) -> Result<(), TypeError> {
+        // Struct names first, so fields and signatures can refer to any of them
+        for stmt in stmts.iter() {
+            if let Statement::StructDefinition { name, generics, .. } = stmt.node() {
+                self.structs.insert(name.clone(), (generics.clone(), Vec::new()));
+            }
+        }
+
+        for stmt in stmts.iter_mut() {
+            let stmt = match stmt {
+                Statement::Spanned(span, inner) => {
+                    self.span = *span;
+                    inner.as_mut()
+                }
+                other => other,
+            };
+
+            match stmt {
+                Statement::StructDefinition { name, generics, fields, .. } => {
+                    let mut field_types = Vec::new();
+                    for field in fields.iter_mut() {
+                        let ty = self.annotated_or_fresh(&mut field.ty)?;
+                        field_types.push((field.name.clone(), ty));
+                    }
+                    self.structs.insert(name.clone(), (generics.clone(), field_types));
+                }
+                Statement::FunctionDefinition { name, generics, parameters, return_type, .. } => {
+                    if generics.is_empty() && parameters.iter().any(|param| param.ty.is_none()) {
+                        self.unannotated.insert(name.clone());
+                    }
+                    let mut param_types = Vec::new();
+                    for param in parameters.iter_mut() {
+                        param_types.push(self.annotated_or_fresh(&mut param.ty)?);
+                    }
+                    let return_type = self.annotated_or_fresh(return_type)?;
+                    self.functions.insert(
+                        name.clone(),
+                        (generics.clone(), Type::Function(param_types, Box::new(return_type))),
+                    );
+                }
+                _ => {}
+            }
+        }
+
+        Ok(())
+    }
+
+    fn check_block(&mut self, stmts: &mut [Statement]) -> Result<(), TypeError> {
+        self.declare(stmts)?;
+        for stmt in stmts.iter_mut() {
+            self.check_statement(stmt)?;
+        }
+        Ok(())
+    }
+
+    // A block's value is its last statement, when that's an expression. A block ending in
+    // a return never gets to produce one, so it fits with whatever the other branches give.
+    // Codegen leaves it out of the if's value the same way
+    fn check_block_value(&mut self, stmts: &mut [Statement]) -> Result<Type, TypeError> {
+        self.declare(stmts)?;
+        let Some((last, rest)) = stmts.split_last_mut() else {
+            return Ok(Type::Null);
+        };
+        for stmt in rest.iter_mut() {
+            self.check_statement(stmt)?;
+        }
+        if let Some(span) = last.span() {
+            self.span = span;
+        }
+        match last.node_mut() {
+            Statement::Expression(expr) => self.infer(expr),
+            Statement::Return(_) => {
+                self.check_statement(last)?;
+                Ok(self.fresh())
+            }
+            _ => {
+                self.check_statement(last)?;
+                Ok(Type::Null)
+            }
+        }
+    }
+
+    // Falling off the end of a body returns its value, so that has to fit the return type.
+    // A function that returns null can end in anything
+    fn check_body(&mut self, body: &mut [Statement], return_type: &Type) -> Result<(), TypeError> {
+        if self.resolve(return_type) == Type::Null {
+            return self.check_block(body);
+        }
+        let found = self.check_block_value(body)?;
+        self.unify(return_type, &found)
+    }
+
+    fn check_statement(&mut self, stmt: &mut Statement) -> Result<(), TypeError> {
+        match stmt {
+            Statement::Spanned(span, inner) => {
+                self.span = *span;
+                self.check_statement(inner)
+            }
+            // Nothing uses the value, so the branches don't need to agree
+            Statement::Expression(Expression::IfElse(cond, block, elif_branches, else_block)) => {
+                self.check_condition(cond)?;
+                self.check_block(block)?;
+                for (elif_cond, elif_block) in elif_branches.iter_mut() {
+                    self.check_condition(elif_cond)?;
+                    self.check_block(elif_block)?;
+                }
+                if let Some(else_block) = else_block {
+                    self.check_block(else_block)?;
+                }
+                Ok(())
+            }
+            Statement::Expression(expr) => self.infer(expr).map(|_| ()),
+            Statement::Block(stmts) => self.check_block(stmts),
+            Statement::Comment(_) | Statement::StructDefinition { .. } => Ok(()),
+            Statement::Return(value) => {
+                let expected = self.return_types.last().cloned().unwrap_or(Type::Null);
+                match value.as_mut() {
+                    Some(expr) => self.check_against(&expected, expr),
+                    None => self.unify(&expected, &Type::Null),
+                }
+            }
+            Statement::FunctionDefinition { name, parameters, body, .. } => {
+                let Some((generics, Type::Function(param_types, return_type))) = self.functions.get(name.as_str()).cloned()
+                else {
+                    return self.error(format!("Function '{}' was never declared", name));
+                };
+
+                for (param, ty) in parameters.iter().zip(param_types) {
+                    self.bind_variable(param.symbol, ty)?;
+                }
+                let outer_bounds = self.bounds.clone();
+                for generic in generics {
+                    self.bounds.insert(generic.name, generic.bounds);
+                }
+                self.return_types.push(*return_type.clone());
+
+                let result = self.check_body(body, &return_type);
+
+                self.return_types.pop();
+                self.bounds = outer_bounds;
+                result
+            }
+            Statement::Let { symbol, ty, value, .. } => {
+                let var_type = match ty {
+                    Some(annotation) => {
+                        self.check_annotation(annotation)?;
+                        let annotation = annotation.clone();
+                        self.check_against(&annotation, value)?;
+                        annotation
+                    }
+                    None => {
+                        let found = self.infer(value)?;
+                        *ty = Some(found.clone());
+                        found
+                    }
+                };
+                self.bind_variable(*symbol, var_type)
+            }
+            Statement::Impl { type_name, .. } => {
+                self.error(format!("impl block for '{}' was not lowered before type checking", type_name))
+            }
+            Statement::TraitDefinition { name, .. } => {
+                self.error(format!("trait '{}' was not lowered before type checking", name))
+            }
+            Statement::Import { module, .. } => {
+                self.error(format!("import of '{}' was not resolved before type checking", module))
+            }
+        }
+    }
+
+    fn infer(&mut self, expr: &mut Expression) -> Result<Type, TypeError> {
+        match expr {
+            Expression::LiteralValue(literal) => Ok(match literal {
+                // Whole numbers can end up as any kind of number, codegen needs to know which
+                LiteralValue::Integer(_, ty) => {
+                    let var = self.fresh_numeric();
+                    *ty = Some(var.clone());
+                    var
+                }
+                LiteralValue::Number(_) => Type::Float,
+                LiteralValue::String(_) | LiteralValue::Template(_) => Type::Str,
+                LiteralValue::Boolean(_) => Type::Bool,
+                LiteralValue::Null => Type::Null,
+            }),
+            Expression::Identifier(name, symbol) => match self.lookup_variable(*symbol) {
+                Some(ty) => Ok(ty),
+                None => match self.functions.get(name.as_str()).cloned() {
+                    Some((generics, ty)) if generics.is_empty() => Ok(ty),
+                    Some((generics, ty)) => {
+                        // Every use of a generic function gets its own copy of the signature,
+                        // and remembers the arguments so codegen knows which specialization to call
+                        let (mapping, args) = self.instantiate(&generics);
+                        *expr = Expression::Specialized(name.clone(), args);
+                        Ok(substitute(&ty, &mapping))
+                    }
+                    None => self.error(format!("Undefined variable: {}", name)),
+                },
+            },
+            Expression::Specialized(name, args) => {
+                let Some((generics, ty)) = self.functions.get(name.as_str()).cloned() else {
+                    return self.error(format!("Undefined function: {}", name));
+                };
+                let mapping = generics
+                    .into_iter()
+                    .map(|generic| generic.name)
+                    .zip(args.iter().cloned())
+                    .collect();
+                Ok(substitute(&ty, &mapping))
+            }
+            Expression::DynCast(inner, trait_name) => {
+                let found = self.infer(inner)?;
+                let trait_name = trait_name.clone();
+                self.require_trait(found, &trait_name);
+                Ok(Type::Dyn(trait_name))
+            }
+            Expression::List(elements) => {
+                let element_type = self.fresh();
+                for element in elements.iter_mut() {
+                    let found = self.infer(element)?;
+                    self.unify(&element_type, &found)?;
+                }
+                Ok(Type::List(Box::new(element_type)))
+            }
+            Expression::StringInterpolation(parts) => {
+                for part in parts.iter_mut() {
+                    if !matches!(part, Expression::LiteralValue(LiteralValue::String(_))) {
+                        self.check_shown(part)?;
+                    }
+                }
+                Ok(Type::Str)
+            }
+            Expression::Index(list, index) => {
+                let list_type = self.infer(list)?;
+                let element_type = self.fresh();
+                self.unify(&Type::List(Box::new(element_type.clone())), &list_type)?;
+                let index_type = self.infer(index)?;
+                self.unify(&Type::Int, &index_type)?;
+                Ok(element_type)
+            }
+            Expression::MathOp(lhs, _, rhs, ty) => {
+                let lhs_type = self.infer(lhs)?;
+                let rhs_type = self.infer(rhs)?;
+                self.unify(&lhs_type, &rhs_type)?;
+                self.require_numeric(&lhs_type)?;
+                *ty = Some(lhs_type.clone());
+                Ok(lhs_type)
+            }
+            Expression::ComparisonOp(lhs, op, rhs, ty) => {
+                let lhs_type = self.infer(lhs)?;
+                let rhs_type = self.infer(rhs)?;
+                self.unify(&lhs_type, &rhs_type)?;
+                // Anything can be compared for equality, only numbers have an order
+                if !matches!(op, ComparisonOperator::Equals | ComparisonOperator::NotEquals) {
+                    self.require_numeric(&lhs_type)?;
+                }
+                *ty = Some(lhs_type);
+                Ok(Type::Bool)
+            }
+            Expression::LogicalOp(lhs, _, rhs) => {
+                let lhs_type = self.infer(lhs)?;
+                self.unify(&Type::Bool, &lhs_type)?;
+                let rhs_type = self.infer(rhs)?;
+                self.unify(&Type::Bool, &rhs_type)?;
+                Ok(Type::Bool)
+            }
+            // Shifts included, both sides are the same kind of int
+            Expression::BitwiseOp(lhs, _, rhs, ty) => {
+                let lhs_type = self.infer(lhs)?;
+                let rhs_type = self.infer(rhs)?;
+                self.unify(&lhs_type, &rhs_type)?;
+                self.require_integer(&lhs_type)?;
+                *ty = Some(lhs_type.clone());
+                Ok(lhs_type)
+            }
+            // Numbers convert to any other number type, bools to ints
+            Expression::Cast(inner, target, from) => {
+                let found = self.infer(inner)?;
+                *from = Some(found.clone());
+                match (self.resolve(&found), &*target) {
+                    (_, Type::Int | Type::Integer(..) | Type::Float) if self.require_numeric(&found).is_ok() => {
+                        Ok(target.clone())
+                    }
+                    (Type::Bool, Type::Int | Type::Integer(..)) => Ok(target.clone()),
+                    (found, target) => self.error(format!("Cannot cast {} to {}", found, target)),
+                }
+            }
+            Expression::If(cond, block) | Expression::While(cond, block) => {
+                self.check_condition(cond)?;
+                self.check_block(block)?;
+                Ok(Type::Null)
+            }
+            // Used as a value, every branch has to end in the same type. Without an else
+            // there's nothing to produce when no condition holds, so it's null
+            Expression::IfElse(cond, block, elif_branches, else_block) => {
+                self.check_condition(cond)?;
+                let value_type = self.check_block_value(block)?;
+                let mut branch_types = Vec::new();
+                for (elif_cond, elif_block) in elif_branches.iter_mut() {
+                    self.check_condition(elif_cond)?;
+                    branch_types.push(self.check_block_value(elif_block)?);
+                }
+                let Some(else_block) = else_block else {
+                    return Ok(Type::Null);
+                };
+                branch_types.push(self.check_block_value(else_block)?);
+
+                for branch_type in branch_types {
+                    if self.unify(&value_type, &branch_type).is_err() {
+                        return self.error(format!(
+                            "if branches have different types, {} and {}",
+                            self.finalize(&value_type),
+                            self.finalize(&branch_type)
+                        ));
+                    }
+                }
+                Ok(value_type)
+            }
+            Expression::For(var, iterable, block) => {
+                // Either a range, range(end) or range(start, end), or a list
+                let element_type = match iterable.as_mut() {
+                    Expression::FunctionCall(callee, args)
+                        if matches!(callee.as_ref(), Expression::Identifier(name, None) if name == "range") =>
+                    {
+                        if args.is_empty() {
+                            return self.error("range(...) needs an end".into());
+                        }
+                        let counter = self.fresh_numeric();
+                        for arg in args.iter_mut() {
+                            let arg_type = self.infer(arg)?;
+                            self.unify(&counter, &arg_type)?;
+                        }
+                        counter
+                    }
+                    other => {
+                        let iterable_type = self.infer(other)?;
+                        let element_type = self.fresh();
+                        self.unify(&Type::List(Box::new(element_type.clone())), &iterable_type)?;
+                        element_type
+                    }
+                };
+                let var_type = self.annotated_or_fresh(&mut var.ty)?;
+                self.unify(&var_type, &element_type)?;
+                self.bind_variable(var.symbol, var_type)?;
+                self.check_block(block)?;
+                Ok(Type::Null)
+            }
+            Expression::Grouping(inner) => self.infer(inner),
+            Expression::Keyword(keyword) => self.error(format!("Unexpected keyword: {}", keyword)),
+            Expression::FunctionCall(callee, args) => {
+                if matches!(callee.as_ref(), Expression::Identifier(name, None) if name == "format") {
+                    return self.check_format(args);
+                }
+                if let Expression::Identifier(name, symbol) = callee.as_ref() {
+                    if self.lookup_variable(*symbol).is_none() && !self.functions.contains_key(name.as_str()) {
+                        if let Some((generics, fields)) = self.structs.get(name.as_str()).cloned() {
+                            if args.len() != fields.len() {
+                                return self.error(format!(
+                                    "'{}' has {} fields but {} were given",
+                                    name,
+                                    fields.len(),
+                                    args.len()
+                                ));
+                            }
+
+                            let name = name.clone();
+                            let (mapping, type_args) = self.instantiate(&generics);
+                            for (arg, (_, field_type)) in args.iter_mut().zip(&fields) {
+                                self.check_against(&substitute(field_type, &mapping), arg)?;
+                            }
+                            if !generics.is_empty() {
+                                **callee = Expression::Specialized(name.clone(), type_args.clone());
+                            }
+                            return Ok(Type::Named(name, type_args));
+                        }
+                    }
+                }
+
+                let callee_type = self.infer(callee)?;
+                if let Type::Function(parameters, return_type) = self.resolve(&callee_type) {
+                    if parameters.len() != args.len() {
+                        return self.error(format!(
+                            "Expected {} arguments, found {}",
+                            parameters.len(),
+                            args.len()
+                        ));
+                    }
+                    let unannotated = match callee.as_ref() {
+                        Expression::Identifier(name, _) if self.unannotated.contains(name.as_str()) => Some(name),
+                        _ => None,
+                    };
+                    for (arg, param_type) in args.iter_mut().zip(&parameters) {
+                        self.check_against(param_type, arg).map_err(|err| match unannotated {
+                            Some(name) => TypeError {
+                                message: format!(
+                                    "{}. '{}' has parameters without types, which take the type of their \
+                                     first use. Annotate them, or make '{}' generic",
+                                    err.message, name, name
+                                ),
+                                span: err.span,
+                            },
+                            None => err,
+                        })?;
+                    }
+                    return Ok(*return_type);
+                }
+
+                let mut arg_types = Vec::new();
+                for arg in args.iter_mut() {
+                    arg_types.push(self.infer(arg)?);
+                }
+                let return_type = self.fresh();
+                self.unify(&callee_type, &Type::Function(arg_types, Box::new(return_type.clone())))?;
+                Ok(return_type)
+            }
+            Expression::MethodCall(receiver, method, args, ty) => {
+                let receiver_type = self.infer(receiver)?;
+                *ty = Some(receiver_type.clone());
+                let type_name = match self.resolve(&receiver_type) {
+                    // Anything can be shown, whatever it turns out to be
+                    Type::Var(_) if method == "show" && args.is_empty() => return Ok(Type::Str),
+                    Type::Var(_) => {
+                        // Nothing says what the receiver is yet, but if only one type has
+                        // this method it has to be that one
+                        let candidates = self.methods.types_with_method(method);
+                        let [type_name] = candidates.as_slice() else {
+                            return self.error(format!("Cannot tell which type's '{}' method is called here", method));
+                        };
+                        let type_name = type_name.to_string();
+                        self.unify(&Type::from_name(&type_name), &receiver_type)?;
+                        type_name
+                    }
+                    Type::Param(param) => {
+                        let bounds = self.bounds.get(&param).cloned().unwrap_or_default();
+                        let Some(trait_name) = bounds.iter().find(|bound| {
+                            self.methods
+                                .trait_methods(bound)
+                                .map_or(false, |methods| methods.iter().any(|m| &m.name == method))
+                        }) else {
+                            return self.error(format!("No method '{}' on type '{}'", method, param));
+                        };
+                        return self.check_trait_call(trait_name, method, receiver_type, args);
+                    }
+                    Type::Dyn(trait_name) => return self.check_trait_call(&trait_name, method, receiver_type, args),
+                    Type::Named(name, _) => name,
+                    other => other.to_string(),
+                };
+
+                let Some(mangled) = self.methods.resolve(&type_name, method) else {
+                    // Codegen shows what has no Show impl of its own
+                    if method == "show" && args.is_empty() {
+                        return Ok(Type::Str);
+                    }
+                    return self.error(format!("No method '{}' on type '{}'", method, type_name));
+                };
+                let Some((_, Type::Function(parameters, return_type))) = self.functions.get(mangled).cloned() else {
+                    return self.error(format!("Undefined function: {}", mangled));
+                };
+                if parameters.len() != args.len() + 1 {
+                    return self.error(format!(
+                        "Expected {} arguments, found {}",
+                        parameters.len().saturating_sub(1),
+                        args.len()
+                    ));
+                }
+
+                self.unify(&parameters[0], &receiver_type)?;
+                for (arg, param_type) in args.iter_mut().zip(&parameters[1..]) {
+                    self.check_against(param_type, arg)?;
+                }
+                Ok(*return_type)
+            }
+            Expression::FieldAccess(object, field) => {
+                let object_type = self.infer(object)?;
+                let (struct_name, type_args) = match self.resolve(&object_type) {
+                    Type::Named(name, args) => (name, args),
+                    Type::Var(_) => {
+                        // Same trick as methods, a field only one struct has decides the type
+                        let candidates: Vec<String> = self
+                            .structs
+                            .iter()
+                            .filter(|(_, (_, fields))| fields.iter().any(|(name, _)| name == field))
+                            .map(|(name, _)| name.clone())
+                            .collect();
+                        let [name] = candidates.as_slice() else {
+                            return self.error(format!("Cannot tell which struct's '{}' field is used here", field));
+                        };
+                        let struct_type = self.struct_type(name);
+                        self.unify(&struct_type, &object_type)?;
+                        let Type::Named(name, args) = struct_type else { unreachable!() };
+                        (name, args)
+                    }
+                    other => return self.error(format!("Type {} has no fields", other)),
+                };
+
+                let (generics, fields) = self.structs.get(&struct_name).cloned().unwrap_or_default();
+                let mapping = generics.into_iter().map(|generic| generic.name).zip(type_args).collect();
+                match fields.into_iter().find(|(name, _)| name == field) {
+                    Some((_, field_type)) => Ok(substitute(&field_type, &mapping)),
+                    None => self.error(format!("'{}' has no field '{}'", struct_name, field)),
+                }
+            }
+            Expression::Assignment(target, value) => {
+                let value_type = self.infer(value)?;
+                match target.as_mut() {
+                    // The first assignment to a name declares it
+                    Expression::Identifier(_, symbol) => match self.lookup_variable(*symbol) {
+                        Some(existing) => self.unify(&existing, &value_type)?,
+                        None => self.bind_variable(*symbol, value_type)?,
+                    },
+                    other => {
+                        let target_type = self.infer(other)?;
+                        self.unify(&target_type, &value_type)?;
+                    }
+                }
+                Ok(Type::Null)
+            }
+            Expression::Lambda(parameters, return_type, body) => {
+                let mut param_types = Vec::new();
+                for param in parameters.iter_mut() {
+                    let ty = self.annotated_or_fresh(&mut param.ty)?;
+                    self.bind_variable(param.symbol, ty.clone())?;
+                    param_types.push(ty);
+                }
+                let return_type = self.annotated_or_fresh(return_type)?;
+
+                self.return_types.push(return_type.clone());
+                let result = self.check_body(body, &return_type);
+                self.return_types.pop();
+                result?;
+
+                Ok(Type::Function(param_types, Box::new(return_type)))
+            }
+        }
+    }
+
+    fn check_condition(&mut self, cond: &mut Expression) -> Result<(), TypeError> {
+        let cond_type = self.infer(cond)?;
+        self.unify(&Type::Bool, &cond_type)
+    }
+
+    // Swaps every inference variable in the AST for what it was solved to
+    fn finalize_slot(&self, ty: &mut Option<Type>) {
+        if let Some(inner) = ty {
+            *inner = self.finalize(inner);
+        }
+    }
+
+    fn finalize_statement(&self, stmt: &mut Statement) {
+        match stmt {
+            Statement::Spanned(_, inner) => self.finalize_statement(inner),
+            Statement::Expression(expr) => self.finalize_expression(expr),
+            Statement::Block(stmts) => {
+                for stmt in stmts {
+                    self.finalize_statement(stmt);
+                }
+            }
+            Statement::Return(value) => {
+                if let Some(expr) = value.as_mut() {
+                    self.finalize_expression(expr);
+                }
+            }
+            Statement::FunctionDefinition { parameters, return_type, body, .. } => {
+                for param in parameters {
+                    self.finalize_slot(&mut param.ty);
+                }
+                self.finalize_slot(return_type);
+                for stmt in body {
+                    self.finalize_statement(stmt);
+                }
+            }
+            Statement::StructDefinition { fields, .. } => {
+                for field in fields {
+                    self.finalize_slot(&mut field.ty);
+                }
+            }
+            Statement::Let { ty, value, .. } => {
+                self.finalize_slot(ty);
+                self.finalize_expression(value);
+            }
+            Statement::Comment(_)
+            | Statement::Impl { .. }
+            | Statement::TraitDefinition { .. }
+            | Statement::Import { .. } => {}
+        }
+    }
+
+    fn finalize_expression(&self, expr: &mut Expression) {
+        match expr {
+            Expression::LiteralValue(LiteralValue::Integer(_, ty)) => self.finalize_slot(ty),
+            Expression::LiteralValue(_) | Expression::Identifier(..) | Expression::Keyword(_) => {}
+            Expression::Specialized(_, args) => {
+                for arg in args {
+                    *arg = self.finalize(arg);
+                }
+            }
+            Expression::List(elements) | Expression::StringInterpolation(elements) => {
+                for element in elements {
+                    self.finalize_expression(element);
+                }
+            }
+            Expression::MathOp(lhs, _, rhs, ty)
+            | Expression::ComparisonOp(lhs, _, rhs, ty)
+            | Expression::BitwiseOp(lhs, _, rhs, ty) => {
+                self.finalize_expression(lhs);
+                self.finalize_expression(rhs);
+                self.finalize_slot(ty);
+            }
+            Expression::LogicalOp(lhs, _, rhs) | Expression::Assignment(lhs, rhs) | Expression::Index(lhs, rhs) => {
+                self.finalize_expression(lhs);
+                self.finalize_expression(rhs);
+            }
+            Expression::Cast(inner, _, from) => {
+                self.finalize_expression(inner);
+                self.finalize_slot(from);
+            }
+            Expression::Grouping(inner) | Expression::FieldAccess(inner, _) | Expression::DynCast(inner, _) => {
+                self.finalize_expression(inner)
+            }
+            Expression::For(var, iterable, block) => {
+                self.finalize_slot(&mut var.ty);
+                self.finalize_expression(iterable);
+                for stmt in block {
+                    self.finalize_statement(stmt);
+                }
+            }
+            Expression::If(cond, block) | Expression::While(cond, block) => {
+                self.finalize_expression(cond);
+                for stmt in block {
+                    self.finalize_statement(stmt);
+                }
+            }
+            Expression::IfElse(cond, block, elif_branches, else_block) => {
+                self.finalize_expression(cond);
+                for stmt in block {
+                    self.finalize_statement(stmt);
+                }
+                for (elif_cond, elif_block) in elif_branches {
+                    self.finalize_expression(elif_cond);
+                    for stmt in elif_block {
+                        self.finalize_statement(stmt);
+                    }
+                }
+                for stmt in else_block.iter_mut().flatten() {
+                    self.finalize_statement(stmt);
+                }
+            }
+            Expression::FunctionCall(callee, args) => {
+                self.finalize_expression(callee);
+                for arg in args {
+                    self.finalize_expression(arg);
+                }
+            }
+            Expression::MethodCall(receiver, _, args, ty) => {
+                self.finalize_expression(receiver);
+                for arg in args {
+                    self.finalize_expression(arg);
+                }
+                self.finalize_slot(ty);
+            }
+            Expression::Lambda(parameters, return_type, body) => {
+                for param in parameters {
+                    self.finalize_slot(&mut param.ty);
+                }
+                self.finalize_slot(return_type);
+                for stmt in body {
+                    self.finalize_statement(stmt);
+                }
+            }
+        }
+    }
+}
+
+// Swaps generic parameters for the types they stand for in one instantiation
+fn substitute(ty: &Type, mapping: &HashMap<String, Type>) -> Type {
+    match ty {
+        Type::Param(name) => mapping.get(name).cloned().unwrap_or_else(|| ty.clone()),
+        Type::Named(name, args) => Type::Named(name.clone(), args.iter().map(|arg| substitute(arg, mapping)).collect()),
+        Type::List(element) => Type::List(Box::new(substitute(element, mapping))),
+        Type::Function(parameters, return_type) => Type::Function(
+            parameters.iter().map(|param| substitute(param, mapping)).collect(),
+            Box::new(substitute(return_type, mapping)),
+        ),
+        other => other.clone(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::Lexer;
+    use crate::modules::Module;
+    use crate::parser::Parser;
+    use crate::semantic::Resolver;
+
+    fn check(source: &str) -> Result<Vec<Statement>, TypeError> {
+        let mut lexer = Lexer::new(source);
+        let tokens = lexer.tokenize().expect("test source should lex").to_vec();
+        let ast = Parser::new(tokens, lexer.spans().to_vec()).parse().expect("test source should parse");
+        let module = Module {
+            name: String::new(),
+            file: 0,
+            imports: HashMap::new(),
+            tokens: Vec::new(),
+            ast,
+        };
+        let ast = Resolver::new().resolve_program(Vec::new(), vec![module]).expect("test source should resolve");
+        let (mut ast, methods) = MethodTable::build(ast).expect("test source should have valid methods");
+        TypeChecker::new(&methods).check_program(&mut ast)?;
+        Ok(ast)
+    }
+
+    fn let_types(ast: &[Statement]) -> Vec<Type> {
+        ast.iter()
+            .filter_map(|stmt| match stmt.node() {
+                Statement::Let { ty, .. } => ty.clone(),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn infers_types_from_use() {
+        let ast = check("fn twice(x) { return x + x }\nlet a = twice(1.5)\nlet b = \"s\"\nlet c = [true]\n").unwrap();
+        assert_eq!(let_types(&ast), vec![Type::Float, Type::Str, Type::List(Box::new(Type::Bool))]);
+        let Statement::FunctionDefinition { parameters, return_type, .. } = ast[0].node() else {
+            panic!("expected a function");
+        };
+        assert_eq!(parameters[0].ty, Some(Type::Float));
+        assert_eq!(*return_type, Some(Type::Float));
+    }
+
+    #[test]
+    fn unannotated_parameters_ask_for_annotations() {
+        let err = check("fn id(x) { return x }\nlet a = id(true)\nlet b = id(\"a\")\n").unwrap_err();
+        assert!(err.message.contains("Expected bool, found str"), "{}", err.message);
+        assert!(err.message.contains("make 'id' generic"), "{}", err.message);
+    }
+
+    #[test]
+    fn a_returning_branch_fits_any_other() {
+        let ast = check("fn pick(n: int) -> str {\n let s = if n > 0 { \"yes\" } else { return \"no\" }\n return s\n}\n")
+            .unwrap();
+        let Statement::FunctionDefinition { body, .. } = ast[0].node() else {
+            panic!("expected a function");
+        };
+        assert_eq!(let_types(body), vec![Type::Str]);
+    }
+
+    #[test]
+    fn branches_have_to_agree() {
+        let err = check("let s = if true { \"yes\" } else { false }\n").unwrap_err();
+        assert_eq!(err.message, "if branches have different types, str and bool");
+    }
+
+    const SHAPES: &str = "trait Area { fn area(self) -> float }\n\
+                          struct Square { side: float }\n\
+                          impl Area for Square {\n fn area(self) -> float { return self.side * self.side }\n}\n\
+                          fn total<T: Area>(shape: T) -> float { return shape.area() }\n";
+
+    #[test]
+    fn bounds_have_to_be_implemented() {
+        assert!(check(&format!("{}let a = total(Square(2.0))\n", SHAPES)).is_ok());
+        let err = check(&format!("{}let a = total(true)\n", SHAPES)).unwrap_err();
+        assert_eq!(err.message, "bool does not implement Area");
+    }
+
+    #[test]
+    fn values_passed_as_dyn_are_cast() {
+        let area = "fn area(shape: dyn Area) -> float { return shape.area() }\nlet a = area(Square(2.0))\n";
+        let ast = check(&format!("{}{}", SHAPES, area)).unwrap();
+        let Statement::Let { value: Expression::FunctionCall(_, args), .. } = ast.last().unwrap().node() else {
+            panic!("expected a call");
+        };
+        assert!(matches!(&args[0], Expression::DynCast(_, trait_name) if trait_name == "Area"));
+    }
+
+    #[test]
+    fn if_else_has_its_branches_type() {
+        let ast = check(
+            "let a = true\nlet b = false\n\
+             let x = if a { \"one\" } elif b { \"two\" } else { \"three\" }\n\
+             let y = if a { \"one\" } elif b { \"two\" }\n",
+        )
+        .unwrap();
+        assert_eq!(let_types(&ast), vec![Type::Bool, Type::Bool, Type::Str, Type::Null]);
+    }
+
+    #[test]
+    fn whole_numbers_are_floats_unless_said_otherwise() {
+        let ast = check(
+            "let half = 7 / 2\nlet whole: int = 7 / 2\nlet n = 7\nlet m = n / 2\n\
+             let bits = 6 & 3\nlet xs = [1, 2]\nlet i = 0\nlet x = xs[i]\n",
+        )
+        .unwrap();
+        assert_eq!(
+            let_types(&ast),
+            vec![
+                Type::Float,
+                Type::Int,
+                Type::Float,
+                Type::Float,
+                Type::Int,
+                Type::List(Box::new(Type::Float)),
+                Type::Int,
+                Type::Float,
+            ]
+        );
+    }
+
+    #[test]
+    fn format_counts_placeholders_not_escaped_braces() {
+        let source = |template: &str| format!("let s: str = \"a\"\nlet t = format(\"{}\", s)\n", template);
+        assert!(check(&source("{{}} = {}")).is_ok());
+        let err = check(&source("{{}}")).unwrap_err();
+        assert_eq!(err.message, "The format string has 0 placeholders but 1 values were given");
+    }
+
+    #[test]
+    fn a_body_has_to_end_in_its_return_type() {
+        let ast = check("fn inc(x: int) { x + 1 }\n").unwrap();
+        let Statement::FunctionDefinition { return_type, .. } = ast[0].node() else {
+            panic!("expected a function");
+        };
+        assert_eq!(*return_type, Some(Type::Int));
+        assert!(check("fn log(x: int) -> null { x + 1 }\n").is_ok());
+        let err = check("fn name(x: int) -> str { x + 1 }\n").unwrap_err();
+        assert!(err.message.contains("str"), "{}", err.message);
+    }
+
+    #[test]
+    fn a_lambda_returns_its_last_expression() {
+        let ast = check("let inc = fn(x: int) { x + 1 }\n").unwrap();
+        assert_eq!(let_types(&ast), vec![Type::Function(vec![Type::Int], Box::new(Type::Int))]);
+        let err = check("let name = fn(x: int) -> str { x + 1 }\n").unwrap_err();
+        assert!(err.message.contains("str"), "{}", err.message);
+    }
+}